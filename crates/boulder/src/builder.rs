@@ -104,6 +104,9 @@ impl Builder {
 
         let rt = Runtime::new()?;
         rt.block_on(async {
+            // Network config applies to both the root & upstream fetches
+            moss::request::load_config(&self.env.config).await?;
+
             let profiles = profile::Manager::new(&self.env).await;
 
             let repos = profiles.repositories(&self.profile)?.clone();
@@ -305,7 +308,7 @@ where
     use std::io::BufRead;
 
     thread::spawn(move || {
-        let pgo = is_pgo.then_some("│").unwrap_or_default().dim();
+        let pgo = if is_pgo { "│" } else { "" }.dim();
         let kind = step.styled(format!("{}│", step.abbrev()));
         let tag = format!("{}{pgo}{kind}", "│".dim());
 
@@ -355,7 +358,7 @@ fn breakpoint_line(
 
     let has_key = |line: &str, key: &str| {
         line.split_once(':')
-            .map_or(false, |(leading, _)| leading.trim().ends_with(key))
+            .is_some_and(|(leading, _)| leading.trim().ends_with(key))
     };
 
     let mut lines = recipe
@@ -426,6 +429,8 @@ pub enum Error {
     Root(#[from] root::Error),
     #[error("upstream")]
     Upstream(#[from] upstream::Error),
+    #[error("network config")]
+    Network(#[from] moss::request::Error),
     #[error("container")]
     Container(#[from] container::Error),
     #[error("recipe")]
@@ -7,7 +7,7 @@ use std::{collections::HashMap, io};
 use boulder::{profile, Env, Profile, Runtime};
 use clap::Parser;
 use itertools::Itertools;
-use moss::{repository, Repository};
+use moss::{repository, signature::PublicKey, Repository};
 use thiserror::Error;
 use url::Url;
 
@@ -33,7 +33,7 @@ pub enum Subcommand {
             help = "profile repositories",
             value_parser = parse_repository,
             help = "repository to add to profile, can be passed multiple times",
-            long_help = "repository to add to profile\n\nExample: --repo name=volatile,uri=https://dev.serpentos.com/volatile/x86_64/stone.index,priority=100"
+            long_help = "repository to add to profile\n\nExample: --repo name=volatile,uri=https://dev.serpentos.com/volatile/x86_64/stone.index,priority=100,key=<hex public key>\n\nMirrors of the uri can be added with one or more mirror=<uri>\n\nUnsigned repositories must set insecure=true"
         )]
         repos: Vec<(repository::Id, Repository)>,
     },
@@ -57,19 +57,42 @@ fn parse_repository(s: &str) -> Result<(repository::Id, Repository), String> {
         .ok_or("missing uri")?
         .parse::<Url>()
         .map_err(|e| e.to_string())?;
+    // Mirrors can be repeated, so aren't collected as a map
+    let mirrors = s
+        .split(',')
+        .filter_map(|kv| kv.split_once('='))
+        .filter(|(key, _)| *key == "mirror")
+        .map(|(_, mirror)| mirror.parse::<Url>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
     let priority = key_values
         .get("priority")
         .map(|p| p.parse::<u64>())
         .transpose()
         .map_err(|e| e.to_string())?
         .unwrap_or_default();
+    let public_key = key_values
+        .get("key")
+        .map(|k| k.parse::<PublicKey>())
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let insecure = key_values
+        .get("insecure")
+        .map(|i| i.parse::<bool>())
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
 
     Ok((
         id,
         Repository {
             description: String::default(),
             uri,
+            mirrors,
             priority: repository::Priority::new(priority),
+            enabled: true,
+            public_key,
+            insecure,
         },
     ))
 }
@@ -137,6 +160,8 @@ pub async fn update<'a>(
 ) -> Result<(), Error> {
     let repos = manager.repositories(profile)?.clone();
 
+    moss::request::load_config(&env.config).await?;
+
     let mut moss_client = moss::Client::new("boulder", &env.moss_dir)
         .await?
         .explicit_repositories(repos)
@@ -153,6 +178,8 @@ pub enum Error {
     Profile(#[from] profile::Error),
     #[error("moss client")]
     MossClient(#[from] moss::client::Error),
+    #[error("network config")]
+    Network(#[from] moss::request::Error),
     #[error("io")]
     Io(#[from] io::Error),
 }
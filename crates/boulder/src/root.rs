@@ -17,13 +17,14 @@ pub async fn populate(builder: &Builder, repositories: repository::Map) -> Resul
     // Recreate root
     util::recreate_dir(&rootfs).await?;
 
-    let mut moss_client = moss::Client::new("boulder", &builder.env.moss_dir)
+    let moss_client = moss::Client::new("boulder", &builder.env.moss_dir)
         .await?
         .explicit_repositories(repositories)
         .await?
         .ephemeral(&rootfs)?;
 
-    moss_client.install(&packages, true).await?;
+    let plan = moss_client.plan_install(&packages).await?;
+    moss_client.execute(&plan).await?;
 
     Ok(())
 }
@@ -60,8 +61,8 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error("moss client")]
     MossClient(#[from] moss::client::Error),
-    #[error("moss install")]
-    MossInstall(#[from] moss::client::install::Error),
+    #[error("moss plan")]
+    MossPlan(#[from] moss::client::plan::Error),
     #[error("container")]
     Container(#[from] container::Error),
 }
@@ -96,6 +96,24 @@ impl Manager {
 
         Ok(())
     }
+
+    /// Delete the config `name` previously saved with [`Manager::save`]
+    ///
+    /// Returns `false` if it doesn't exist
+    pub async fn delete<T: Config>(&self, name: impl fmt::Display) -> Result<bool, SaveError> {
+        let domain = T::domain();
+
+        let path = self
+            .scope
+            .save_dir(&domain)
+            .join(format!("{name}.{EXTENSION}"));
+
+        match fs::remove_file(&path).await {
+            Ok(()) => Ok(true),
+            Err(io) if io.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(io) => Err(SaveError::Delete(path, io)),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -110,6 +128,8 @@ pub enum SaveError {
     Yaml(#[from] serde_yaml::Error),
     #[error("write config file {0:?}")]
     Write(PathBuf, #[source] io::Error),
+    #[error("delete config file {0:?}")]
+    Delete(PathBuf, #[source] io::Error),
 }
 
 async fn enumerate_paths(entry: Entry, resolve: Resolve<'_>, domain: &str) -> Vec<PathBuf> {
@@ -175,7 +195,7 @@ impl Scope {
         .dir(domain)
     }
 
-    fn load_with(&self) -> Vec<(Entry, Resolve)> {
+    fn load_with(&self) -> Vec<(Entry, Resolve<'_>)> {
         match &self {
             // System we search / merge all base file / .d files
             // from vendor then admin
@@ -138,7 +138,7 @@ impl Container {
                         1
                     }
                 }),
-                &mut STACK,
+                &mut *std::ptr::addr_of_mut!(STACK),
                 flags,
                 Some(SIGCHLD),
             )?
@@ -137,6 +137,14 @@ fn fragments_from_string(s: &str) -> Result<Vec<Fragment>, Error> {
             '\\' => Some(Fragment::BackSlash),
             '/' => Some(Fragment::ForwardSlash),
             '.' => Some(Fragment::Dot),
+            // Parentheses without a `name:pattern` inside are plain text
+            '(' if !walker
+                .substring_to(')')
+                .is_some_and(|end| end.contains(':')) =>
+            {
+                text.push(ch);
+                None
+            }
             '(' => {
                 if let Some(end) = walker.substring_to(')') {
                     walker.eat(end.len() + 1);
@@ -155,7 +163,6 @@ fn fragments_from_string(s: &str) -> Result<Vec<Fragment>, Error> {
                 }
                 None
             }
-            ')' => None,
             _ => {
                 text.push(ch);
                 None
@@ -186,7 +193,7 @@ fn fragment_to_regex_str(fragment: &Fragment) -> (String, Vec<String>) {
         Fragment::BackSlash => "\\".into(),
         Fragment::ForwardSlash => "\\/".into(),
         Fragment::Dot => "\\.".into(),
-        Fragment::Text(t) => t.clone(),
+        Fragment::Text(t) => regex::escape(t),
         Fragment::Group(id, elements) => {
             let elements = elements
                 .iter()
@@ -221,7 +228,8 @@ impl FromStr for Pattern {
 
         Ok(Self {
             pattern: s.into(),
-            regex: Regex::new(&compiled)?,
+            // Patterns match the whole path
+            regex: Regex::new(&format!("^{compiled}$"))?,
             groups: groups.into_iter().collect(),
         })
     }
@@ -248,5 +256,24 @@ pub mod path_tests {
 
         let bad = k.match_path("/usr/lib/modules/6.2.6/l/modules.symbols");
         assert!(bad.is_none());
+
+        let partial = k.match_path("/usr/lib/modules/6.2.6/modules.symbols.bin");
+        assert!(partial.is_none());
+    }
+
+    #[test]
+    fn test_literal_text() {
+        let k = "/usr/lib/libstdc++*".parse::<Pattern>().unwrap();
+        assert!(k.match_path("/usr/lib/libstdc++.so.6").is_some());
+        assert!(k.match_path("/usr/lib/libstdcc.so.6").is_none());
+
+        let k = "/usr/share/a.b/[1]{2}$".parse::<Pattern>().unwrap();
+        assert!(k.match_path("/usr/share/a.b/[1]{2}$").is_some());
+        assert!(k.match_path("/usr/share/axb/[1]{2}$").is_none());
+        assert!(k.match_path("/usr/share/a.b/1{2}").is_none());
+
+        let k = "/usr/share/man/man1/foo(1)*".parse::<Pattern>().unwrap();
+        assert!(k.match_path("/usr/share/man/man1/foo(1).gz").is_some());
+        assert!(k.match_path("/usr/share/man/man1/foo1.gz").is_none());
     }
 }
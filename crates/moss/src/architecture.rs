@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Architecture configuration, loaded from the `architecture` config domain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Native architecture of the installation, otherwise the architecture
+    /// moss was built for. Useful when managing a root for another machine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native: Option<String>,
+    /// Foreign architectures which may also be installed, i.e. `x86`
+    /// emul32 packages on an `x86_64` installation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub foreign: Vec<String>,
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "architecture".into()
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            native: other.native.or(self.native),
+            foreign: self
+                .foreign
+                .into_iter()
+                .chain(other.foreign)
+                .filter(|arch| !arch.is_empty())
+                .collect(),
+        }
+    }
+}
+
+/// The architectures packages can be installed for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Architectures {
+    native: String,
+    foreign: Vec<String>,
+}
+
+impl Architectures {
+    /// Only the architecture moss was built for
+    pub fn host() -> Self {
+        Self {
+            native: std::env::consts::ARCH.to_string(),
+            foreign: vec![],
+        }
+    }
+
+    /// Architectures as configured, falling back to the host architecture
+    pub fn from_config(config: Config) -> Self {
+        let native = config.native.unwrap_or_else(|| Self::host().native);
+        let foreign = config
+            .foreign
+            .into_iter()
+            .filter(|arch| *arch != native)
+            .fold(vec![], |mut foreign, arch| {
+                if !foreign.contains(&arch) {
+                    foreign.push(arch);
+                }
+                foreign
+            });
+
+        Self { native, foreign }
+    }
+
+    /// Native architecture of the installation
+    pub fn native(&self) -> &str {
+        &self.native
+    }
+
+    /// Allowed foreign architectures
+    pub fn foreign(&self) -> &[String] {
+        &self.foreign
+    }
+
+    /// Returns true if packages built for `architecture` can be installed
+    pub fn is_supported(&self, architecture: &str) -> bool {
+        self.native == architecture || self.foreign.iter().any(|arch| arch == architecture)
+    }
+}
+
+impl Default for Architectures {
+    fn default() -> Self {
+        Self::host()
+    }
+}
+
+impl fmt::Display for Architectures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.native)?;
+
+        for arch in &self.foreign {
+            write!(f, ", {arch}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn supported() {
+        let architectures = Architectures::from_config(Config {
+            native: Some("x86_64".into()),
+            foreign: vec!["x86".into(), "x86_64".into(), "x86".into()],
+        });
+
+        assert!(architectures.is_supported("x86_64"));
+        assert!(architectures.is_supported("x86"));
+        assert!(!architectures.is_supported("aarch64"));
+        assert_eq!(architectures.to_string(), "x86_64, x86");
+    }
+}
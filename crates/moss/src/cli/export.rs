@@ -0,0 +1,61 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::{Path, PathBuf};
+
+use clap::{arg, ArgMatches, Command};
+use moss::{
+    client::{self, export, Client},
+    environment, state,
+};
+use thiserror::Error;
+
+pub fn command() -> Command {
+    Command::new("export")
+        .about("Export a state as a tarball or OCI image")
+        .long_about(
+            "Export the filesystem of a state as an archive, read from the cached \
+             layouts & assets without blitting it first.\n\
+             \n\
+             Archives are reproducible: entries are sorted, mtimes are fixed and \
+             ownership & modes are those recorded by the packages.",
+        )
+        .arg(arg!(<STATE> "State to export").value_parser(clap::value_parser!(i64)))
+        .arg(arg!(<OUTPUT> "Archive to write").value_parser(clap::value_parser!(PathBuf)))
+        .arg(
+            arg!(--format <FORMAT> "Archive format")
+                .value_parser(["tar", "oci"])
+                .default_value("tar"),
+        )
+        .arg(arg!(--"to-dir" "Write the OCI image layout to the OUTPUT directory instead"))
+}
+
+/// Handle the `export` command
+pub async fn handle(args: &ArgMatches, root: &Path) -> Result<(), Error> {
+    let state_id = state::Id::from(*args.get_one::<i64>("STATE").unwrap());
+    let output = args.get_one::<PathBuf>("OUTPUT").cloned().unwrap();
+
+    let format = match args.get_one::<String>("format").unwrap().as_str() {
+        "oci" => export::Format::Oci,
+        _ => export::Format::Tar,
+    };
+    let target = if args.get_flag("to-dir") {
+        export::Target::Directory(output.clone())
+    } else {
+        export::Target::Archive(output.clone())
+    };
+
+    let client = Client::new(environment::NAME, root).await?;
+    client.export(state_id, format, target).await?;
+
+    println!("Exported state #{state_id} to {}", output.display());
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+}
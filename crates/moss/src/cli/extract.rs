@@ -3,13 +3,15 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    fs::{create_dir_all, hard_link, remove_dir_all, remove_file, File},
+    fs::{self, create_dir_all, remove_dir_all, File, Permissions},
     io::{copy, Read, Seek, SeekFrom, Write},
-    os::unix::fs::symlink,
-    path::PathBuf,
+    os::unix::fs::{symlink, PermissionsExt},
+    path::{Path, PathBuf},
 };
 
 use clap::{arg, ArgMatches, Command};
+use fnmatch::Pattern;
+use itertools::Itertools;
 use moss::package::{self, MissingMetaFieldError};
 use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
 use stone::{payload::layout, read::PayloadKind};
@@ -17,11 +19,31 @@ use thiserror::{self, Error};
 use tokio::task;
 use tui::{ProgressBar, ProgressStyle};
 
+use super::info::{layout_line, layout_path};
+
 pub fn command() -> Command {
     Command::new("extract")
         .about("Extract a `.stone` content to disk")
-        .long_about("For all valid content-bearing archives, extract to disk")
+        .long_about(
+            "For all valid content-bearing archives, extract to disk. \n\
+             \n\
+             Each archive is extracted to `<package id>/usr` within the output directory. \
+             Paths are matched against --include / --exclude as installed, i.e. /usr/bin/*",
+        )
         .arg(arg!(<PATH> ... "files to inspect").value_parser(clap::value_parser!(PathBuf)))
+        .arg(
+            arg!(-o --output <DIR> "Directory to extract into, defaults to the current directory")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--include <PATTERN> ... "Only extract paths matching the glob")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--exclude <PATTERN> ... "Don't extract paths matching the glob")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(arg!(-l --list "List the layout instead of extracting"))
 }
 
 /// Handle the `extract` command
@@ -32,19 +54,81 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
         .flatten()
         .cloned()
         .collect::<Vec<_>>();
-
-    task::spawn_blocking(move || extract(paths))
-        .await
-        .expect("join handle")?;
+    let output = args
+        .get_one::<PathBuf>("output")
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let list = *args.get_one::<bool>("list").unwrap();
+
+    let patterns = |id: &str| {
+        args.get_many::<String>(id)
+            .into_iter()
+            .flatten()
+            .map(|pattern| pattern.parse::<Pattern>())
+            .collect::<Result<Vec<_>, _>>()
+    };
+    let filter = Filter {
+        include: patterns("include")?,
+        exclude: patterns("exclude")?,
+    };
+
+    task::spawn_blocking(move || {
+        if list {
+            list_layouts(paths, &filter)
+        } else {
+            extract(paths, &output, &filter)
+        }
+    })
+    .await
+    .expect("join handle")?;
 
     Ok(())
 }
 
-fn extract(paths: Vec<PathBuf>) -> Result<(), Error> {
-    // Begin unpack
-    create_dir_all(".stoneStore")?;
+/// Selects layout entries by their installed path
+struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    fn matches(&self, layout: &layout::Layout) -> bool {
+        let path = layout_path(layout);
 
-    let content_store = PathBuf::from(".stoneStore");
+        (self.include.is_empty() || self.include.iter().any(|p| p.match_path(&path).is_some()))
+            && !self.exclude.iter().any(|p| p.match_path(&path).is_some())
+    }
+}
+
+fn list_layouts(paths: Vec<PathBuf>, filter: &Filter) -> Result<(), Error> {
+    let titled = paths.len() > 1;
+
+    for path in paths {
+        let rdr = File::open(&path).map_err(Error::IO)?;
+        let mut reader = stone::read(rdr).map_err(Error::Format)?;
+
+        let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
+        let layouts = payloads
+            .iter()
+            .filter_map(PayloadKind::layout)
+            .flat_map(|p| &p.body)
+            .filter(|layout| filter.matches(layout))
+            .sorted_by_key(|layout| layout_path(layout));
+
+        if titled {
+            println!("{}:", path.display());
+        }
+        for layout in layouts {
+            println!("{}", layout_line(layout));
+        }
+    }
+
+    Ok(())
+}
+
+fn extract(paths: Vec<PathBuf>, output: &Path, filter: &Filter) -> Result<(), Error> {
+    // Private to this user & removed when dropped
+    let temp = tempfile::Builder::new().prefix("moss-extract-").tempdir()?;
 
     for path in paths {
         println!("Extract: {:?}", path);
@@ -54,19 +138,29 @@ fn extract(paths: Vec<PathBuf>) -> Result<(), Error> {
 
         let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
         let content = payloads.iter().find_map(PayloadKind::content);
-        let layouts = payloads.iter().find_map(PayloadKind::layout);
+        let all_layouts = payloads
+            .iter()
+            .filter_map(PayloadKind::layout)
+            .flat_map(|p| &p.body)
+            .collect::<Vec<_>>();
+        let layouts = all_layouts
+            .iter()
+            .copied()
+            .filter(|layout| filter.matches(layout))
+            .collect::<Vec<_>>();
         let meta = payloads
             .iter()
             .find_map(PayloadKind::meta)
             .ok_or(Error::MissingMeta)?;
 
         let pkg = package::Meta::from_stone_payload(&meta.body).map_err(Error::MalformedMeta)?;
-        let extraction_root = PathBuf::from(pkg.id().to_string());
+        let extraction_root = output.join(pkg.id().to_string());
 
         // Cleanup old extraction root
         if extraction_root.exists() {
             remove_dir_all(&extraction_root)?;
         }
+        create_dir_all(&extraction_root)?;
 
         let progress = ProgressBar::new(1000).with_style(
             ProgressStyle::with_template("|{bar:20.cyan/bue}| {percent}%")
@@ -74,19 +168,29 @@ fn extract(paths: Vec<PathBuf>) -> Result<(), Error> {
                 .progress_chars("■≡=- "),
         );
 
-        if let Some(content) = content {
+        // Store of the content split into hash-indexed unique files
+        let content_store = temp.path().join("store");
+        create_dir_all(&content_store)?;
+
+        let has_regular = layouts
+            .iter()
+            .any(|layout| matches!(layout.entry, layout::Entry::Regular(..)));
+
+        if let Some(content) = content.filter(|_| has_regular) {
             let size = content.header.plain_size;
 
+            let content_path = temp.path().join("content");
             let content_file = File::options()
                 .read(true)
                 .write(true)
                 .create(true)
-                .open(".stoneContent")?;
+                .truncate(true)
+                .open(&content_path)?;
 
             let mut writer = ProgressWriter::new(&content_file, size, progress.clone());
             reader.unpack_content(content, &mut writer)?;
 
-            // Extract all indices from the `.stoneContent` into hash-indexed unique files
+            // Extract all indices from the content into the store
             payloads
                 .par_iter()
                 .filter_map(PayloadKind::index)
@@ -97,7 +201,8 @@ fn extract(paths: Vec<PathBuf>) -> Result<(), Error> {
                     file.seek(SeekFrom::Start(idx.start))?;
                     let mut split_file = (&mut file).take(idx.end - idx.start);
 
-                    let mut output = File::create(format!(".stoneStore/{:02x}", idx.digest))?;
+                    let mut output =
+                        File::create(content_store.join(format!("{:02x}", idx.digest)))?;
 
                     copy(&mut split_file, &mut output)?;
 
@@ -105,50 +210,66 @@ fn extract(paths: Vec<PathBuf>) -> Result<(), Error> {
                 })
                 .collect::<Result<Vec<_>, Error>>()?;
 
-            remove_file(".stoneContent")?;
+            fs::remove_file(content_path)?;
         }
 
-        if let Some(layouts) = layouts {
-            for layout in &layouts.body {
-                match &layout.entry {
-                    layout::Entry::Regular(id, target) => {
-                        let store_path = content_store.join(format!("{:02x}", id));
-                        let target_disk = extraction_root.join("usr").join(target);
-
-                        // drop it into a valid dir
-                        // TODO: Fix the permissions & mask
-                        let directory_target = target_disk.parent().unwrap();
-                        create_dir_all(directory_target)?;
-
-                        // link from CA store
-                        hard_link(store_path, target_disk)?;
-                    }
-                    layout::Entry::Symlink(source, target) => {
-                        let target_disk = extraction_root.join("usr").join(target);
-                        let directory_target = target_disk.parent().unwrap();
-
-                        // ensure dumping ground exists
-                        create_dir_all(directory_target)?;
-
-                        // join the link path to the directory target for relative joinery
-                        symlink(source, target_disk)?;
-                    }
-                    layout::Entry::Directory(target) => {
-                        let target_disk = extraction_root.join("usr").join(target);
-                        // TODO: Fix perms!
-                        create_dir_all(target_disk)?;
-                    }
-                    _ => unreachable!(),
+        for layout in &layouts {
+            match &layout.entry {
+                layout::Entry::Regular(id, target) => {
+                    let store_path = content_store.join(format!("{:02x}", id));
+                    let target_disk = extraction_root.join("usr").join(target);
+
+                    // drop it into a valid dir
+                    create_dir_all(target_disk.parent().unwrap())?;
+
+                    // Copied, as files sharing content may differ in mode
+                    fs::copy(store_path, &target_disk)?;
+                    fs::set_permissions(
+                        &target_disk,
+                        Permissions::from_mode(layout.mode & 0o7777),
+                    )?;
                 }
+                layout::Entry::Symlink(source, target) => {
+                    let target_disk = extraction_root.join("usr").join(target);
+
+                    // ensure dumping ground exists
+                    create_dir_all(target_disk.parent().unwrap())?;
+
+                    // Symlink modes are ignored by Linux
+                    symlink(source, target_disk)?;
+                }
+                layout::Entry::Directory(target) => {
+                    create_dir_all(extraction_root.join("usr").join(target))?;
+                }
+                // Device nodes, fifos & sockets aren't supported
+                _ => {}
             }
         }
 
+        // Apply directory modes once everything within them is written, as
+        // they may not permit writing. This includes the parents of selected
+        // entries, deepest first in case they don't permit searching.
+        let directories = all_layouts
+            .iter()
+            .filter_map(|layout| match &layout.entry {
+                layout::Entry::Directory(target) => Some((target, layout.mode)),
+                _ => None,
+            })
+            .sorted_by(|(a, _), (b, _)| b.cmp(a));
+        for (target, mode) in directories {
+            let target_disk = extraction_root.join("usr").join(target);
+
+            if target_disk.is_dir() {
+                fs::set_permissions(target_disk, Permissions::from_mode(mode & 0o7777))?;
+            }
+        }
+
+        // Clean up for the next archive
+        remove_dir_all(content_store)?;
+
         progress.finish_and_clear();
     }
 
-    // Clean up.
-    remove_dir_all(content_store)?;
-
     Ok(())
 }
 
@@ -160,6 +281,9 @@ pub enum Error {
     #[error("malformed meta")]
     MalformedMeta(#[from] MissingMetaFieldError),
 
+    #[error("invalid glob")]
+    Pattern(#[from] fnmatch::Error),
+
     #[error("io")]
     IO(#[from] std::io::Error),
 
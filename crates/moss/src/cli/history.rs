@@ -0,0 +1,89 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::Path;
+
+use clap::{ArgMatches, Command};
+use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
+use moss::{
+    client::{self, Client},
+    environment,
+    state::Provenance,
+};
+use thiserror::Error;
+use tui::Stylize;
+
+use super::state::format_duration;
+
+pub fn command() -> Command {
+    Command::new("history")
+        .about("Show how each state was created")
+        .long_about(
+            "Show the history of the installation, newest first: when each state \
+             was created, by whom, how long it took and the command line used.\n\
+             \n\
+             Use `moss state show <ID>` for the full details of a state.",
+        )
+}
+
+/// Handle the `history` command
+pub async fn handle(_args: &ArgMatches, root: &Path) -> Result<(), Error> {
+    let client = Client::new(environment::NAME, root).await?;
+
+    let state_ids = client.state_db.list_ids().await?;
+
+    let states = stream::iter(state_ids.iter().rev().map(|(id, _)| id))
+        .then(|id| client.state_db.get(id).map_err(Error::StateDB))
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for state in states {
+        let summary = state
+            .summary
+            .unwrap_or_else(|| String::from("system transaction"));
+
+        match state.provenance {
+            Some(provenance) => println!(
+                "#{} {} {} {} {} {}",
+                state.id.to_string().bold(),
+                state.created.format("%Y-%m-%d %H:%M:%S"),
+                summary,
+                user(&provenance),
+                format_duration(provenance.duration).dim(),
+                provenance.command_line,
+            ),
+            None => println!(
+                "#{} {} {}",
+                state.id.to_string().bold(),
+                state.created.format("%Y-%m-%d %H:%M:%S"),
+                summary,
+            ),
+        }
+
+        if let Some(description) = state.description {
+            println!("    {}", description.dim());
+        }
+    }
+
+    Ok(())
+}
+
+/// The user of a state, noting the unverified `SUDO_USER` when set
+fn user(provenance: &Provenance) -> String {
+    let user = provenance.user.as_deref().unwrap_or("unknown");
+
+    match &provenance.sudo_user {
+        Some(sudo_user) => format!("{user} (SUDO_USER={sudo_user})"),
+        None => user.to_string(),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("state db")]
+    StateDB(#[from] moss::db::state::Error),
+}
@@ -2,27 +2,60 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 use std::{
-    collections::{btree_map, BTreeMap},
+    cmp::Reverse,
+    collections::BTreeMap,
     io,
     path::{Path, PathBuf, StripPrefixError},
-    time::Duration,
+    sync::Arc,
 };
 
 use clap::{arg, value_parser, ArgMatches, Command};
 use futures::{future::BoxFuture, stream, FutureExt, StreamExt, TryStreamExt};
 use moss::{
-    client, environment,
+    client::{
+        self,
+        cache::Progress,
+        progress::{self, Event, Reporter},
+    },
+    environment,
     package::{self, Meta, MissingMetaFieldError},
+    repository,
+    signature::{self, SecretKey},
+    stone::delta,
 };
 use sha2::{Digest, Sha256};
 use thiserror::Error;
 use tokio::{fs, task};
-use tui::{MultiProgress, ProgressBar, ProgressStyle, Stylize};
 
 pub fn command() -> Command {
     Command::new("index")
         .about("Index a collection of packages")
         .arg(arg!(<INDEX_DIR> "directory of index files").value_parser(value_parser!(PathBuf)))
+        .arg(
+            arg!(--"keep-history" <N> "number of releases to keep per package")
+                .default_value("1")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--sign <KEY> "sign the index with the secret key file")
+                .long_help(
+                    "Sign the index with the secret key file, writing a detached signature \
+                     alongside the index. \n\
+                     \n\
+                     The key file holds a hex encoded 32 byte ed25519 seed, i.e. \
+                     the output of `openssl rand -hex 32`",
+                )
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            arg!(--deltas "generate delta stones from older releases to the latest release")
+                .long_help(
+                    "Generate delta stones from each older release of a package found in \
+                     the directory to it's latest release, written to the `deltas` \
+                     subdirectory & listed in a separate `deltas.index`. Clients with an \
+                     older release cached only fetch the files which changed.",
+                ),
+        )
 }
 
 pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
@@ -30,93 +63,224 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
         .get_one::<PathBuf>("INDEX_DIR")
         .unwrap()
         .canonicalize()?;
+    let keep_history = *args.get_one::<u64>("keep-history").unwrap() as usize;
+    let generate_deltas = *args.get_one::<bool>("deltas").unwrap();
+    let secret_key = match args.get_one::<PathBuf>("sign") {
+        Some(path) => Some(SecretKey::read(path).await?),
+        None => None,
+    };
 
     let stone_files = enumerate_stone_files(&dir).await?;
 
-    println!("Indexing {} files\n", stone_files.len());
+    let reporter: Arc<dyn Reporter> = Arc::new(progress::Tui::new());
 
-    let multi_progress = MultiProgress::new();
-
-    let total_progress = multi_progress.add(
-        ProgressBar::new(stone_files.len() as u64).with_style(
-            ProgressStyle::with_template("\n|{bar:20.cyan/blue}| {pos}/{len}")
-                .unwrap()
-                .progress_chars("■≡=- "),
-        ),
-    );
-    total_progress.tick();
+    reporter.report(Event::IndexStarted {
+        total: stone_files.len(),
+    });
 
     let list = stream::iter(&stone_files)
-        .map(|path| get_meta(path, &dir, &multi_progress, &total_progress))
+        .map(|path| get_meta(path, &dir, &reporter))
         .buffer_unordered(environment::MAX_DISK_CONCURRENCY)
         .try_collect::<Vec<_>>()
         .await?;
 
-    let mut map = BTreeMap::new();
+    let mut map = BTreeMap::<_, Vec<Meta>>::new();
 
-    // Add each meta to the map, removing
-    // dupes by keeping the latest release
+    // Group each meta by name, erroring on dupes
+    // of the same release
     for meta in list {
-        match map.entry(meta.name.clone()) {
-            btree_map::Entry::Vacant(entry) => {
-                entry.insert(meta);
-            }
-            btree_map::Entry::Occupied(mut entry) => {
-                match (entry.get().source_release, meta.source_release) {
-                    // Error if dupe is same version
-                    (prev, curr) if prev == curr => {
-                        return Err(Error::DuplicateRelease(
-                            meta.name.clone(),
-                            meta.source_release,
-                        ));
-                    }
-                    // Update if dupe is newer version
-                    (prev, curr) if prev < curr => {
-                        entry.insert(meta);
-                    }
-                    // Otherwise prev is more recent, don't replace
-                    _ => {}
-                }
-            }
+        let releases = map.entry(meta.name.clone()).or_default();
+
+        if releases
+            .iter()
+            .any(|prev| prev.source_release == meta.source_release)
+        {
+            return Err(Error::DuplicateRelease(
+                meta.name.clone(),
+                meta.source_release,
+            ));
         }
+
+        releases.push(meta);
+    }
+
+    for releases in map.values_mut() {
+        releases.sort_by_key(|meta| Reverse(meta.source_release));
+    }
+
+    // Deltas are generated from every older release on disk, not only those kept
+    let deltas = if generate_deltas {
+        create_deltas(&dir, &map, &reporter).await?
+    } else {
+        vec![]
+    };
+
+    // Only keep the latest `keep_history` releases
+    for releases in map.values_mut() {
+        releases.truncate(keep_history);
+    }
+
+    // Deltas are published in their own index so older clients can still read `stone.index`
+    let mut indexes = vec![dir.join("stone.index")];
+    let deltas_path = dir.join(repository::DELTAS_INDEX);
+
+    reporter.report(Event::IndexWriting);
+
+    write_index(
+        &indexes[0],
+        map.into_values()
+            .flatten()
+            .map(|meta| meta.to_stone_payload()),
+    )
+    .await?;
+
+    if deltas.is_empty() {
+        remove_stale(&deltas_path).await?;
+        remove_stale(&signature_path(&deltas_path)).await?;
+    } else {
+        write_index(
+            &deltas_path,
+            deltas.into_iter().map(|delta| delta.to_stone_payload()),
+        )
+        .await?;
+        indexes.push(deltas_path);
+    }
+
+    reporter.report(Event::IndexFinished);
+
+    println!();
+    for path in &indexes {
+        println!("Index file written to {:?}", path.display());
     }
 
-    write_index(&dir, map, &total_progress).await?;
+    if let Some(secret_key) = secret_key {
+        for path in &indexes {
+            let index = fs::read(path).await?;
+            let signature_path = signature_path(path);
 
-    multi_progress.clear()?;
+            fs::write(&signature_path, secret_key.sign(&index).to_string()).await?;
 
-    println!(
-        "\nIndex file written to {:?}",
-        dir.join("stone.index").display()
-    );
+            println!(
+                "Signature written to {:?} using public key {}",
+                signature_path.display(),
+                secret_key.public_key()
+            );
+        }
+    }
 
     Ok(())
 }
 
-async fn write_index(
+fn signature_path(index: &Path) -> PathBuf {
+    let mut path = index.as_os_str().to_owned();
+    path.push(format!(".{}", signature::EXTENSION));
+    path.into()
+}
+
+/// Remove a file left over from a previous run, if any
+async fn remove_stale(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Create a delta from each older release to the latest release of every package
+async fn create_deltas(
     dir: &Path,
-    map: BTreeMap<package::Name, Meta>,
-    total_progress: &ProgressBar,
+    map: &BTreeMap<package::Name, Vec<Meta>>,
+    reporter: &Arc<dyn Reporter>,
+) -> Result<Vec<package::Delta>, Error> {
+    let deltas_dir = dir.join("deltas");
+
+    let pairs = map
+        .values()
+        .filter_map(|releases| releases.split_first())
+        .flat_map(|(target, bases)| bases.iter().map(move |base| (base, target)))
+        .collect::<Vec<_>>();
+
+    if pairs.is_empty() {
+        return Ok(vec![]);
+    }
+
+    fs::create_dir_all(&deltas_dir).await?;
+
+    reporter.report(Event::DeltasStarted { total: pairs.len() });
+
+    stream::iter(pairs)
+        .map(|(base, target)| {
+            let relative_path = format!(
+                "deltas/{}-{}-{}-{}.{}",
+                target.name,
+                base.source_release,
+                target.source_release,
+                target.architecture,
+                delta::EXTENSION
+            );
+            create_delta(dir, base, target, relative_path, reporter)
+        })
+        .buffer_unordered(environment::MAX_DISK_CONCURRENCY)
+        .try_collect()
+        .await
+}
+
+async fn create_delta(
+    dir: &Path,
+    base: &Meta,
+    target: &Meta,
+    relative_path: String,
+    reporter: &Arc<dyn Reporter>,
+) -> Result<package::Delta, Error> {
+    reporter.report(Event::DeltaStarted {
+        path: &relative_path,
+    });
+
+    // Uri & hash are always set by `get_meta`
+    let base_path = dir.join(base.uri.as_ref().expect("indexed uri"));
+    let target_path = dir.join(target.uri.as_ref().expect("indexed uri"));
+    let out_path = dir.join(&relative_path);
+    let base_hash = base.hash.clone().expect("indexed hash");
+    let target_hash = target.hash.clone().expect("indexed hash");
+
+    let files = task::spawn_blocking({
+        let base_hash = base_hash.clone();
+        move || delta::create(&base_path, &base_hash, &target_path, &out_path)
+    })
+    .await
+    .expect("join handle")?;
+
+    let (size, hash) = stat_file(&dir.join(&relative_path), &relative_path, reporter).await?;
+
+    reporter.report(Event::DeltaCreated {
+        path: &relative_path,
+        files,
+    });
+
+    Ok(package::Delta {
+        target: target_hash,
+        base: base_hash,
+        uri: relative_path,
+        hash,
+        download_size: Some(size),
+    })
+}
+
+/// Write each meta payload to a repository stone at `path`
+async fn write_index(
+    path: &Path,
+    payloads: impl Iterator<Item = Vec<stone::payload::Meta>>,
 ) -> Result<(), Error> {
     use std::fs::File;
 
-    let dir = dir.to_path_buf();
-
-    total_progress.set_message("Writing index file");
-    total_progress.set_style(
-        ProgressStyle::with_template("\n {spinner} {wide_msg}")
-            .unwrap()
-            .tick_chars("--=≡■≡=--"),
-    );
-    total_progress.enable_steady_tick(Duration::from_millis(150));
+    let path = path.to_path_buf();
+    let payloads = payloads.collect::<Vec<_>>();
 
     task::spawn_blocking(move || {
-        let mut file = File::create(dir.join("stone.index"))?;
+        let mut file = File::create(path)?;
 
         let mut writer = stone::Writer::new(&mut file, stone::header::v1::FileType::Repository)?;
 
-        for (_, meta) in map {
-            let payload = meta.to_stone_payload();
+        for payload in payloads {
             writer.add_payload(payload.as_slice())?;
         }
 
@@ -128,29 +292,14 @@ async fn write_index(
     .expect("join handle")
 }
 
-async fn get_meta(
-    path: &Path,
-    dir: &Path,
-    multi_progress: &MultiProgress,
-    total_progress: &ProgressBar,
-) -> Result<Meta, Error> {
+async fn get_meta(path: &Path, dir: &Path, reporter: &Arc<dyn Reporter>) -> Result<Meta, Error> {
     let relative_path = format!("{}", path.strip_prefix(dir)?.display());
 
-    let progress = multi_progress.insert_before(total_progress, ProgressBar::new_spinner());
-    progress.enable_steady_tick(Duration::from_millis(150));
-
-    let (size, hash) = stat_file(path, &relative_path, &progress).await?;
+    let (size, hash) = stat_file(path, &relative_path, reporter).await?;
 
-    progress.set_message(format!(
-        "{} {}",
-        "Indexing".yellow(),
-        relative_path.clone().bold(),
-    ));
-    progress.set_style(
-        ProgressStyle::with_template(" {spinner} {wide_msg}")
-            .unwrap()
-            .tick_chars("--=≡■≡=--"),
-    );
+    reporter.report(Event::ReadMeta {
+        path: &relative_path,
+    });
 
     let (_, payloads) = moss::stone::stream_payloads(path).await?;
 
@@ -166,10 +315,9 @@ async fn get_meta(
     meta.download_size = Some(size);
     meta.uri = Some(relative_path.clone());
 
-    progress.finish();
-    multi_progress.remove(&progress);
-    multi_progress.println(format!("{} {}", "Indexed".green(), relative_path.bold()))?;
-    total_progress.inc(1);
+    reporter.report(Event::StoneIndexed {
+        path: &relative_path,
+    });
 
     Ok(meta)
 }
@@ -177,30 +325,45 @@ async fn get_meta(
 async fn stat_file(
     path: &Path,
     relative_path: &str,
-    progress: &ProgressBar,
+    reporter: &Arc<dyn Reporter>,
 ) -> Result<(u64, String), Error> {
-    use std::fs::File;
+    use std::{fs::File, io::Read};
 
     let path = path.to_path_buf();
     let relative_path = relative_path.to_string();
-    let progress = progress.clone();
+    let reporter = reporter.clone();
 
     task::spawn_blocking(move || {
-        let file = File::open(path)?;
+        let mut file = File::open(path)?;
         let size = file.metadata()?.len();
 
-        progress.set_length(size);
-        progress.set_message(format!("{} {}", "Hashing".blue(), relative_path.bold()));
-        progress.set_style(
-            ProgressStyle::with_template(
-                " {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ",
-            )
-            .unwrap()
-            .tick_chars("--=≡■≡=--"),
-        );
+        reporter.report(Event::HashStarted {
+            path: &relative_path,
+            size,
+        });
 
         let mut hasher = Sha256::new();
-        io::copy(&mut &file, &mut progress.wrap_write(&mut hasher))?;
+        let mut buffer = vec![0; 64 * 1024];
+        let mut completed = 0;
+
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..read]);
+            completed += read as u64;
+
+            reporter.report(Event::HashProgress {
+                path: &relative_path,
+                progress: Progress {
+                    delta: read as u64,
+                    completed,
+                    total: size,
+                },
+            });
+        }
 
         let hash = hex::encode(hasher.finalize());
 
@@ -210,7 +373,7 @@ async fn stat_file(
     .expect("join hande")
 }
 
-fn enumerate_stone_files(dir: &Path) -> BoxFuture<Result<Vec<PathBuf>, Error>> {
+fn enumerate_stone_files(dir: &Path) -> BoxFuture<'_, Result<Vec<PathBuf>, Error>> {
     async move {
         let mut read_dir = fs::read_dir(dir).await?;
 
@@ -222,7 +385,10 @@ fn enumerate_stone_files(dir: &Path) -> BoxFuture<Result<Vec<PathBuf>, Error>> {
 
             if meta.is_dir() {
                 paths.extend(enumerate_stone_files(&path).await?);
-            } else if meta.is_file() && path.extension().and_then(|s| s.to_str()) == Some("stone") {
+            } else if meta.is_file()
+                && path.extension().and_then(|s| s.to_str()) == Some("stone")
+                && !path.to_string_lossy().ends_with(delta::EXTENSION)
+            {
                 paths.push(path);
             }
         }
@@ -257,4 +423,10 @@ pub enum Error {
 
     #[error("client")]
     Client(#[from] client::Error),
+
+    #[error("create delta")]
+    Delta(#[from] delta::Error),
+
+    #[error("signing key")]
+    Signature(#[from] signature::Error),
 }
@@ -11,10 +11,12 @@ use moss::{
     client::{self, Client},
     environment,
     package::Flags,
+    registry,
+    stone::payload::layout,
     Package, Provider,
 };
 use thiserror::Error;
-use tui::Stylize;
+use tui::{HumanBytes, Stylize};
 
 const COLUMN_WIDTH: usize = 20;
 
@@ -23,6 +25,7 @@ pub fn command() -> Command {
         .about("Query packages")
         .long_about("List detailed package information from all available sources")
         .arg(arg!(<NAME> ... "packages to query").value_parser(clap::value_parser!(String)))
+        .arg(arg!(--files "List the files of each package, fetching it if needed"))
 }
 
 /// For all arguments, try to match a package
@@ -33,22 +36,35 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
         .flatten()
         .cloned()
         .collect::<Vec<_>>();
+    let show_files = *args.get_one::<bool>("files").unwrap();
 
     let root = args.get_one::<PathBuf>("root").unwrap().clone();
     let client = Client::new(environment::NAME, root).await?;
 
     for pkg in pkgs {
         let lookup = Provider::from_name(&pkg).unwrap();
-        let resolved = client
-            .registry
-            .by_provider(&lookup, Flags::NONE)
-            .collect::<Vec<_>>()
-            .await;
+        // Every repository providing a package is shown
+        let resolved = registry::dedupe(
+            client
+                .registry
+                .by_provider(&lookup, Flags::NONE)
+                .collect::<Vec<_>>()
+                .await,
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+
         if resolved.is_empty() {
             return Err(Error::NotFound(pkg));
         }
         for candidate in resolved {
-            print_package(&candidate);
+            let installed_size = client.installed_size(&candidate).await?;
+            print_package(&candidate, installed_size);
+
+            if show_files {
+                let layouts = client.layouts(&candidate).await?;
+                print_files(&layouts);
+            }
         }
     }
 
@@ -72,12 +88,24 @@ fn print_paragraph(p: &str) {
     }
 }
 
-/// Pretty print a package
-fn print_package(pkg: &Package) {
+/// Pretty print a package, with it's installed size if cached
+fn print_package(pkg: &Package, installed_size: Option<u64>) {
     print_titled("Name");
     println!("{}", pkg.meta.name);
     print_titled("Version");
     println!("{}", pkg.meta.version_identifier);
+    print_titled("Source");
+    println!("{}", pkg.source);
+    print_titled("Architecture");
+    println!("{}", pkg.meta.architecture);
+    if let Some(size) = pkg.meta.download_size {
+        print_titled("Download size");
+        println!("{}", HumanBytes(size));
+    }
+    if let Some(size) = installed_size {
+        print_titled("Installed size");
+        println!("{}", HumanBytes(size));
+    }
     print_titled("Homepage");
     println!("{}", pkg.meta.homepage);
     print_titled("Summary");
@@ -106,6 +134,99 @@ fn print_package(pkg: &Package) {
             .join("\n");
         print_paragraph(&provs);
     }
+    if !pkg.meta.conflicts.is_empty() {
+        print_titled("Conflicts");
+        let conflicts = pkg
+            .meta
+            .conflicts
+            .iter()
+            .map(|c| c.to_string())
+            .sorted()
+            .join("\n");
+        print_paragraph(&conflicts);
+    }
+    if !pkg.meta.build_dependencies.is_empty() {
+        print_titled("Build dependencies");
+        let deps = pkg
+            .meta
+            .build_dependencies
+            .iter()
+            .map(|d| d.to_string())
+            .sorted()
+            .join("\n");
+        print_paragraph(&deps);
+    }
+    if let Some(uri) = &pkg.meta.source_uri {
+        print_titled("Source URI");
+        println!("{uri}");
+    }
+    if let Some(path) = &pkg.meta.source_path {
+        print_titled("Source path");
+        println!("{path}");
+    }
+    if let Some(source_ref) = &pkg.meta.source_ref {
+        print_titled("Source ref");
+        println!("{source_ref}");
+    }
+}
+
+/// Print the type, mode & path of each file
+fn print_files(layouts: &[layout::Layout]) {
+    print_titled("Files");
+
+    let files = layouts
+        .iter()
+        .sorted_by_key(|layout| layout_path(layout))
+        .map(layout_line)
+        .join("\n");
+
+    if files.is_empty() {
+        println!("{}", "none".dim());
+    } else {
+        print_paragraph(&files);
+    }
+}
+
+/// Kind of file a layout entry creates
+pub fn layout_kind(layout: &layout::Layout) -> &'static str {
+    match &layout.entry {
+        layout::Entry::Regular(..) => "regular",
+        layout::Entry::Symlink(..) => "symlink",
+        layout::Entry::Directory(..) => "directory",
+        layout::Entry::CharacterDevice(..) => "char",
+        layout::Entry::BlockDevice(..) => "block",
+        layout::Entry::Fifo(..) => "fifo",
+        layout::Entry::Socket(..) => "socket",
+    }
+}
+
+/// Kind, mode & path of a layout entry, along with the source of symlinks
+pub fn layout_line(layout: &layout::Layout) -> String {
+    let line = format!(
+        "{:<10} {:04o} {}",
+        layout_kind(layout),
+        layout.mode & 0o7777,
+        layout_path(layout)
+    );
+    match &layout.entry {
+        layout::Entry::Symlink(source, _) => format!("{line} -> {source}"),
+        _ => line,
+    }
+}
+
+/// Absolute path a layout entry is installed to
+pub fn layout_path(layout: &layout::Layout) -> String {
+    let target = match &layout.entry {
+        layout::Entry::Regular(_, target)
+        | layout::Entry::Symlink(_, target)
+        | layout::Entry::Directory(target)
+        | layout::Entry::CharacterDevice(target)
+        | layout::Entry::BlockDevice(target)
+        | layout::Entry::Fifo(target)
+        | layout::Entry::Socket(target) => target,
+    };
+
+    format!("/usr/{target}")
 }
 
 #[derive(Debug, Error)]
@@ -115,4 +236,7 @@ pub enum Error {
 
     #[error("client")]
     Client(#[from] client::Error),
+
+    #[error("registry")]
+    Registry(#[from] registry::Error),
 }
@@ -0,0 +1,202 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{collections::HashMap, path::Path};
+
+use clap::{arg, ArgAction, ArgMatches, Command};
+use moss::{
+    client::{self, plan, Client},
+    environment, installation,
+    repository::{self, Priority},
+    signature::PublicKey,
+    Installation, Repository,
+};
+use thiserror::Error;
+use tui::{ask_yes_no, pretty::print_to_columns};
+use url::Url;
+
+pub fn command() -> Command {
+    Command::new("init")
+        .about("Create a new installation root")
+        .long_about(
+            "Create a new installation root, add it's repositories and install the \
+             requested packages as the first state. \n\
+             \n\
+             The root must not exist, be empty or only hold moss directories. \n\
+             \n\
+             Each repository needs a trusted key to verify it's index, unless --insecure \
+             is passed. The repository configs are only written once the packages are \
+             installed, so a failed or cancelled init can be retried.",
+        )
+        .arg(
+            arg!(--repo <REPO> "Repository to add, as name=uri")
+                .action(ArgAction::Append)
+                .value_parser(parse_repo),
+        )
+        .arg(
+            arg!(--key <KEY> "Trusted public key of a repository, as name=key")
+                .action(ArgAction::Append)
+                .value_parser(parse_key),
+        )
+        .arg(
+            arg!(--package <NAME> "Package to install")
+                .action(ArgAction::Append)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            arg!(--insecure "Don't verify the signature of the repository indexes")
+                .conflicts_with("key"),
+        )
+}
+
+/// Handle execution of `moss init`
+pub async fn handle(args: &ArgMatches, root: &Path) -> Result<(), Error> {
+    let repos = args
+        .get_many::<(String, Url)>("repo")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    let packages = args
+        .get_many::<String>("package")
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let mut keys = args
+        .get_many::<(String, PublicKey)>("key")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<HashMap<_, _>>();
+    let insecure = args.get_flag("insecure");
+    let yes = *args.get_one::<bool>("yes").unwrap();
+
+    // Validate before anything is created
+    let repositories = repos
+        .into_iter()
+        .map(|(name, uri)| {
+            let public_key = keys.remove(&name);
+
+            if public_key.is_none() && !insecure {
+                return Err(Error::MissingKey(name));
+            }
+
+            Ok((
+                repository::Id::new(name),
+                Repository {
+                    description: String::default(),
+                    uri,
+                    mirrors: vec![],
+                    priority: Priority::new(0),
+                    enabled: true,
+                    public_key,
+                    insecure,
+                },
+            ))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(name) = keys.into_keys().next() {
+        return Err(Error::UnknownRepository(name));
+    }
+
+    let installation = Installation::init(root)?;
+    let repositories = repository::Map::with(repositories);
+
+    // Fetch & verify every repository before anything is written outside of
+    // `.moss`, so a failure or a declined plan leaves a root that can be retried
+    {
+        let mut manager = repository::Manager::explicit(
+            environment::NAME,
+            repositories.clone(),
+            installation.clone(),
+        )
+        .await?;
+
+        manager.refresh_all().await?;
+    }
+
+    if !packages.is_empty() {
+        let client = Client::new(environment::NAME, root)
+            .await?
+            .explicit_repositories(repositories.clone())
+            .await?;
+        let plan = client.plan_install(&packages).await?;
+
+        println!("The following package(s) will be installed:");
+        println!();
+        print_to_columns(&plan.additions);
+        println!();
+
+        if !yes && !ask_yes_no("Do you wish to continue?")? {
+            return Err(Error::Cancelled);
+        }
+
+        client.execute(&plan).await?;
+    }
+
+    // Add repositories once the first state is applied
+    {
+        let config = config::Manager::system(root, "moss");
+        let mut manager = repository::Manager::system(config, installation).await?;
+
+        for (id, repository) in repositories {
+            manager.add_repository(id, repository).await?;
+        }
+    }
+
+    println!("Initialized {}", root.display());
+
+    Ok(())
+}
+
+/// Parse a `name=uri` repository argument
+fn parse_repo(value: &str) -> Result<(String, Url), String> {
+    let (name, uri) = value
+        .split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected name=uri, got {value}"))?;
+    let uri = uri.parse::<Url>().map_err(|e| e.to_string())?;
+
+    Ok((name.to_string(), uri))
+}
+
+/// Parse a `name=key` repository key argument
+fn parse_key(value: &str) -> Result<(String, PublicKey), String> {
+    let (name, key) = value
+        .split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected name=key, got {value}"))?;
+    let key = key.parse::<PublicKey>().map_err(|e| e.to_string())?;
+
+    Ok((name.to_string(), key))
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("repository {0} has no trusted key, pass --key {0}=KEY or --insecure")]
+    MissingKey(String),
+
+    #[error("key given for unknown repository {0}")]
+    UnknownRepository(String),
+
+    #[error("installation")]
+    Installation(#[from] installation::Error),
+
+    #[error("repo manager")]
+    RepositoryManager(#[from] repository::manager::Error),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("plan")]
+    Plan(#[from] plan::Error),
+
+    #[error("io")]
+    Io(#[from] std::io::Error),
+}
@@ -8,8 +8,11 @@ use moss::stone;
 use moss::stone::payload::layout;
 use moss::stone::payload::meta;
 use moss::stone::read::PayloadKind;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::{collections::HashSet, fs::File, path::PathBuf};
 use thiserror::Error;
+use tokio::task;
+use tui::Stylize;
 
 const COLUMN_WIDTH: usize = 20;
 
@@ -18,6 +21,21 @@ pub fn command() -> Command {
         .about("Examine raw stone files")
         .long_about("Show detailed (debug) information on a local `.stone` file")
         .arg(arg!(<PATH> ... "files to inspect").value_parser(clap::value_parser!(PathBuf)))
+        .arg(
+            arg!(--verify "Verify the payloads & their consistency").long_help(
+                "Verify the payloads & their consistency. \n\
+                 \n\
+                 Reports the kind, compression, sizes, record count & checksum validity of \
+                 each payload, and checks every regular layout entry has an index entry \
+                 within the content. Exits non-zero when any problem is found.",
+            ),
+        )
+        .arg(
+            arg!(--format <FORMAT> "Output format of the verification report")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .requires("verify"),
+        )
 }
 
 ///
@@ -31,6 +49,14 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
         .cloned()
         .collect::<Vec<_>>();
 
+    if *args.get_one::<bool>("verify").unwrap() {
+        let json = args.get_one::<String>("format").unwrap() == "json";
+
+        return task::spawn_blocking(move || verify(paths, json))
+            .await
+            .expect("join handle");
+    }
+
     inspect(paths).await
 }
 
@@ -116,8 +142,171 @@ async fn inspect(paths: Vec<PathBuf>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Verification report of a single stone
+#[derive(Debug, Serialize)]
+struct Report {
+    path: PathBuf,
+    payloads: Vec<PayloadReport>,
+    problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PayloadReport {
+    kind: String,
+    compression: String,
+    stored_size: u64,
+    plain_size: u64,
+    records: usize,
+    checksum_valid: bool,
+}
+
+fn verify(paths: Vec<PathBuf>, json: bool) -> Result<(), Error> {
+    let reports = paths
+        .into_iter()
+        .map(verify_stone)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in &reports {
+            print_report(report);
+        }
+    }
+
+    let failed = reports.iter().filter(|r| !r.problems.is_empty()).count();
+    if failed > 0 {
+        return Err(Error::Verification(failed));
+    }
+
+    Ok(())
+}
+
+fn verify_stone(path: PathBuf) -> Result<Report, Error> {
+    let mut reader = ::stone::read(File::open(&path)?)?;
+
+    let checksums = reader.checksums()?;
+
+    let mut problems = checksums
+        .iter()
+        .filter(|checksum| !checksum.is_valid())
+        .map(|checksum| {
+            format!(
+                "{:?} payload checksum mismatch: got {:02x}, expected {:02x}",
+                checksum.header.kind,
+                checksum.got,
+                checksum.expected()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let payloads = checksums
+        .iter()
+        .map(|checksum| PayloadReport {
+            kind: format!("{:?}", checksum.header.kind).to_lowercase(),
+            compression: format!("{:?}", checksum.header.compression).to_lowercase(),
+            stored_size: checksum.header.stored_size,
+            plain_size: checksum.header.plain_size,
+            records: checksum.header.num_records,
+            checksum_valid: checksum.is_valid(),
+        })
+        .collect();
+
+    // Records can't be decoded from a corrupt payload
+    if problems.is_empty() {
+        match reader.payloads()?.collect::<Result<Vec<_>, _>>() {
+            Ok(decoded) => problems.extend(check_consistency(&decoded)),
+            Err(error) => problems.push(format!("decode: {error}")),
+        }
+    }
+
+    Ok(Report {
+        path,
+        payloads,
+        problems,
+    })
+}
+
+/// Check the layout, index & content payloads of a binary package agree
+fn check_consistency(payloads: &[PayloadKind]) -> Vec<String> {
+    let mut problems = vec![];
+
+    let content_size = payloads
+        .iter()
+        .find_map(PayloadKind::content)
+        .map(|content| content.header.plain_size);
+    let indices = payloads
+        .iter()
+        .filter_map(PayloadKind::index)
+        .flat_map(|p| &p.body)
+        .collect::<Vec<_>>();
+    let digests = indices.iter().map(|i| i.digest).collect::<HashSet<_>>();
+
+    for layout in payloads
+        .iter()
+        .filter_map(PayloadKind::layout)
+        .flat_map(|p| &p.body)
+    {
+        if let layout::Entry::Regular(digest, target) = &layout.entry {
+            if !digests.contains(digest) {
+                problems.push(format!("/usr/{target} has no index entry for {digest:02x}"));
+            }
+        }
+    }
+
+    for index in indices {
+        match content_size {
+            Some(size) if index.start <= index.end && index.end <= size => {}
+            Some(size) => problems.push(format!(
+                "index {:02x} range {}..{} is outside the content of {size} bytes",
+                index.digest, index.start, index.end
+            )),
+            None => problems.push(format!("index {:02x} has no content payload", index.digest)),
+        }
+    }
+
+    problems
+}
+
+fn print_report(report: &Report) {
+    println!("{}", report.path.display().to_string().bold());
+    println!(
+        "    {:<12}{:<13}{:>12}{:>12}{:>10}  Checksum",
+        "Kind", "Compression", "Stored", "Plain", "Records"
+    );
+    for payload in &report.payloads {
+        let checksum = if payload.checksum_valid {
+            "valid".green()
+        } else {
+            "invalid".red()
+        };
+        println!(
+            "    {:<12}{:<13}{:>12}{:>12}{:>10}  {checksum}",
+            payload.kind,
+            payload.compression,
+            payload.stored_size,
+            payload.plain_size,
+            payload.records,
+        );
+    }
+
+    if report.problems.is_empty() {
+        println!("    {}", "No problems found".green());
+    }
+    for problem in &report.problems {
+        println!("    {} {problem}", "Problem".red());
+    }
+    println!();
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("{0} stone(s) failed verification")]
+    Verification(usize),
+
+    #[error("json")]
+    Json(#[from] serde_json::Error),
+
     #[error("io")]
     IO(#[from] std::io::Error),
 
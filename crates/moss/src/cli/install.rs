@@ -5,24 +5,36 @@
 use std::path::{Path, PathBuf};
 
 use clap::{arg, value_parser, ArgMatches, Command};
-use moss::{client::Client, environment};
+use moss::{
+    client::{self, plan, Client},
+    environment,
+};
+use thiserror::Error;
+use tui::{ask_yes_no, pretty::print_to_columns};
 
-pub use moss::client::install::Error;
+use super::{apply_blit_args, print_conflicts, with_blit_args};
 
 pub fn command() -> Command {
-    Command::new("install")
-        .about("Install packages")
-        .long_about("Install the requested software to the local system")
-        .arg(arg!(<NAME> ... "packages to install").value_parser(value_parser!(String)))
-        .arg(
-            arg!(--to <blit_target> "Blit this install to the provided directory instead of the root")
-                .long_help(
-                    "Blit this install to the provided directory instead of the root. \n\
-                     \n\
-                     This operation won't be captured as a new state",
-                )
-                .value_parser(value_parser!(PathBuf)),
-        )
+    with_blit_args(
+        Command::new("install")
+            .about("Install packages")
+            .long_about(
+                "Install the requested software to the local system. \n\
+                 \n\
+                 Packages can be pinned to a specific version (name@version) or release (name#release), \
+                 replacing any installed package of the same name.",
+            )
+            .arg(arg!(<NAME> ... "packages to install").value_parser(value_parser!(String)))
+            .arg(
+                arg!(--to <blit_target> "Blit this install to the provided directory instead of the root")
+                    .long_help(
+                        "Blit this install to the provided directory instead of the root. \n\
+                         \n\
+                         This operation won't be captured as a new state",
+                    )
+                    .value_parser(value_parser!(PathBuf)),
+            ),
+    )
 }
 
 /// Handle execution of `moss install`
@@ -36,12 +48,66 @@ pub async fn handle(args: &ArgMatches, root: &Path) -> Result<(), Error> {
     let yes = *args.get_one::<bool>("yes").unwrap();
 
     // Grab a client for the root
-    let mut client = Client::new(environment::NAME, root).await?;
+    let mut client = apply_blit_args(Client::new(environment::NAME, root).await?, args);
 
     // Make ephemeral if a blit target was provided
     if let Some(blit_target) = args.get_one::<PathBuf>("to").cloned() {
         client = client.ephemeral(blit_target)?;
     }
 
-    client.install(&pkgs, yes).await
+    let plan = client.plan_install(&pkgs).await?;
+
+    // If no new packages exist, exit and print
+    // packages already installed
+    if plan.is_empty() {
+        if !plan.unchanged.is_empty() {
+            println!("The following package(s) are already installed:");
+            println!();
+            print_to_columns(&plan.unchanged);
+        }
+
+        return Ok(());
+    }
+
+    if !plan.additions.is_empty() {
+        println!("The following package(s) will be installed:");
+        println!();
+        print_to_columns(&plan.additions);
+        println!();
+    }
+
+    if !plan.upgrades.is_empty() {
+        let replacements = plan.upgrades.iter().map(|u| &u.to).collect::<Vec<_>>();
+
+        println!("The following package(s) will be replaced:");
+        println!();
+        print_to_columns(&replacements);
+        println!();
+    }
+
+    print_conflicts(&plan.conflicts);
+
+    // Must we prompt?
+    if !yes && !ask_yes_no("Do you wish to continue?")? {
+        return Err(Error::Cancelled);
+    }
+
+    client.execute(&plan).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("plan")]
+    Plan(#[from] plan::Error),
+
+    #[error("io")]
+    Io(#[from] std::io::Error),
 }
@@ -2,19 +2,24 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::path::PathBuf;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
-use clap::{arg, ArgMatches, Command};
-use futures::StreamExt;
+use clap::{arg, value_parser, ArgMatches, Command};
+use futures::TryStreamExt;
 use itertools::Itertools;
 use thiserror::Error;
 
 use moss::{
-    client::{self, Client},
+    client::{self, plan, Client},
     environment,
-    package::Flags,
+    package::{self, Flags},
+    registry, Package,
 };
-use tui::Stylize;
+use tui::{HumanBytes, Stylize};
 
 pub fn command() -> Command {
     Command::new("list")
@@ -24,7 +29,12 @@ pub fn command() -> Command {
         .subcommand(
             Command::new("installed")
                 .about("List all installed packages")
-                .visible_alias("li"),
+                .visible_alias("li")
+                .arg(
+                    arg!(--sort <KEY> "Sort by name, or by installed size, largest first")
+                        .value_parser(["name", "size"])
+                        .default_value("name"),
+                ),
         )
         .subcommand(
             Command::new("available")
@@ -35,95 +45,73 @@ pub fn command() -> Command {
             Command::new("sync")
                 .about("List packages with sync changes")
                 .visible_alias("ls")
-                .arg(arg!(--"upgrade-only" "Only sync packages that have a version upgrade")),
+                .arg(arg!(--"upgrade-only" "Only sync packages that have a version upgrade"))
+                .arg(arg!(--"allow-downgrade" "Allow pinned packages to sync to an older release"))
+                .arg(
+                    arg!([NAME] ... "only list sync changes for the named packages")
+                        .value_parser(value_parser!(package::Spec)),
+                ),
+        )
+        .subcommand(
+            Command::new("versions")
+                .about("List all versions of a package")
+                .long_about("List all versions of a package available across repositories")
+                .visible_alias("lv")
+                .arg(arg!(<NAME> "package name").value_parser(value_parser!(String))),
         )
-}
-
-enum Sync {
-    All,
-    Upgrades,
 }
 
 /// Handle listing by filter
 pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
     let root = args.get_one::<PathBuf>("root").unwrap().clone();
 
-    let (filter_flags, sync) = match args.subcommand() {
-        Some(("available", _)) => (Flags::AVAILABLE, None),
-        Some(("installed", _)) => (Flags::INSTALLED, None),
-        Some(("sync", args)) => {
-            let sync = if *args.get_one::<bool>("upgrade-only").unwrap() {
-                Sync::Upgrades
-            } else {
-                Sync::All
-            };
+    let mut sort_by_size = false;
+
+    let filter_flags = match args.subcommand() {
+        Some(("versions", args)) => return list_versions(args, root).await,
+        Some(("sync", args)) => return list_sync(args, root).await,
+        Some(("available", _)) => Flags::AVAILABLE,
+        Some(("installed", args)) => {
+            sort_by_size = args
+                .get_one::<String>("sort")
+                .is_some_and(|key| key == "size");
 
-            (Flags::INSTALLED, Some(sync))
+            Flags::INSTALLED
         }
         _ => unreachable!(),
     };
 
     // Grab a client for the target, enumerate packages
     let client = Client::new(environment::NAME, root).await?;
-    let pkgs = client.registry.list(filter_flags).collect::<Vec<_>>().await;
-
-    let sync_available = if sync.is_some() {
-        client
-            .registry
-            .list(Flags::AVAILABLE)
-            .collect::<Vec<_>>()
-            .await
-    } else {
-        vec![]
-    };
+    let pkgs = client
+        .registry
+        .list(filter_flags)
+        .try_collect::<Vec<_>>()
+        .await?;
 
     if pkgs.is_empty() {
         return Err(Error::NoneFound);
     }
 
+    let mut installed_sizes = HashMap::new();
+    if sort_by_size {
+        for package in &pkgs {
+            installed_sizes.insert(package.id.clone(), client.installed_size(package).await?);
+        }
+    }
+
     // map to renderable state
     let mut set = pkgs
         .into_iter()
-        .map(|p| {
-            let sync = sync_available
-                .iter()
-                // Get first (priority based)
-                .find(|u| u.meta.name == p.meta.name)
-                // Ensure it's an upgrade (if `upgrades-only`)
-                // otherwise check if it's a change
-                .filter(|u| {
-                    if matches!(sync, Some(Sync::Upgrades)) {
-                        u.meta.source_release > p.meta.source_release
-                    } else {
-                        u.meta.source_release != p.meta.source_release
-                    }
-                })
-                .map(|u| Revision {
-                    version: u.meta.version_identifier.clone(),
-                    release: u.meta.source_release.to_string(),
-                });
-
-            Format {
-                name: p.meta.name.to_string(),
-                revision: Revision {
-                    version: p.meta.version_identifier,
-                    release: p.meta.source_release.to_string(),
-                },
-                summary: p.meta.summary,
-                explicit: if filter_flags == Flags::INSTALLED {
-                    p.flags.contains(Flags::EXPLICIT)
-                } else {
-                    true
-                },
-                sync,
-            }
-        })
-        .filter(|item| {
-            if sync.is_some() {
-                item.sync.is_some()
+        .map(|p| Format {
+            installed_size: installed_sizes.get(&p.id).copied().flatten(),
+            source: (filter_flags == Flags::AVAILABLE).then(|| p.source.to_string()),
+            explicit: if filter_flags == Flags::INSTALLED {
+                p.flags.contains(Flags::EXPLICIT)
             } else {
                 true
-            }
+            },
+            ..Format::new(&p)
         })
         .collect_vec();
 
@@ -132,6 +120,57 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
     set.sort_by_key(|s| s.name.clone());
     set.dedup_by_key(|s| s.name.clone());
 
+    if sort_by_size {
+        set.sort_by_key(|s| Reverse(s.installed_size));
+    }
+
+    print_formatted(set);
+
+    Ok(())
+}
+
+/// List the changes a sync with the same arguments would make
+async fn list_sync(args: &ArgMatches, root: PathBuf) -> Result<(), Error> {
+    let options = plan::SyncOptions {
+        upgrade_only: *args.get_one::<bool>("upgrade-only").unwrap(),
+        allow_downgrade: *args.get_one::<bool>("allow-downgrade").unwrap(),
+        targets: args
+            .get_many::<package::Spec>("NAME")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+    };
+
+    let client = Client::new(environment::NAME, root).await?;
+    let plan = client.plan_sync(&options).await?;
+
+    let upgrades = plan.upgrades.iter().map(|upgrade| Format {
+        sync: Some(Revision::new(&upgrade.to)),
+        ..Format::new(&upgrade.from)
+    });
+    let additions = plan.additions.iter().map(|package| Format {
+        explicit: false,
+        change: Some("new"),
+        ..Format::new(package)
+    });
+    let removals = plan.removals.iter().map(|package| Format {
+        change: Some("removed"),
+        ..Format::new(package)
+    });
+
+    print_formatted(
+        upgrades
+            .chain(additions)
+            .chain(removals)
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect(),
+    );
+
+    Ok(())
+}
+
+fn print_formatted(set: Vec<Format>) {
     // Grab maximum length
     let max_length = set.iter().map(Format::size).max().unwrap_or_default();
 
@@ -163,8 +202,78 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
             print_revision(sync, true);
         }
 
+        // Print package added or removed by a sync
+        if let Some(change) = item.change {
+            print!(" {}", format!("({change})").dim());
+        }
+
+        // Print source
+        if let Some(source) = item.source {
+            print!(" {}", format!("[{source}]").blue());
+        }
+
+        // Print installed size
+        if let Some(size) = item.installed_size {
+            print!(" {}", format!("({})", HumanBytes(size)).dim());
+        }
+
         println!(" - {}", item.summary);
     }
+}
+
+/// List every known release of the named package, in order of preference
+async fn list_versions(args: &ArgMatches, root: PathBuf) -> Result<(), Error> {
+    let name = package::Name::from(args.get_one::<String>("NAME").unwrap().clone());
+
+    let client = Client::new(environment::NAME, root).await?;
+    let packages = client
+        .registry
+        .by_name(&name, Flags::NONE)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    if packages.is_empty() {
+        return Err(Error::NoneFound);
+    }
+
+    let installed = packages
+        .iter()
+        .filter(|p| p.flags.contains(Flags::INSTALLED))
+        .map(|p| p.id.clone())
+        .collect::<HashSet<_>>();
+
+    for package in packages.iter().unique_by(|p| &p.id) {
+        let version = if installed.contains(&package.id) {
+            package.meta.version_identifier.clone().green()
+        } else {
+            package.meta.version_identifier.clone().magenta()
+        };
+        // All origins of this release, skipping the installed entry
+        let sources = packages
+            .iter()
+            .filter(|p| p.id == package.id && p.source != package::Source::Installed)
+            .map(|p| p.source.to_string())
+            .join(", ");
+        let source_tag = if sources.is_empty() {
+            String::default()
+        } else {
+            format!(" {}", format!("[{sources}]").blue())
+        };
+        let installed_tag = if installed.contains(&package.id) {
+            format!(" {}", "(installed)".dim())
+        } else {
+            String::default()
+        };
+
+        println!(
+            "{} {}-{}{}{}",
+            package.meta.name.to_string().bold(),
+            version,
+            package.meta.source_release.to_string().dim(),
+            source_tag,
+            installed_tag,
+        );
+    }
 
     Ok(())
 }
@@ -173,12 +282,28 @@ pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
 struct Format {
     name: String,
     summary: String,
+    source: Option<String>,
     revision: Revision,
     explicit: bool,
     sync: Option<Revision>,
+    change: Option<&'static str>,
+    installed_size: Option<u64>,
 }
 
 impl Format {
+    fn new(package: &Package) -> Self {
+        Self {
+            name: package.meta.name.to_string(),
+            summary: package.meta.summary.clone(),
+            source: None,
+            revision: Revision::new(package),
+            explicit: package.flags.contains(Flags::EXPLICIT),
+            sync: None,
+            change: None,
+            installed_size: None,
+        }
+    }
+
     fn size(&self) -> usize {
         self.name.len()
             + self.revision.size()
@@ -193,6 +318,13 @@ struct Revision {
 }
 
 impl Revision {
+    fn new(package: &Package) -> Self {
+        Self {
+            version: package.meta.version_identifier.clone(),
+            release: package.meta.source_release.to_string(),
+        }
+    }
+
     fn size(&self) -> usize {
         self.version.len() + self.release.len()
     }
@@ -204,4 +336,10 @@ pub enum Error {
     NoneFound,
     #[error("client")]
     Client(#[from] client::Error),
+
+    #[error("plan")]
+    Plan(#[from] plan::Error),
+
+    #[error("registry")]
+    Registry(#[from] registry::Error),
 }
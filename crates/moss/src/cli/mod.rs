@@ -2,19 +2,33 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use clap::{Arg, ArgAction, Command};
+use clap::{arg, value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum};
+use moss::{
+    client::{blit, Client},
+    registry, repository,
+};
 use thiserror::Error;
+use tui::Stylize;
+use vfs::tree::{Conflict, Policy};
 
+mod export;
 mod extract;
+mod history;
 mod index;
 mod info;
+mod init;
 mod inspect;
 mod install;
 mod list;
+mod provides;
 mod remove;
 mod repo;
+mod search_file;
 mod state;
 mod sync;
 mod version;
@@ -48,19 +62,108 @@ fn command() -> Command {
                 .action(ArgAction::SetTrue),
         )
         .arg_required_else_help(true)
+        .subcommand(export::command())
         .subcommand(extract::command())
+        .subcommand(history::command())
         .subcommand(index::command())
         .subcommand(info::command())
+        .subcommand(init::command())
         .subcommand(inspect::command())
         .subcommand(install::command())
         .subcommand(list::command())
+        .subcommand(provides::command())
         .subcommand(remove::command())
         .subcommand(repo::command())
+        .subcommand(search_file::command())
         .subcommand(state::command())
         .subcommand(sync::command())
         .subcommand(version::command())
 }
 
+/// How files provided by more than one package are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ConflictPolicy {
+    /// Fail before anything is changed
+    Error,
+    /// Keep the file of the first package & report the conflict
+    Warn,
+    /// Keep the file of the package listed first by --prefer & report the conflict
+    Prefer,
+}
+
+/// Add the args controlling how a new state is blitted to `command`
+fn with_blit_args(command: Command) -> Command {
+    command
+        .arg(
+            arg!(--"blit-mode" <MODE> "How files are created from the cache")
+                .long_help(
+                    "How files are created from the cache: auto, hardlink, reflink or copy. \n\
+                     \n\
+                     auto hardlinks files, falling back to reflinks then copies when the \
+                     target filesystem doesn't support them",
+                )
+                .default_value("auto")
+                .value_parser(value_parser!(blit::Mode)),
+        )
+        .arg(
+            arg!(--conflicts <POLICY> "How files provided by more than one package are handled")
+                .default_value("error")
+                .value_parser(value_parser!(ConflictPolicy)),
+        )
+        .arg(
+            arg!(--prefer <NAME> "Package whose files win conflicts, highest priority first")
+                .long_help(
+                    "Package whose files win conflicts, highest priority first. \n\
+                     \n\
+                     Only used with --conflicts prefer, packages not listed have \
+                     the lowest priority",
+                )
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(String)),
+        )
+}
+
+/// Configure `client` with the args added by [`with_blit_args`]
+fn apply_blit_args(client: Client, args: &ArgMatches) -> Client {
+    let policy = match args.get_one::<ConflictPolicy>("conflicts").unwrap() {
+        ConflictPolicy::Error => Policy::Error,
+        ConflictPolicy::Warn => Policy::Warn,
+        ConflictPolicy::Prefer => {
+            let preferred = args
+                .get_many::<String>("prefer")
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+
+            Policy::Prefer(
+                preferred
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| (name.to_string(), (preferred.len() - index) as u64))
+                    .collect::<HashMap<_, _>>(),
+            )
+        }
+    };
+
+    client
+        .with_blit_mode(*args.get_one::<blit::Mode>("blit-mode").unwrap())
+        .with_conflict_policy(policy)
+}
+
+/// Print the file conflicts a plan resolved, if any
+fn print_conflicts(conflicts: &[Conflict]) {
+    if conflicts.is_empty() {
+        return;
+    }
+
+    println!("The following file conflicts will be ignored:");
+    println!();
+    for conflict in conflicts {
+        println!("{} {conflict}", "Warning".yellow());
+    }
+    println!();
+}
+
 /// Process all CLI arguments
 pub async fn process() -> Result<(), Error> {
     let matches = command().get_matches();
@@ -71,15 +174,22 @@ pub async fn process() -> Result<(), Error> {
 
     let root = matches.get_one::<PathBuf>("root").unwrap();
 
-    match command().get_matches().subcommand() {
+    moss::request::load_config(&config::Manager::system(root, "moss")).await?;
+
+    let result = match command().get_matches().subcommand() {
+        Some(("export", args)) => export::handle(args, root).await.map_err(Error::Export),
         Some(("extract", args)) => extract::handle(args).await.map_err(Error::Extract),
+        Some(("history", args)) => history::handle(args, root).await.map_err(Error::History),
         Some(("index", args)) => index::handle(args).await.map_err(Error::Index),
         Some(("info", args)) => info::handle(args).await.map_err(Error::Info),
+        Some(("init", args)) => init::handle(args, root).await.map_err(Error::Init),
         Some(("inspect", args)) => inspect::handle(args).await.map_err(Error::Inspect),
         Some(("install", args)) => install::handle(args, root).await.map_err(Error::Install),
         Some(("list", args)) => list::handle(args).await.map_err(Error::List),
+        Some(("provides", args)) => provides::handle(args).await.map_err(Error::Provides),
         Some(("remove", args)) => remove::handle(args, root).await.map_err(Error::Remove),
         Some(("repo", args)) => repo::handle(args, root).await.map_err(Error::Repo),
+        Some(("search-file", args)) => search_file::handle(args).await.map_err(Error::SearchFile),
         Some(("state", args)) => state::handle(args, root).await.map_err(Error::State),
         Some(("sync", args)) => sync::handle(args, root).await.map_err(Error::Sync),
         Some(("version", _)) => {
@@ -91,17 +201,74 @@ pub async fn process() -> Result<(), Error> {
             Ok(())
         }
         _ => unreachable!(),
+    };
+
+    if let Some(id) = result.as_ref().err().and_then(corrupt_repository) {
+        offer_refetch(root, id, matches.get_flag("yes")).await?;
     }
+
+    result
+}
+
+/// Returns the repository whose meta db is corrupt, if that's what
+/// caused `error`
+fn corrupt_repository(error: &Error) -> Option<repository::Id> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+
+    while let Some(error) = source {
+        let id = error
+            .downcast_ref::<registry::Error>()
+            .and_then(registry::Error::corrupt_repository)
+            .or_else(|| {
+                error
+                    .downcast_ref::<repository::manager::Error>()
+                    .and_then(repository::manager::Error::corrupt_repository)
+            });
+
+        if let Some(id) = id {
+            return Some(id.clone());
+        }
+
+        source = error.source();
+    }
+
+    None
+}
+
+/// Offer to fetch a repository with a corrupt meta db from scratch. The
+/// original error is still reported, so the command can be retried.
+async fn offer_refetch(root: &Path, id: repository::Id, yes: bool) -> Result<(), Error> {
+    println!(
+        "{} meta db of repository {id} is corrupt",
+        "Warning".yellow()
+    );
+
+    // Without a terminal to ask, fall through to the original error
+    if !yes && !tui::ask_yes_no("Do you wish to fetch it again?").unwrap_or(false) {
+        return Ok(());
+    }
+
+    repo::refetch(root, config::Manager::system(root, "moss"), id.clone()).await?;
+
+    println!("{} repository {id}, please retry", "Refetched".green());
+
+    Ok(())
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("history")]
+    History(#[from] history::Error),
+
     #[error("index")]
     Index(#[from] index::Error),
 
     #[error("info")]
     Info(#[from] info::Error),
 
+    #[error("init")]
+    Init(#[from] init::Error),
+
     #[error("install")]
     Install(#[from] install::Error),
 
@@ -111,18 +278,30 @@ pub enum Error {
     #[error("inspect")]
     Inspect(#[from] inspect::Error),
 
+    #[error("export")]
+    Export(#[from] export::Error),
+
     #[error("extract")]
     Extract(#[from] extract::Error),
 
+    #[error("provides")]
+    Provides(#[from] provides::Error),
+
     #[error("remove")]
     Remove(#[from] remove::Error),
 
     #[error("repo")]
     Repo(#[from] repo::Error),
 
+    #[error("search-file")]
+    SearchFile(#[from] search_file::Error),
+
     #[error("state")]
     State(#[from] state::Error),
 
     #[error("sync")]
     Sync(#[from] sync::Error),
+
+    #[error("network config")]
+    Network(#[from] moss::request::Error),
 }
@@ -0,0 +1,94 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::PathBuf;
+
+use clap::{arg, ArgMatches, Command};
+use futures::StreamExt;
+use itertools::Itertools;
+use moss::{
+    client::{self, Client},
+    dependency, environment,
+    package::{self, Flags},
+    registry, Provider,
+};
+use thiserror::Error;
+use tui::Stylize;
+
+pub fn command() -> Command {
+    Command::new("provides")
+        .visible_alias("whatprovides")
+        .about("List packages providing a dependency")
+        .long_about(
+            "List every installed & available package providing a dependency, \
+             in order of preference, i.e. soname(libz.so.1(x86_64)), pkgconfig(zlib) \
+             or binary(git)",
+        )
+        .arg(arg!(<PROVIDER> "provider to look up").value_parser(clap::value_parser!(String)))
+}
+
+/// Handle execution of `moss provides`
+pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
+    let provider = args
+        .get_one::<String>("PROVIDER")
+        .unwrap()
+        .parse::<Provider>()?;
+    let root = args.get_one::<PathBuf>("root").unwrap().clone();
+
+    let client = Client::new(environment::NAME, root).await?;
+
+    // Every repository providing a package is shown
+    let packages = registry::dedupe(
+        client
+            .registry
+            .by_provider(&provider, Flags::NONE)
+            .collect::<Vec<_>>()
+            .await,
+    )
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    if packages.is_empty() {
+        return Err(Error::NotFound(provider));
+    }
+
+    for package in packages {
+        let source = match &package.source {
+            package::Source::Installed => "installed".to_string(),
+            package::Source::Cobble(path) => path.display().to_string(),
+            package::Source::Repository(ids) => ids
+                .iter()
+                .map(|id| match client.repository(id) {
+                    Some(repo) => format!("{id} [{}]", repo.priority),
+                    None => id.to_string(),
+                })
+                .join(", "),
+        };
+
+        println!(
+            "{} {}-{} {}",
+            package.meta.name.to_string().bold(),
+            package.meta.version_identifier.clone().magenta(),
+            package.meta.source_release.to_string().dim(),
+            format!("({source})").blue(),
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No package provides {0}")]
+    NotFound(Provider),
+
+    #[error(transparent)]
+    Parse(#[from] dependency::ParseError),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("registry")]
+    Registry(#[from] registry::Error),
+}
@@ -4,10 +4,11 @@
 
 use std::path::Path;
 
-use clap::{arg, Arg, ArgAction, ArgMatches, Command};
+use clap::{arg, Arg, ArgAction, ArgGroup, ArgMatches, Command};
 use itertools::Itertools;
 use moss::{
     repository::{self, Priority},
+    signature::PublicKey,
     Installation, Repository,
 };
 use thiserror::Error;
@@ -17,12 +18,26 @@ use url::Url;
 enum Action<'a> {
     // Root
     List(&'a Path),
-    // Root, Id, Url, Comment
-    Add(&'a Path, String, Url, String, Priority),
+    // Root, Id, Repository
+    Add(&'a Path, String, Repository),
     // Root, Id
     Remove(&'a Path, String),
-    // Root, Id
-    Update(&'a Path, Option<String>),
+    // Root, Id, Refetch
+    Update(&'a Path, Option<String>, bool),
+    // Root, Id, Enabled
+    Enable(&'a Path, String, bool),
+    // Root, Id, Changes
+    Set(&'a Path, String, Changes),
+}
+
+/// Requested changes to a repository config
+struct Changes {
+    priority: Option<Priority>,
+    uri: Option<Url>,
+    mirrors: Option<Vec<Url>>,
+    comment: Option<String>,
+    public_key: Option<PublicKey>,
+    insecure: Option<bool>,
 }
 
 /// Return a command for handling `repo` subcommands
@@ -50,6 +65,19 @@ pub fn command() -> Command {
                         .action(ArgAction::Set)
                         .default_value("0")
                         .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    arg!(--mirror <URI> "Mirror of the repo uri, tried in order when unavailable")
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(Url)),
+                )
+                .arg(
+                    arg!(--key <KEY> "Trusted public key which signs the repository index")
+                        .value_parser(clap::value_parser!(PublicKey)),
+                )
+                .arg(
+                    arg!(--insecure "Don't verify the signature of the repository index")
+                        .conflicts_with("key"),
                 ),
         )
         .subcommand(
@@ -66,7 +94,63 @@ pub fn command() -> Command {
             Command::new("update")
                 .about("Update the system repositories")
                 .long_about("If no repository is named, update them all")
-                .arg(arg!([NAME] "repo name").value_parser(clap::value_parser!(String))),
+                .arg(arg!([NAME] "repo name").value_parser(clap::value_parser!(String)))
+                .arg(
+                    arg!(--refetch "Discard the cached index & meta db and fetch them again")
+                        .requires("NAME"),
+                ),
+        )
+        .subcommand(
+            Command::new("enable")
+                .about("Enable a repository")
+                .arg(arg!(<NAME> "repo name").value_parser(clap::value_parser!(String))),
+        )
+        .subcommand(
+            Command::new("disable")
+                .about("Disable a repository")
+                .long_about(
+                    "Disable a repository, excluding it's packages from all operations \
+                     while keeping it's cached index",
+                )
+                .arg(arg!(<NAME> "repo name").value_parser(clap::value_parser!(String))),
+        )
+        .subcommand(
+            Command::new("set")
+                .about("Change the configuration of a repository")
+                .arg(arg!(<NAME> "repo name").value_parser(clap::value_parser!(String)))
+                .arg(
+                    arg!(--priority <N> "Repository priority")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(arg!(--uri <URI> "Repository uri").value_parser(clap::value_parser!(Url)))
+                .arg(
+                    arg!(--mirror <URI> "Replace the mirrors of the repository uri")
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(Url)),
+                )
+                .arg(
+                    arg!(--comment <COMMENT> "Repository comment")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    arg!(--key <KEY> "Trusted public key which signs the repository index")
+                        .value_parser(clap::value_parser!(PublicKey)),
+                )
+                .arg(
+                    arg!(--insecure <BOOL> "Don't verify the signature of the repository index")
+                        .long_help(
+                            "Don't verify the signature of the repository index. \n\
+                             \n\
+                             Setting a --key also marks the repository as secure",
+                        )
+                        .value_parser(clap::value_parser!(bool)),
+                )
+                .group(
+                    ArgGroup::new("changes")
+                        .args(["priority", "uri", "mirror", "comment", "key", "insecure"])
+                        .required(true)
+                        .multiple(true),
+                ),
         )
 }
 
@@ -78,28 +162,71 @@ pub async fn handle(args: &ArgMatches, root: &Path) -> Result<(), Error> {
         Some(("add", cmd_args)) => Action::Add(
             root,
             cmd_args.get_one::<String>("NAME").cloned().unwrap(),
-            cmd_args.get_one::<Url>("URI").cloned().unwrap(),
-            cmd_args.get_one::<String>("comment").cloned().unwrap(),
-            Priority::new(*cmd_args.get_one::<u64>("priority").unwrap()),
+            Repository {
+                description: cmd_args.get_one::<String>("comment").cloned().unwrap(),
+                uri: cmd_args.get_one::<Url>("URI").cloned().unwrap(),
+                mirrors: cmd_args
+                    .get_many::<Url>("mirror")
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect(),
+                priority: Priority::new(*cmd_args.get_one::<u64>("priority").unwrap()),
+                enabled: true,
+                public_key: cmd_args.get_one::<PublicKey>("key").cloned(),
+                insecure: *cmd_args.get_one::<bool>("insecure").unwrap(),
+            },
         ),
         Some(("list", _)) => Action::List(root),
         Some(("remove", cmd_args)) => {
             Action::Remove(root, cmd_args.get_one::<String>("NAME").cloned().unwrap())
         }
-        Some(("update", cmd_args)) => {
-            Action::Update(root, cmd_args.get_one::<String>("NAME").cloned())
-        }
+        Some(("update", cmd_args)) => Action::Update(
+            root,
+            cmd_args.get_one::<String>("NAME").cloned(),
+            cmd_args.get_flag("refetch"),
+        ),
+        Some(("enable", cmd_args)) => Action::Enable(
+            root,
+            cmd_args.get_one::<String>("NAME").cloned().unwrap(),
+            true,
+        ),
+        Some(("disable", cmd_args)) => Action::Enable(
+            root,
+            cmd_args.get_one::<String>("NAME").cloned().unwrap(),
+            false,
+        ),
+        Some(("set", cmd_args)) => Action::Set(
+            root,
+            cmd_args.get_one::<String>("NAME").cloned().unwrap(),
+            Changes {
+                priority: cmd_args
+                    .get_one::<u64>("priority")
+                    .copied()
+                    .map(Priority::new),
+                uri: cmd_args.get_one::<Url>("uri").cloned(),
+                mirrors: cmd_args
+                    .get_many::<Url>("mirror")
+                    .map(|mirrors| mirrors.cloned().collect()),
+                comment: cmd_args.get_one::<String>("comment").cloned(),
+                public_key: cmd_args.get_one::<PublicKey>("key").cloned(),
+                insecure: cmd_args.get_one::<bool>("insecure").copied(),
+            },
+        ),
         _ => unreachable!(),
     };
 
     // dispatch to runtime handler function
     match handler {
         Action::List(root) => list(root, config).await,
-        Action::Add(root, name, uri, comment, priority) => {
-            add(root, config, name, uri, comment, priority).await
+        Action::Add(root, name, repository) => add(root, config, name, repository).await,
+        Action::Remove(root, name) => remove(root, config, name).await,
+        Action::Update(root, name, false) => update(root, config, name).await,
+        Action::Update(root, name, true) => {
+            refetch(root, config, repository::Id::new(name.unwrap())).await
         }
-        Action::Remove(_, _) => unimplemented!(),
-        Action::Update(root, name) => update(root, config, name).await,
+        Action::Enable(root, name, enabled) => enable(root, config, name, enabled).await,
+        Action::Set(root, name, changes) => set(root, config, name, changes).await,
     }
 }
 
@@ -108,23 +235,14 @@ async fn add(
     root: &Path,
     config: config::Manager,
     name: String,
-    uri: Url,
-    comment: String,
-    priority: Priority,
+    repository: Repository,
 ) -> Result<(), Error> {
     let installation = Installation::open(root);
 
     let mut manager = repository::Manager::system(config, installation).await?;
 
     manager
-        .add_repository(
-            repository::Id::new(name),
-            Repository {
-                description: comment,
-                uri,
-                priority,
-            },
-        )
+        .add_repository(repository::Id::new(name), repository)
         .await?;
 
     manager.refresh_all().await?;
@@ -132,6 +250,17 @@ async fn add(
     Ok(())
 }
 
+/// Remove a repository along with it's cached index
+async fn remove(root: &Path, config: config::Manager, name: String) -> Result<(), Error> {
+    let installation = Installation::open(root);
+    let mut manager = repository::Manager::system(config, installation).await?;
+
+    let id = repository::Id::new(name);
+    manager.remove_repository(&id).await?;
+
+    Ok(())
+}
+
 /// List the repositories and pretty print them
 async fn list(root: &Path, config: config::Manager) -> Result<(), Error> {
     let installation = Installation::open(root);
@@ -146,7 +275,20 @@ async fn list(root: &Path, config: config::Manager) -> Result<(), Error> {
     for (id, repo) in
         configured_repos.sorted_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).reverse())
     {
-        println!(" - {} = {} [{}]", id, repo.uri, repo.priority);
+        let disabled = if repo.enabled { "" } else { " (disabled)" };
+        let refreshed = match manager.last_refreshed(id).await? {
+            Some(time) => format!("refreshed {}", time.format("%Y-%m-%d %H:%M:%S %Z")),
+            None => "never refreshed".to_string(),
+        };
+
+        println!(
+            " - {} = {} [{}]{} - {}",
+            id, repo.uri, repo.priority, disabled, refreshed
+        );
+
+        for mirror in &repo.mirrors {
+            println!("     mirror {mirror}");
+        }
     }
 
     Ok(())
@@ -165,8 +307,120 @@ async fn update(root: &Path, config: config::Manager, which: Option<String>) ->
     Ok(())
 }
 
+/// Fetch a repository from scratch, discarding it's cached index & meta db
+pub async fn refetch(
+    root: &Path,
+    config: config::Manager,
+    id: repository::Id,
+) -> Result<(), Error> {
+    let installation = Installation::open(root);
+
+    repository::manager::discard_cache(&config, &installation, &id).await?;
+
+    let mut manager = repository::Manager::system(config, installation).await?;
+    manager.refresh(&id).await?;
+
+    Ok(())
+}
+
+/// Enable or disable a repository
+async fn enable(
+    root: &Path,
+    config: config::Manager,
+    name: String,
+    enabled: bool,
+) -> Result<(), Error> {
+    let installation = Installation::open(root);
+    let mut manager = repository::Manager::system(config, installation).await?;
+
+    let id = repository::Id::new(name);
+    let repository = manager
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| Error::UnknownRepo(id.clone()))?;
+
+    manager
+        .add_repository(
+            id.clone(),
+            Repository {
+                enabled,
+                ..repository
+            },
+        )
+        .await?;
+
+    // Catch up on any refreshes skipped while disabled
+    if enabled {
+        manager.refresh(&id).await?;
+    }
+
+    Ok(())
+}
+
+/// Update the configuration of a repository
+async fn set(
+    root: &Path,
+    config: config::Manager,
+    name: String,
+    changes: Changes,
+) -> Result<(), Error> {
+    let installation = Installation::open(root);
+    let mut manager = repository::Manager::system(config, installation).await?;
+
+    let id = repository::Id::new(name);
+    let repository = manager
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| Error::UnknownRepo(id.clone()))?;
+
+    let uri_changed = changes
+        .uri
+        .as_ref()
+        .is_some_and(|uri| *uri != repository.uri);
+    let enabled = repository.enabled;
+
+    // A new key is only useful if it's checked
+    let insecure = match (&changes.public_key, changes.insecure) {
+        (Some(_), Some(true)) => return Err(Error::InsecureKey(id)),
+        (_, Some(insecure)) => insecure,
+        (Some(_), None) => false,
+        (None, None) => repository.insecure,
+    };
+    let public_key = changes.public_key.or(repository.public_key.clone());
+    let trust_changed = public_key != repository.public_key || insecure != repository.insecure;
+
+    manager
+        .add_repository(
+            id.clone(),
+            Repository {
+                description: changes.comment.unwrap_or(repository.description),
+                uri: changes.uri.unwrap_or(repository.uri),
+                mirrors: changes.mirrors.unwrap_or(repository.mirrors),
+                priority: changes.priority.unwrap_or(repository.priority),
+                enabled,
+                public_key,
+                insecure,
+            },
+        )
+        .await?;
+
+    // A new uri has it's own meta db which needs populating, while new
+    // trust settings wipe the meta db so the index is fetched & verified again
+    if (uri_changed || trust_changed) && enabled {
+        manager.refresh(&id).await?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("unknown repository {0}")]
+    UnknownRepo(repository::Id),
+
+    #[error("repository {0} can't be given a trusted key and marked as insecure")]
+    InsecureKey(repository::Id),
+
     #[error("repo manager")]
     RepositoryManager(#[from] repository::manager::Error),
 }
@@ -0,0 +1,113 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{collections::HashMap, path::PathBuf};
+
+use clap::{arg, ArgMatches, Command};
+use fnmatch::Pattern;
+use futures::TryStreamExt;
+use itertools::Itertools;
+use moss::{
+    client::{self, Client},
+    environment,
+    package::Flags,
+    registry,
+};
+use thiserror::Error;
+use tui::Stylize;
+
+use super::info::{layout_kind, layout_path};
+
+pub fn command() -> Command {
+    Command::new("search-file")
+        .about("Find the installed package owning a path")
+        .long_about(
+            "Find the installed packages owning a path. \n\
+             \n\
+             Globs are supported, where `*` matches within a single path component \
+             and `?` matches any single character, i.e. /usr/bin/*sh",
+        )
+        .arg(arg!(<PATH> "absolute path or glob").value_parser(clap::value_parser!(String)))
+}
+
+/// Handle execution of `moss search-file`
+pub async fn handle(args: &ArgMatches) -> Result<(), Error> {
+    let path = args.get_one::<String>("PATH").unwrap().clone();
+    let root = args.get_one::<PathBuf>("root").unwrap().clone();
+
+    // Layout targets are relative to /usr
+    let target = path
+        .strip_prefix("/usr/")
+        .ok_or_else(|| Error::Unmanaged(path.clone()))?;
+
+    let client = Client::new(environment::NAME, root).await?;
+
+    let layouts = match target.find(['*', '?']) {
+        Some(wildcard) => {
+            let pattern = path.parse::<Pattern>()?;
+
+            // Narrow down by the literal prefix using the target index
+            client
+                .layout_db
+                .by_target_prefix(&target[..wildcard])
+                .await?
+                .into_iter()
+                .filter(|(_, layout)| pattern.match_path(&layout_path(layout)).is_some())
+                .collect()
+        }
+        None => client.layout_db.by_target(target).await?,
+    };
+
+    // The layout db also holds cached packages which aren't installed
+    let installed = client
+        .registry
+        .list_installed(Flags::NONE)
+        .map_ok(|package| (package.id.clone(), package))
+        .try_collect::<HashMap<_, _>>()
+        .await?;
+
+    let owned = layouts
+        .iter()
+        .filter_map(|(id, layout)| Some((installed.get(id)?, layout)))
+        .sorted_by_key(|(package, layout)| (layout_path(layout), package.meta.name.to_string()))
+        .collect::<Vec<_>>();
+
+    if owned.is_empty() {
+        return Err(Error::NotFound(path));
+    }
+
+    for (package, layout) in owned {
+        println!(
+            "{} {} {}-{} {}",
+            layout_path(layout),
+            package.meta.name.to_string().bold(),
+            package.meta.version_identifier.clone().magenta(),
+            package.meta.source_release.to_string().dim(),
+            format!("({})", layout_kind(layout)).dim(),
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No installed package owns {0}")]
+    NotFound(String),
+
+    #[error("Only paths within /usr are owned by packages, got {0}")]
+    Unmanaged(String),
+
+    #[error("invalid glob")]
+    Pattern(#[from] fnmatch::Error),
+
+    #[error("layout db")]
+    Layout(#[from] moss::db::layout::Error),
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("registry")]
+    Registry(#[from] registry::Error),
+}
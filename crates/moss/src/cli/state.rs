@@ -2,7 +2,7 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::path::Path;
+use std::{path::Path, time::Duration};
 
 use clap::{arg, ArgAction, ArgMatches, Command};
 use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
@@ -11,7 +11,7 @@ use moss::{
     environment, state,
 };
 use thiserror::Error;
-use tui::Stylize;
+use tui::{pretty::print_to_columns, HumanBytes, Stylize};
 
 pub fn command() -> Command {
     Command::new("state")
@@ -19,6 +19,16 @@ pub fn command() -> Command {
         .long_about("Manage state ...")
         .subcommand_required(true)
         .subcommand(Command::new("list").about("List all states"))
+        .subcommand(
+            Command::new("show")
+                .about("Show the details of a state")
+                .long_about(
+                    "Show the details of a state, including how it was created \
+                     and the packages it holds",
+                )
+                .arg(arg!(<ID> "State to show").value_parser(clap::value_parser!(i64)))
+                .arg(arg!(--size "Show the disk usage of the state's packages")),
+        )
         .subcommand(
             Command::new("prune").about("Prune old states").arg(
                 arg!(-k --keep "Keep this many states")
@@ -32,6 +42,7 @@ pub fn command() -> Command {
 pub async fn handle(args: &ArgMatches, root: &Path) -> Result<(), Error> {
     match args.subcommand() {
         Some(("list", _)) => list(root).await,
+        Some(("show", args)) => show(args, root).await,
         Some(("prune", args)) => prune(args, root).await,
         _ => unreachable!(),
     }
@@ -53,6 +64,46 @@ pub async fn list(root: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Show a single state in full
+pub async fn show(args: &ArgMatches, root: &Path) -> Result<(), Error> {
+    let id = state::Id::from(*args.get_one::<i64>("ID").unwrap());
+    let show_size = args.get_flag("size");
+
+    let client = Client::new(environment::NAME, root).await?;
+
+    let state = client.state_db.get(&id).await?;
+    let mut packages = client
+        .resolve_packages(state.selections.iter().map(|s| &s.package))
+        .await?;
+    packages.sort_by(|a, b| a.meta.name.cmp(&b.meta.name));
+
+    let footprint = if show_size {
+        Some(
+            client
+                .footprint(state.selections.iter().map(|s| &s.package))
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    print_state(state);
+    print_to_columns(&packages);
+
+    // Files shared between packages are hardlinks to the same asset
+    if let Some(footprint) = footprint {
+        println!();
+        println!(
+            "{} {} ({} before deduplication)",
+            "Size:".bold(),
+            HumanBytes(footprint.unique),
+            HumanBytes(footprint.total)
+        );
+    }
+
+    Ok(())
+}
+
 pub async fn prune(args: &ArgMatches, root: &Path) -> Result<(), Error> {
     let keep = *args.get_one::<u64>("keep").unwrap();
 
@@ -75,12 +126,53 @@ fn print_state(state: state::State) {
         "Description:".bold(),
         state.description.unwrap_or(String::from("no description"))
     );
-    // TODO: List packages?
+    if let Some(provenance) = state.provenance {
+        println!("{} {}", "Command:".bold(), provenance.command_line);
+        println!(
+            "{} {} (uid {})",
+            "User:".bold(),
+            provenance.user.as_deref().unwrap_or("unknown"),
+            provenance.uid
+        );
+        if let Some(sudo_user) = &provenance.sudo_user {
+            println!(
+                "{} {} {}",
+                "Sudo user:".bold(),
+                sudo_user,
+                "(unverified)".dim()
+            );
+        }
+        println!("{} moss {}", "Version:".bold(), provenance.version);
+        println!(
+            "{} {}",
+            "Duration:".bold(),
+            format_duration(provenance.duration)
+        );
+        for index in provenance.repositories {
+            println!(
+                "{} {} {}",
+                "Repository:".bold(),
+                index.repository,
+                index.hash.as_deref().unwrap_or("never fetched").dim()
+            );
+        }
+    }
     // TODO: Start with normal list, compute diff, reverse to print ?
     println!("{} {}", "Packages:".bold(), state.selections.len());
     println!();
 }
 
+/// Format a duration as i.e. `1m 5s`, with millisecond precision below 10 seconds
+pub(super) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match secs {
+        0..=9 => format!("{:.2}s", duration.as_secs_f64()),
+        10..=59 => format!("{secs}s"),
+        _ => format!("{}m {}s", secs / 60, secs % 60),
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("client")]
@@ -0,0 +1,416 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Creation of regular files from the asset cache when blitting
+//!
+//! Hardlinks are the cheapest, but can't cross filesystems. Reflinks
+//! (`FICLONE`) share extents across mounts of the same filesystem on
+//! supporting filesystems (btrfs, xfs), otherwise the asset is copied.
+
+use std::{
+    fmt,
+    fs::File,
+    io,
+    os::fd::{AsRawFd, RawFd},
+    str::FromStr,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{self, OFlag},
+    libc::{ioctl, FICLONE},
+    sys::stat::{fchmodat, FchmodatFlags, Mode as FileMode},
+    unistd::{linkat, unlinkat, LinkatFlags, UnlinkatFlags},
+};
+use thiserror::Error;
+
+/// How regular files are created from the asset cache
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Hardlink, falling back to a reflink then a copy when
+    /// the target filesystem doesn't support it
+    #[default]
+    Auto,
+    Hardlink,
+    Reflink,
+    Copy,
+}
+
+impl Mode {
+    /// The mode to fall back to when this one is unsupported
+    fn fallback(self) -> Option<Self> {
+        match self {
+            Mode::Auto | Mode::Hardlink => Some(Mode::Reflink),
+            Mode::Reflink => Some(Mode::Copy),
+            Mode::Copy => None,
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Mode::Auto),
+            "hardlink" => Ok(Mode::Hardlink),
+            "reflink" => Ok(Mode::Reflink),
+            "copy" => Ok(Mode::Copy),
+            _ => Err(ParseModeError(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Auto => "auto",
+            Mode::Hardlink => "hardlink",
+            Mode::Reflink => "reflink",
+            Mode::Copy => "copy",
+        }
+        .fmt(f)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown blit mode {0}, expected one of auto, hardlink, reflink or copy")]
+pub struct ParseModeError(String);
+
+/// Number of regular files created with each [`Mode`] during a blit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Used {
+    pub hardlink: u64,
+    pub reflink: u64,
+    pub copy: u64,
+}
+
+impl Used {
+    /// The modes used for at least one file
+    pub fn modes(&self) -> impl Iterator<Item = Mode> {
+        [
+            (Mode::Hardlink, self.hardlink),
+            (Mode::Reflink, self.reflink),
+            (Mode::Copy, self.copy),
+        ]
+        .into_iter()
+        .filter_map(|(mode, count)| (count > 0).then_some(mode))
+    }
+
+    fn record(&mut self, mode: Mode) {
+        match mode {
+            Mode::Auto | Mode::Hardlink => self.hardlink += 1,
+            Mode::Reflink => self.reflink += 1,
+            Mode::Copy => self.copy += 1,
+        }
+    }
+}
+
+impl fmt::Display for Used {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, mode) in self.modes().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{mode}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the modes regular files are created with during a blit
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Linker {
+    requested: Mode,
+    used: Used,
+}
+
+impl Linker {
+    pub fn new(requested: Mode) -> Self {
+        Self {
+            requested,
+            used: Used::default(),
+        }
+    }
+
+    /// The modes used for all files created so far
+    pub fn used(&self) -> Used {
+        self.used
+    }
+
+    /// Create `subpath` in `parent` from the asset at `source` within `cache`
+    pub fn create(
+        &mut self,
+        cache: RawFd,
+        source: &str,
+        parent: RawFd,
+        subpath: &str,
+        mode: u32,
+    ) -> Result<(), Error> {
+        self.attempt(|current| match current {
+            Mode::Auto | Mode::Hardlink => hardlink(cache, source, parent, subpath),
+            Mode::Reflink => reflink(cache, source, parent, subpath),
+            Mode::Copy => copy(cache, source, parent, subpath),
+        })?;
+
+        // Fix permissions
+        fchmodat(
+            Some(parent),
+            subpath,
+            FileMode::from_bits_truncate(mode),
+            FchmodatFlags::NoFollowSymlink,
+        )?;
+
+        Ok(())
+    }
+
+    /// Run `create` with the requested mode, falling back to costlier
+    /// modes when [`Mode::Auto`] was requested and the mode is unsupported
+    ///
+    /// Each file starts over from a hardlink, as a single file crossing
+    /// into another filesystem (i.e. a bind mount) says nothing of the rest.
+    fn attempt(&mut self, mut create: impl FnMut(Mode) -> Result<(), Error>) -> Result<(), Error> {
+        let mut current = match self.requested {
+            Mode::Auto => Mode::Hardlink,
+            mode => mode,
+        };
+
+        loop {
+            match create(current) {
+                Ok(()) => {
+                    self.used.record(current);
+                    return Ok(());
+                }
+                Err(error) if self.requested == Mode::Auto && error.is_unsupported(current) => {
+                    current = current.fallback().ok_or(error)?;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+fn hardlink(cache: RawFd, source: &str, parent: RawFd, subpath: &str) -> Result<(), Error> {
+    linkat(
+        Some(cache),
+        source,
+        Some(parent),
+        subpath,
+        LinkatFlags::NoSymlinkFollow,
+    )?;
+
+    Ok(())
+}
+
+fn reflink(cache: RawFd, source: &str, parent: RawFd, subpath: &str) -> Result<(), Error> {
+    let (source, target) = open_pair(cache, source, parent, subpath)?;
+
+    // SAFETY: Both fds are open & owned for the duration of the call
+    let result = unsafe { ioctl(target.as_raw_fd(), FICLONE, source.as_raw_fd()) };
+
+    if result < 0 {
+        let errno = Errno::last();
+        drop(target);
+        let _ = unlinkat(Some(parent), subpath, UnlinkatFlags::NoRemoveDir);
+        return Err(errno.into());
+    }
+
+    Ok(())
+}
+
+fn copy(cache: RawFd, source: &str, parent: RawFd, subpath: &str) -> Result<(), Error> {
+    let (mut source, mut target) = open_pair(cache, source, parent, subpath)?;
+
+    io::copy(&mut source, &mut target).map_err(Error::Copy)?;
+
+    Ok(())
+}
+
+/// Open the asset `source` for reading & create the new file `subpath` for writing
+fn open_pair(
+    cache: RawFd,
+    source: &str,
+    parent: RawFd,
+    subpath: &str,
+) -> Result<(File, File), Error> {
+    use std::os::fd::FromRawFd;
+
+    let source = fcntl::openat(
+        cache,
+        source,
+        OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+        FileMode::empty(),
+    )?;
+    // SAFETY: We own the newly opened fd
+    let source = unsafe { File::from_raw_fd(source) };
+
+    let target = fcntl::openat(
+        parent,
+        subpath,
+        OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_CLOEXEC,
+        FileMode::from_bits_truncate(0o644),
+    )?;
+    // SAFETY: We own the newly opened fd
+    let target = unsafe { File::from_raw_fd(target) };
+
+    Ok((source, target))
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("blit")]
+    Errno(#[from] Errno),
+    #[error("copy")]
+    Copy(#[source] io::Error),
+}
+
+impl Error {
+    /// Returns true if `mode` isn't supported between the source & target filesystems
+    fn is_unsupported(&self, mode: Mode) -> bool {
+        match self {
+            Error::Errno(Errno::EXDEV | Errno::EOPNOTSUPP) => true,
+            // FICLONE reports filesystems without reflink support as invalid or unknown
+            Error::Errno(Errno::EINVAL | Errno::ENOTTY) => mode == Mode::Reflink,
+            Error::Errno(_) | Error::Copy(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, os::fd::AsRawFd};
+
+    use super::*;
+
+    /// Attempt a single file, failing each mode with the mapped errno
+    fn attempt(linker: &mut Linker, errors: &[(Mode, Errno)]) -> (Vec<Mode>, Result<(), Error>) {
+        let mut tried = vec![];
+        let result = linker.attempt(|mode| {
+            tried.push(mode);
+            match errors.iter().find(|(failed, _)| *failed == mode) {
+                Some((_, errno)) => Err(Error::Errno(*errno)),
+                None => Ok(()),
+            }
+        });
+        (tried, result)
+    }
+
+    #[test]
+    fn test_fallback_chain() {
+        let mut linker = Linker::new(Mode::Auto);
+
+        let (tried, result) = attempt(
+            &mut linker,
+            &[
+                (Mode::Hardlink, Errno::EXDEV),
+                (Mode::Reflink, Errno::EOPNOTSUPP),
+            ],
+        );
+        assert!(result.is_ok());
+        assert_eq!(tried, [Mode::Hardlink, Mode::Reflink, Mode::Copy]);
+
+        let (tried, result) = attempt(
+            &mut linker,
+            &[
+                (Mode::Hardlink, Errno::EXDEV),
+                (Mode::Reflink, Errno::EINVAL),
+            ],
+        );
+        assert!(result.is_ok());
+        assert_eq!(tried, [Mode::Hardlink, Mode::Reflink, Mode::Copy]);
+
+        let (tried, result) = attempt(&mut linker, &[(Mode::Hardlink, Errno::EXDEV)]);
+        assert!(result.is_ok());
+        assert_eq!(tried, [Mode::Hardlink, Mode::Reflink]);
+
+        // A fallback only applies to the file it happened for
+        let (tried, result) = attempt(&mut linker, &[]);
+        assert!(result.is_ok());
+        assert_eq!(tried, [Mode::Hardlink]);
+
+        assert_eq!(
+            linker.used(),
+            Used {
+                hardlink: 1,
+                reflink: 1,
+                copy: 2
+            }
+        );
+        assert_eq!(linker.used().to_string(), "hardlink, reflink, copy");
+    }
+
+    #[test]
+    fn test_fallback_errors() {
+        let mut linker = Linker::new(Mode::Auto);
+
+        // Permission & link count errors are real failures
+        for errno in [Errno::EPERM, Errno::EMLINK, Errno::EINVAL, Errno::ENOTTY] {
+            let (tried, result) = attempt(&mut linker, &[(Mode::Hardlink, errno)]);
+            assert!(matches!(result, Err(Error::Errno(e)) if e == errno));
+            assert_eq!(tried, [Mode::Hardlink]);
+        }
+
+        let (tried, result) = attempt(
+            &mut linker,
+            &[
+                (Mode::Hardlink, Errno::EXDEV),
+                (Mode::Reflink, Errno::EPERM),
+            ],
+        );
+        assert!(matches!(result, Err(Error::Errno(Errno::EPERM))));
+        assert_eq!(tried, [Mode::Hardlink, Mode::Reflink]);
+
+        // Nothing left to fall back to
+        let (tried, result) = attempt(
+            &mut linker,
+            &[
+                (Mode::Hardlink, Errno::EXDEV),
+                (Mode::Reflink, Errno::EXDEV),
+                (Mode::Copy, Errno::EXDEV),
+            ],
+        );
+        assert!(matches!(result, Err(Error::Errno(Errno::EXDEV))));
+        assert_eq!(tried, [Mode::Hardlink, Mode::Reflink, Mode::Copy]);
+
+        assert_eq!(linker.used(), Used::default());
+        assert_eq!(linker.used().modes().count(), 0);
+    }
+
+    #[test]
+    fn test_requested_mode() {
+        // Explicit modes never fall back
+        for mode in [Mode::Hardlink, Mode::Reflink, Mode::Copy] {
+            let mut linker = Linker::new(mode);
+            let (tried, result) = attempt(&mut linker, &[(mode, Errno::EXDEV)]);
+            assert!(result.is_err());
+            assert_eq!(tried, [mode]);
+        }
+    }
+
+    #[test]
+    fn test_create() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("asset"), b"content").unwrap();
+
+        let fd = File::open(dir).unwrap();
+        let fd = fd.as_raw_fd();
+
+        for (mode, name) in [
+            (Mode::Auto, "auto"),
+            (Mode::Hardlink, "hardlink"),
+            (Mode::Copy, "copy"),
+        ] {
+            let mut linker = Linker::new(mode);
+            linker.create(fd, "asset", fd, name, 0o600).unwrap();
+            assert_eq!(fs::read(dir.join(name)).unwrap(), b"content");
+
+            let expected = match mode {
+                Mode::Copy => Mode::Copy,
+                _ => Mode::Hardlink,
+            };
+            assert_eq!(linker.used().modes().collect::<Vec<_>>(), [expected]);
+        }
+    }
+}
@@ -2,10 +2,13 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, path::PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 use futures::{stream, StreamExt};
-use stone::{payload, read::PayloadKind};
+use stone::read::PayloadKind;
 use thiserror::Error;
 use tokio::{
     fs::{self, File},
@@ -31,12 +34,21 @@ impl Progress {
 }
 
 /// Fetch a package with the provided [`package::Meta`] and [`Installation`] and return a [`Download`] on success.
+///
+/// The package is fetched from the first of `mirrors` which is available, falling back to
+/// the uri of `meta` if none are provided.
 pub async fn fetch(
     meta: &package::Meta,
+    mirrors: &[Url],
     installation: &Installation,
+    on_failover: impl Fn(&Url, &request::Error),
     on_progress: impl Fn(Progress),
 ) -> Result<Download, Error> {
-    let url = meta.uri.as_ref().ok_or(Error::MissingUri)?.parse::<Url>()?;
+    let urls = if mirrors.is_empty() {
+        vec![meta.uri.as_ref().ok_or(Error::MissingUri)?.parse::<Url>()?]
+    } else {
+        mirrors.to_vec()
+    };
     let hash = meta.hash.as_ref().ok_or(Error::MissingHash)?;
 
     let download_path = download_path(installation, hash).await?;
@@ -50,7 +62,7 @@ pub async fn fetch(
         });
     }
 
-    let mut bytes = request::get(url).await?;
+    let (_, mut bytes) = request::failover(&urls, request::get, on_failover).await?;
     let mut out = File::create(&download_path).await?;
 
     let mut total = 0;
@@ -93,6 +105,11 @@ pub struct UnpackedAsset {
 }
 
 impl Download {
+    /// Path of the downloaded stone
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     /// Unpack the downloaded package
     // TODO: Return an "Unpacked" struct which has a "blit" method on it?
     pub async fn unpack(
@@ -156,9 +173,15 @@ impl Download {
                 .filter_map(PayloadKind::index)
                 .flat_map(|p| &p.body)
                 .collect::<Vec<_>>();
+            let digests = indicies.iter().map(|idx| idx.digest).collect::<Vec<_>>();
+
+            // Nothing to unpack, i.e. a delta with no changed files
+            if indicies.is_empty() {
+                return Ok(UnpackedAsset { payloads });
+            }
 
             // If download was cached & all assets exist, we can skip unpacking
-            if self.was_cached && rt.block_on(check_assets_exist(&indicies, &self.installation)) {
+            if self.was_cached && rt.block_on(check_assets_exist(&digests, &self.installation)) {
                 return Ok(UnpackedAsset { payloads });
             }
 
@@ -171,6 +194,7 @@ impl Download {
                 .read(true)
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .open(&content_path)?;
 
             reader.unpack_content(
@@ -208,11 +232,11 @@ impl Download {
     }
 }
 
-/// Returns true if all assets already exist in the installation
-async fn check_assets_exist(indicies: &[&payload::Index], installation: &Installation) -> bool {
-    stream::iter(indicies)
-        .map(|index| async move {
-            if let Ok(path) = asset_path(installation, &format!("{:02x}", index.digest)).await {
+/// Returns true if all assets with the provided digests already exist in the installation
+pub(crate) async fn check_assets_exist(digests: &[u128], installation: &Installation) -> bool {
+    stream::iter(digests)
+        .map(|digest| async move {
+            if let Ok(path) = asset_path(installation, &format!("{digest:02x}")).await {
                 return fs::try_exists(path).await.unwrap_or_default();
             }
 
@@ -259,6 +283,22 @@ pub async fn asset_path(installation: &Installation, hash: &str) -> Result<PathB
     Ok(directory.join(hash))
 }
 
+/// Path of the cached asset for `digest`, without creating it's directory
+pub fn cached_asset(installation: &Installation, digest: u128) -> PathBuf {
+    let hash = format!("{digest:02x}");
+    let directory = if hash.len() >= 10 {
+        installation
+            .assets_path("v2")
+            .join(&hash[..2])
+            .join(&hash[2..4])
+            .join(&hash[4..6])
+    } else {
+        installation.assets_path("v2")
+    };
+
+    directory.join(hash)
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Missing download hash")]
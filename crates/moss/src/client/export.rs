@@ -0,0 +1,646 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Export the filesystem of a state as an archive, streamed from the
+//! layout db & cached assets instead of a blitted tree.
+//!
+//! Archives are deterministic: entries are sorted by path, every mtime is
+//! [`MTIME`] and ownership & modes are taken from the recorded layouts.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use stone::payload::layout;
+use tar::{EntryType, Header};
+use thiserror::Error;
+use tokio::task;
+use vfs::tree::{BlitFile, Tree};
+
+use super::{cache, os_release, PendingFile, ROOT_LINKS};
+use crate::{state, Installation};
+
+/// Modification time of every archive entry
+pub const MTIME: u64 = 0;
+
+/// Archive format of an export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Plain tarball of the root filesystem
+    Tar,
+    /// OCI image with the root filesystem as it's single layer
+    Oci,
+}
+
+/// Where an export is written
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// A single archive file
+    Archive(PathBuf),
+    /// An unpacked directory, only supported by [`Format::Oci`]
+    Directory(PathBuf),
+}
+
+/// Write `tree` for state `state_id` to `target` in the given [`Format`]
+///
+/// Regular file contents are read from the asset cache of `installation`
+pub(super) async fn export(
+    installation: &Installation,
+    tree: Tree<PendingFile>,
+    state_id: state::Id,
+    architecture: &str,
+    format: Format,
+    target: Target,
+) -> Result<(), Error> {
+    let entries = entries(installation, tree, state_id);
+    let architecture = architecture.to_string();
+
+    task::spawn_blocking(move || match (format, target) {
+        (Format::Tar, Target::Archive(path)) => {
+            write_archive(&path, |writer| write_layer(&entries, writer))
+        }
+        (Format::Tar, Target::Directory(path)) => Err(Error::DirectoryUnsupported(path)),
+        (Format::Oci, Target::Directory(path)) => {
+            create_empty_dir(&path)?;
+            write_oci_layout(&entries, state_id, &architecture, &path)
+        }
+        (Format::Oci, Target::Archive(path)) => {
+            let parent = parent_dir(&path);
+            let layout = tempfile::Builder::new()
+                .prefix(".moss-export-")
+                .tempdir_in(parent)
+                .map_err(|e| Error::Write(parent.to_path_buf(), e))?;
+
+            write_oci_layout(&entries, state_id, &architecture, layout.path())?;
+
+            write_archive(&path, |writer| archive_dir(layout.path(), writer))
+        }
+    })
+    .await
+    .expect("join handle")
+}
+
+/// Directory containing `path`, the current directory for bare file names
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// Write an archive to a temporary file next to `path`, renamed into
+/// place once complete so a failed export never leaves a partial archive
+fn write_archive(
+    path: &Path,
+    write: impl FnOnce(BufWriter<&mut File>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let parent = parent_dir(path);
+    let mut file = tempfile::Builder::new()
+        .prefix(".moss-export-")
+        .tempfile_in(parent)
+        .map_err(|e| Error::Write(parent.to_path_buf(), e))?;
+
+    write(BufWriter::new(file.as_file_mut()))?;
+
+    // Temporary files are private, archives are created like any other file
+    file.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o644))
+        .map_err(|e| Error::Write(path.to_path_buf(), e))?;
+    file.persist(path)
+        .map_err(|e| Error::Write(path.to_path_buf(), e.error))?;
+
+    Ok(())
+}
+
+/// A single archive entry
+#[derive(Debug)]
+struct Entry {
+    /// Path relative to the root
+    path: String,
+    kind: Kind,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Directory,
+    /// Contents read from a cached asset
+    Regular(PathBuf),
+    /// Inline contents
+    Data(Vec<u8>),
+    /// Hardlink to an earlier entry with identical contents
+    Hardlink(String),
+    Symlink(String),
+}
+
+/// Sorted archive entries for the `tree` of a state, including the
+/// files written alongside a blit
+fn entries(
+    installation: &Installation,
+    tree: Tree<PendingFile>,
+    state_id: state::Id,
+) -> Vec<Entry> {
+    let mut entries = vec![];
+    let mut has_usr_lib = false;
+
+    for file in tree.iter() {
+        let path = file.path();
+        let Ok(relative) = path.strip_prefix("/") else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().to_string();
+
+        if relative.is_empty() {
+            continue;
+        }
+
+        let kind = match &file.layout.entry {
+            layout::Entry::Regular(digest, _) => {
+                Kind::Regular(cache::cached_asset(installation, *digest))
+            }
+            layout::Entry::Symlink(source, _) => Kind::Symlink(source.clone()),
+            layout::Entry::Directory(_) => {
+                has_usr_lib |= relative == "usr/lib";
+                Kind::Directory
+            }
+            // Not produced by boulder & not supported by blitting either
+            layout::Entry::CharacterDevice(_)
+            | layout::Entry::BlockDevice(_)
+            | layout::Entry::Fifo(_)
+            | layout::Entry::Socket(_) => continue,
+        };
+
+        entries.push(Entry {
+            path: relative,
+            kind,
+            mode: file.layout.mode,
+            uid: file.layout.uid,
+            gid: file.layout.gid,
+        });
+    }
+
+    let file = |path: &str, contents: String| Entry {
+        path: path.to_string(),
+        kind: Kind::Data(contents.into_bytes()),
+        mode: 0o644,
+        uid: 0,
+        gid: 0,
+    };
+
+    entries.push(file("usr/.stateID", state_id.to_string()));
+    entries.push(file("usr/lib/os-release", os_release(Some(state_id))));
+    if !has_usr_lib {
+        entries.push(Entry {
+            path: "usr/lib".to_string(),
+            kind: Kind::Directory,
+            mode: 0o755,
+            uid: 0,
+            gid: 0,
+        });
+    }
+
+    for (source, target) in ROOT_LINKS {
+        entries.push(Entry {
+            path: target.to_string(),
+            kind: Kind::Symlink(source.to_string()),
+            mode: 0o777,
+            uid: 0,
+            gid: 0,
+        });
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // Blitted files of the same asset are hardlinked, do the same when
+    // the recorded metadata agrees
+    let mut first = HashMap::<(PathBuf, u32, u32, u32), String>::new();
+    for entry in &mut entries {
+        if let Kind::Regular(asset) = &entry.kind {
+            let key = (asset.clone(), entry.mode, entry.uid, entry.gid);
+
+            match first.get(&key) {
+                Some(path) => entry.kind = Kind::Hardlink(path.clone()),
+                None => {
+                    first.insert(key, entry.path.clone());
+                }
+            }
+        }
+    }
+
+    entries
+}
+
+/// Header with the deterministic fields set
+fn header(entry_type: EntryType, mode: u32, uid: u32, gid: u32, size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode & 0o7777);
+    header.set_uid(uid as u64);
+    header.set_gid(gid as u64);
+    header.set_mtime(MTIME);
+    header.set_size(size);
+    header
+}
+
+/// Write `entries` as a tarball to `writer`
+fn write_layer(entries: &[Entry], writer: impl Write) -> Result<(), Error> {
+    let mut builder = tar::Builder::new(writer);
+
+    for entry in entries {
+        let (mode, uid, gid) = (entry.mode, entry.uid, entry.gid);
+
+        match &entry.kind {
+            Kind::Directory => {
+                let mut header = header(EntryType::Directory, mode, uid, gid, 0);
+                builder.append_data(&mut header, format!("{}/", entry.path), io::empty())?;
+            }
+            Kind::Regular(asset) => {
+                let file = File::open(asset).map_err(|e| Error::ReadAsset(asset.clone(), e))?;
+                let size = file
+                    .metadata()
+                    .map_err(|e| Error::ReadAsset(asset.clone(), e))?
+                    .len();
+                let mut header = header(EntryType::Regular, mode, uid, gid, size);
+                builder.append_data(&mut header, &entry.path, file)?;
+            }
+            Kind::Data(data) => {
+                let mut header = header(EntryType::Regular, mode, uid, gid, data.len() as u64);
+                builder.append_data(&mut header, &entry.path, data.as_slice())?;
+            }
+            Kind::Hardlink(target) => {
+                let mut header = header(EntryType::Link, mode, uid, gid, 0);
+                builder.append_link(&mut header, &entry.path, target)?;
+            }
+            Kind::Symlink(source) => {
+                let mut header = header(EntryType::Symlink, mode, uid, gid, 0);
+                builder.append_link(&mut header, &entry.path, source)?;
+            }
+        }
+    }
+
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+/// Tar the regular files & directories below `dir`, sorted with root ownership
+fn archive_dir(dir: &Path, writer: impl Write) -> Result<(), Error> {
+    fn walk(dir: &Path, relative: &Path, paths: &mut Vec<(PathBuf, bool)>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                paths.push((path.clone(), true));
+                walk(&entry.path(), &path, paths)?;
+            } else {
+                paths.push((path, false));
+            }
+        }
+        Ok(())
+    }
+
+    let mut paths = vec![];
+    walk(dir, Path::new(""), &mut paths).map_err(|e| Error::Write(dir.to_path_buf(), e))?;
+    paths.sort();
+
+    let mut builder = tar::Builder::new(writer);
+
+    for (path, is_dir) in paths {
+        if is_dir {
+            let mut header = header(EntryType::Directory, 0o755, 0, 0, 0);
+            builder.append_data(&mut header, path, io::empty())?;
+        } else {
+            let file = File::open(dir.join(&path))?;
+            let size = file.metadata()?.len();
+            let mut header = header(EntryType::Regular, 0o644, 0, 0, size);
+            builder.append_data(&mut header, path, file)?;
+        }
+    }
+
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+/// Create `path` as a directory, which may already exist if empty
+fn create_empty_dir(path: &Path) -> Result<(), Error> {
+    match fs::read_dir(path) {
+        Ok(mut contents) => match contents.next() {
+            Some(_) => Err(Error::NotEmpty(path.to_path_buf())),
+            None => Ok(()),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::create_dir_all(path).map_err(|e| Error::Write(path.to_path_buf(), e))
+        }
+        Err(e) => Err(Error::Write(path.to_path_buf(), e)),
+    }
+}
+
+/// Media types of the OCI image spec
+mod media_type {
+    pub const INDEX: &str = "application/vnd.oci.image.index.v1+json";
+    pub const MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+    pub const CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+    pub const LAYER: &str = "application/vnd.oci.image.layer.v1.tar";
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: &'static str,
+    digest: String,
+    size: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageIndex {
+    schema_version: u32,
+    media_type: &'static str,
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageManifest {
+    schema_version: u32,
+    media_type: &'static str,
+    config: Descriptor,
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImageConfig {
+    architecture: String,
+    os: &'static str,
+    config: BTreeMap<String, String>,
+    rootfs: RootFs,
+}
+
+#[derive(Debug, Serialize)]
+struct RootFs {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    diff_ids: Vec<String>,
+}
+
+/// Write an OCI image layout with a single layer of `entries` to `dir`
+fn write_oci_layout(
+    entries: &[Entry],
+    state_id: state::Id,
+    architecture: &str,
+    dir: &Path,
+) -> Result<(), Error> {
+    let blobs = dir.join("blobs").join("sha256");
+    fs::create_dir_all(&blobs).map_err(|e| Error::Write(blobs.clone(), e))?;
+
+    // Stream the layer to disk, then move it to it's digest
+    let layer_path = blobs.join("layer.partial");
+    let layer = {
+        let file = File::create(&layer_path).map_err(|e| Error::Write(layer_path.clone(), e))?;
+        let mut writer = HashWriter::new(BufWriter::new(file));
+        write_layer(entries, &mut writer)?;
+        writer.descriptor(media_type::LAYER)
+    };
+    fs::rename(&layer_path, blobs.join(&layer.digest[7..]))
+        .map_err(|e| Error::Write(layer_path, e))?;
+
+    let config = ImageConfig {
+        architecture: oci_architecture(architecture).to_string(),
+        os: "linux",
+        config: BTreeMap::new(),
+        rootfs: RootFs {
+            kind: "layers",
+            diff_ids: vec![layer.digest.clone()],
+        },
+    };
+    let config = write_blob(&blobs, media_type::CONFIG, &config)?;
+
+    let manifest = ImageManifest {
+        schema_version: 2,
+        media_type: media_type::MANIFEST,
+        config,
+        layers: vec![layer],
+    };
+    let mut manifest = write_blob(&blobs, media_type::MANIFEST, &manifest)?;
+    manifest.annotations.insert(
+        "org.opencontainers.image.ref.name",
+        format!("state-{state_id}"),
+    );
+
+    let index = ImageIndex {
+        schema_version: 2,
+        media_type: media_type::INDEX,
+        manifests: vec![manifest],
+    };
+
+    write_json(&dir.join("index.json"), &index)?;
+    write_json(
+        &dir.join("oci-layout"),
+        &BTreeMap::from([("imageLayoutVersion", "1.0.0")]),
+    )?;
+
+    Ok(())
+}
+
+/// Write `value` as json to a blob named by it's digest
+fn write_blob(
+    blobs: &Path,
+    media_type: &'static str,
+    value: &impl Serialize,
+) -> Result<Descriptor, Error> {
+    let bytes = serde_json::to_vec(value)?;
+    let digest = hex::encode(Sha256::digest(&bytes));
+    let path = blobs.join(&digest);
+
+    fs::write(&path, &bytes).map_err(|e| Error::Write(path, e))?;
+
+    Ok(Descriptor {
+        media_type,
+        digest: format!("sha256:{digest}"),
+        size: bytes.len() as u64,
+        annotations: BTreeMap::new(),
+    })
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<(), Error> {
+    let bytes = serde_json::to_vec(value)?;
+    fs::write(path, bytes).map_err(|e| Error::Write(path.to_path_buf(), e))
+}
+
+/// OCI (GOARCH) name of a package architecture
+fn oci_architecture(architecture: &str) -> &str {
+    match architecture {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        other => other,
+    }
+}
+
+/// Hashes all bytes written to the inner writer
+struct HashWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> HashWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    fn descriptor(self, media_type: &'static str) -> Descriptor {
+        Descriptor {
+            media_type,
+            digest: format!("sha256:{}", hex::encode(self.hasher.finalize())),
+            size: self.size,
+            annotations: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("tar exports can't be written to a directory: {0}")]
+    DirectoryUnsupported(PathBuf),
+
+    #[error("output directory isn't empty: {0}")]
+    NotEmpty(PathBuf),
+
+    #[error("read cached asset {0}")]
+    ReadAsset(PathBuf, #[source] io::Error),
+
+    #[error("write {0}")]
+    Write(PathBuf, #[source] io::Error),
+
+    #[error("json")]
+    Json(#[from] serde_json::Error),
+
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        client::Client,
+        registry::{plugin, Registry},
+    };
+
+    #[tokio::test]
+    async fn test_deterministic() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let package = plugin::test::stone_package();
+        let mut client = Client::new("test", root).await.unwrap();
+        client.registry = Registry::default();
+        client
+            .registry
+            .add_plugin(Box::new(plugin::Test::new(1, vec![package.clone()])));
+
+        let plan = client.plan_install(&["bash-completion"]).await.unwrap();
+        client.execute(&plan).await.unwrap();
+        let state = client.state_db.list_ids().await.unwrap()[0].0;
+
+        let first = root.join("first.tar");
+        let second = root.join("second.tar");
+        for path in [&first, &second] {
+            client
+                .export(state, Format::Tar, Target::Archive(path.clone()))
+                .await
+                .unwrap();
+        }
+
+        let bytes = fs::read(&first).unwrap();
+        assert_eq!(bytes, fs::read(&second).unwrap());
+
+        // Only the archives, no temporary files left behind
+        assert_eq!(
+            fs::read_dir(root)
+                .unwrap()
+                .filter(|e| e.as_ref().unwrap().path().is_file())
+                .count(),
+            2
+        );
+
+        let layouts = client
+            .layout_db
+            .query(&package.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|layout| {
+                let target = match &layout.entry {
+                    layout::Entry::Regular(_, target)
+                    | layout::Entry::Symlink(_, target)
+                    | layout::Entry::Directory(target) => target.clone(),
+                    _ => return None,
+                };
+                Some((format!("usr/{}", target.trim_matches('/')), layout))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let mut paths = vec![];
+        let mut checked = 0;
+
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            let header = entry.header();
+            let path = entry
+                .path()
+                .unwrap()
+                .to_string_lossy()
+                .trim_end_matches('/')
+                .to_string();
+
+            assert_eq!(header.mtime().unwrap(), MTIME);
+
+            if let Some(layout) = layouts.get(&path) {
+                checked += 1;
+                assert_eq!(header.mode().unwrap(), layout.mode & 0o7777);
+                assert_eq!(header.uid().unwrap(), layout.uid as u64);
+                assert_eq!(header.gid().unwrap(), layout.gid as u64);
+            }
+
+            paths.push(path);
+        }
+
+        let mut sorted = paths.clone();
+        sorted.sort();
+        assert_eq!(paths, sorted);
+        assert!(paths.contains(&"usr/.stateID".to_string()));
+        assert!(checked > 0);
+    }
+}
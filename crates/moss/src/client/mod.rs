@@ -3,10 +3,12 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
+    collections::{HashMap, HashSet},
     io,
     os::fd::RawFd,
     path::{Path, PathBuf},
-    time::Duration,
+    sync::Arc,
+    time::Instant,
 };
 
 use futures::{future::try_join_all, stream, StreamExt, TryStreamExt};
@@ -15,27 +17,31 @@ use nix::{
     errno::Errno,
     fcntl::{self, OFlag},
     libc::{syscall, SYS_renameat2, AT_FDCWD, RENAME_EXCHANGE},
-    sys::stat::{fchmodat, mkdirat, Mode},
-    unistd::{close, linkat, mkdir, symlinkat},
+    sys::stat::{mkdirat, Mode},
+    unistd::{close, mkdir, symlinkat},
 };
 use stone::{payload::layout, read::PayloadKind};
 use thiserror::Error;
 use tokio::fs::{self, create_dir_all, remove_dir_all, remove_file, rename, symlink};
-use tui::{MultiProgress, ProgressBar, ProgressStyle, Stylize};
-use vfs::tree::{builder::TreeBuilder, BlitFile, Element};
+use url::Url;
+use vfs::tree::{builder::TreeBuilder, BlitFile, Conflict, Element, Policy};
 
-use self::install::install;
+use self::plan::Plan;
+use self::progress::{Event, Reporter};
 use self::prune::prune;
 use crate::{
-    db, environment, package,
-    registry::plugin::{self, Plugin},
+    architecture, db, environment, package,
+    registry::{self, plugin},
     repository,
-    state::{self, Selection},
-    Installation, Package, Registry, State,
+    state::{self, Provenance, Selection},
+    Architectures, Installation, Package, Registry, Repository, State,
 };
 
+pub mod blit;
 pub mod cache;
-pub mod install;
+pub mod export;
+pub mod plan;
+pub mod progress;
 pub mod prune;
 
 /// A Client is a connection to the underlying package management systems
@@ -50,8 +56,12 @@ pub struct Client {
     pub layout_db: db::layout::Database,
 
     config: config::Manager,
+    architectures: Architectures,
     repositories: repository::Manager,
     scope: Scope,
+    reporter: Arc<dyn Reporter>,
+    blit_mode: blit::Mode,
+    conflict_policy: Policy,
 }
 
 impl Client {
@@ -68,6 +78,12 @@ impl Client {
 
         let config = config::Manager::system(&root, "moss");
         let installation = Installation::open(root);
+        let architectures = Architectures::from_config(
+            config
+                .load::<architecture::Config>()
+                .await
+                .unwrap_or_default(),
+        );
         let repositories =
             repository::Manager::system(config.clone(), installation.clone()).await?;
         let install_db =
@@ -76,11 +92,19 @@ impl Client {
         let state_db = db::state::Database::new(&installation).await?;
         let layout_db = db::layout::Database::new(&installation).await?;
 
-        let registry = build_registry(&installation, &repositories, &install_db, &state_db).await?;
+        let registry = build_registry(
+            &installation,
+            &architectures,
+            &repositories,
+            &install_db,
+            &state_db,
+        )
+        .await?;
 
         Ok(Client {
             name: client_name.to_string(),
             config,
+            architectures,
             installation,
             repositories,
             registry,
@@ -88,6 +112,9 @@ impl Client {
             state_db,
             layout_db,
             scope: Scope::Stateful,
+            reporter: Arc::new(progress::Tui::new()),
+            blit_mode: blit::Mode::default(),
+            conflict_policy: Policy::default(),
         })
     }
 
@@ -95,8 +122,36 @@ impl Client {
         matches!(self.scope, Scope::Ephemeral { .. })
     }
 
-    pub async fn install(&mut self, packages: &[&str], yes: bool) -> Result<(), install::Error> {
-        install(self, packages, yes).await
+    /// Plan the installation of the provided package names / providers
+    pub async fn plan_install(&self, packages: &[&str]) -> Result<Plan, plan::Error> {
+        plan::install(self, packages).await
+    }
+
+    /// Plan the removal of the provided package names / providers
+    pub async fn plan_remove(&self, packages: &[&str]) -> Result<Plan, plan::Error> {
+        plan::remove(self, packages).await
+    }
+
+    /// Plan a sync of selections with candidates from the highest
+    /// priority repository, as filtered by the [`plan::SyncOptions`]
+    pub async fn plan_sync(&self, options: &plan::SyncOptions) -> Result<Plan, plan::Error> {
+        plan::sync(self, options).await
+    }
+
+    /// Cache all packages required by the [`Plan`] and apply its selections
+    /// as a new state
+    ///
+    /// Returns `None` if the client is ephemeral
+    pub async fn execute(&self, plan: &Plan) -> Result<Option<State>, Error> {
+        let started = Instant::now();
+        let to_cache = plan.to_cache();
+
+        if !to_cache.is_empty() {
+            self.cache_packages(&to_cache).await?;
+        }
+
+        self.new_state(&plan.selections, plan.kind, plan.description(), started)
+            .await
     }
 
     /// Transition to an ephemeral client that doesn't record state changes
@@ -120,6 +175,34 @@ impl Client {
         })
     }
 
+    /// Transition to a client that emits progress [`Event`]s to the provided
+    /// [`Reporter`] instead of rendering them to the terminal
+    pub fn with_reporter(mut self, reporter: impl Reporter + 'static) -> Self {
+        let reporter: Arc<dyn Reporter> = Arc::new(reporter);
+        self.repositories.set_reporter(reporter.clone());
+
+        Self { reporter, ..self }
+    }
+
+    /// Returns the configured [`Repository`] for `id`, if known
+    pub fn repository(&self, id: &repository::Id) -> Option<&Repository> {
+        self.repositories.get(id)
+    }
+
+    /// Transition to a client that creates regular files with the provided [`blit::Mode`]
+    pub fn with_blit_mode(self, blit_mode: blit::Mode) -> Self {
+        Self { blit_mode, ..self }
+    }
+
+    /// Transition to a client that resolves files provided by more than one package
+    /// with `policy`, where [`Policy::Prefer`] priorities are keyed by package name
+    pub fn with_conflict_policy(self, conflict_policy: Policy) -> Self {
+        Self {
+            conflict_policy,
+            ..self
+        }
+    }
+
     /// Transition the client to use the provided explicit repositories, instead of loading
     /// repository configuration from moss config folders
     pub async fn explicit_repositories(
@@ -133,6 +216,7 @@ impl Client {
         // Rebuild registry
         self.registry = build_registry(
             &self.installation,
+            &self.architectures,
             &self.repositories,
             &self.install_db,
             &self.state_db,
@@ -156,6 +240,7 @@ impl Client {
         // Rebuild registry
         self.registry = build_registry(
             &self.installation,
+            &self.architectures,
             &self.repositories,
             &self.install_db,
             &self.state_db,
@@ -182,6 +267,38 @@ impl Client {
         Ok(())
     }
 
+    /// Export the filesystem of a state as a deterministic archive, read
+    /// from the layout db & asset cache without blitting it
+    pub async fn export(
+        &self,
+        state_id: state::Id,
+        format: export::Format,
+        target: export::Target,
+    ) -> Result<(), Error> {
+        let state = self.state_db.get(&state_id).await?;
+        let tree = self
+            .build_tree(state.selections.iter().map(|s| &s.package))
+            .await?;
+
+        let architecture = self
+            .registry
+            .architectures()
+            .map(|architectures| architectures.native().to_string())
+            .unwrap_or_else(|| Architectures::host().native().to_string());
+
+        export::export(
+            &self.installation,
+            tree,
+            state_id,
+            &architecture,
+            format,
+            target,
+        )
+        .await?;
+
+        Ok(())
+    }
+
     /// Resolves the provided id's with the underlying registry, returning
     /// the first [`Package`] for each id. Packages are sorted by name
     /// and deduped before returning.
@@ -193,8 +310,8 @@ impl Client {
             self.registry
                 .by_id(id)
                 .boxed()
-                .next()
-                .await
+                .try_next()
+                .await?
                 .ok_or(Error::MissingMetadata(id.clone()))
         }))
         .await?;
@@ -207,26 +324,56 @@ impl Client {
     /// provided packages and write that state ID to the installation
     /// Then blit the filesystem, promote it, finally archiving the active ID
     ///
+    /// The time since `started` is recorded as the duration of the state
+    ///
     /// Returns `None` if the client is ephemeral
     pub async fn apply_state(
         &self,
         selections: &[Selection],
         summary: impl ToString,
+        started: Instant,
+    ) -> Result<Option<State>, Error> {
+        self.new_state(selections, summary, None, started).await
+    }
+
+    /// Apply `selections` as a new state, recording it's provenance
+    /// as taking the time since `started`
+    async fn new_state(
+        &self,
+        selections: &[Selection],
+        summary: impl ToString,
+        description: Option<String>,
+        started: Instant,
     ) -> Result<Option<State>, Error> {
         let old_state = self.installation.active_state;
 
-        self.blit_root(
-            selections.iter().map(|s| &s.package),
-            old_state.map(state::Id::next),
-        )
-        .await?;
+        let conflicts = self
+            .blit_root(
+                selections.iter().map(|s| &s.package),
+                old_state.map(state::Id::next),
+            )
+            .await?;
+
+        if !conflicts.is_empty() {
+            self.reporter.report(Event::FileConflicts {
+                conflicts: &conflicts,
+            });
+        }
 
         match &self.scope {
             Scope::Stateful => {
+                let provenance =
+                    Provenance::current(self.repositories.index_hashes().await?, started.elapsed());
+
                 // Add to db
                 let state = self
                     .state_db
-                    .add(selections, Some(summary.to_string()), None)
+                    .add(
+                        selections,
+                        Some(summary.to_string()),
+                        description,
+                        Some(&provenance),
+                    )
                     .await?;
 
                 // Write state id
@@ -249,11 +396,18 @@ impl Client {
                     self.archive_state(id).await?;
                 }
 
+                self.reporter.report(Event::StateApplied {
+                    state: Some(&state),
+                });
+
                 Ok(Some(state))
             }
             Scope::Ephemeral { blit_root } => {
                 record_os_release(blit_root, None).await?;
                 create_root_links(blit_root).await?;
+
+                self.reporter.report(Event::StateApplied { state: None });
+
                 Ok(None)
             }
         }
@@ -322,87 +476,32 @@ impl Client {
 
     /// Download & unpack the provided packages. Packages already cached will be validated & skipped.
     pub async fn cache_packages(&self, packages: &[&Package]) -> Result<(), Error> {
-        // Setup progress bar
-        let multi_progress = MultiProgress::new();
-
-        // Add bar to track total package counts
-        let total_progress = multi_progress.add(
-            ProgressBar::new(packages.len() as u64).with_style(
-                ProgressStyle::with_template("\n|{bar:20.cyan/blue}| {pos}/{len}")
-                    .unwrap()
-                    .progress_chars("■≡=- "),
-            ),
-        );
-        total_progress.tick();
+        self.reporter.report(Event::CacheStarted {
+            total: packages.len(),
+        });
 
         // Download and unpack each package
         stream::iter(packages.iter().map(|package| async {
-            // Setup the progress bar and set as downloading
-            let progress_bar = multi_progress.insert_before(
-                &total_progress,
-                ProgressBar::new(package.meta.download_size.unwrap_or_default())
-                    .with_message(format!(
-                        "{} {}",
-                        "Downloading".blue(),
-                        package.meta.name.to_string().bold(),
-                    ))
-                    .with_style(
-                        ProgressStyle::with_template(
-                            " {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ",
-                        )
-                        .unwrap()
-                        .tick_chars("--=≡■≡=--"),
-                    ),
-            );
-            progress_bar.enable_steady_tick(Duration::from_millis(150));
-
-            // Download and update progress
-            let download = cache::fetch(&package.meta, &self.installation, |progress| {
-                progress_bar.inc(progress.delta);
-            })
-            .await?;
+            self.reporter.report(Event::DownloadStarted { package });
 
-            let is_cached = download.was_cached;
-            let package_name = package.meta.name.to_string();
-
-            // Set progress to unpacking
-            progress_bar.set_message(format!(
-                "{} {}",
-                "Unpacking".yellow(),
-                package_name.clone().bold(),
-            ));
-            progress_bar.set_length(1000);
-            progress_bar.set_position(0);
-
-            // Unpack and update progress
-            let unpacked = download
-                .unpack({
-                    let progress_bar = progress_bar.clone();
-
-                    move |progress| {
-                        progress_bar.set_position((progress.pct() * 1000.0) as u64);
-                    }
-                })
-                .await?;
+            let (unpacked, is_cached) = match self.fetch_delta(package).await? {
+                Some(result) => result,
+                None => self.fetch_package(package).await?,
+            };
 
             // Merge layoutdb
-            progress_bar.set_message(format!(
-                "{} {}",
-                "Store layout".white(),
-                package_name.clone().bold()
-            ));
+            self.reporter.report(Event::LayoutStore { package });
             // Remove old layout entries for package
             self.layout_db.remove(&package.id).await?;
             // Add new entries in batches of 1k
-            for chunk in progress_bar.wrap_iter(
-                unpacked
-                    .payloads
-                    .iter()
-                    .find_map(PayloadKind::layout)
-                    .map(|p| &p.body)
-                    .ok_or(Error::CorruptedPackage)?
-                    .chunks(environment::DB_BATCH_SIZE),
-            ) {
+            for chunk in unpacked
+                .payloads
+                .iter()
+                .find_map(PayloadKind::layout)
+                .map(|p| &p.body)
+                .ok_or(Error::CorruptedPackage)?
+                .chunks(environment::DB_BATCH_SIZE)
+            {
                 let entries = chunk
                     .iter()
                     .map(|i| (package.id.clone(), i.clone()))
@@ -410,72 +509,411 @@ impl Client {
                 self.layout_db.batch_add(entries).await?;
             }
 
-            // Consume the package in the metadb
+            // Consume the package in the metadb, with it's unpacked size
+            let installed_size = self.unpacked_size(&unpacked.payloads).await?;
             self.install_db
-                .add(package.id.clone(), package.meta.clone())
+                .add(
+                    package.id.clone(),
+                    package::Meta {
+                        installed_size: Some(installed_size),
+                        ..package.meta.clone()
+                    },
+                )
                 .await?;
 
-            // Remove this progress bar
-            progress_bar.finish();
-            multi_progress.remove(&progress_bar);
-
-            let cached_tag = is_cached
-                .then_some(format!("{}", " (cached)".dim()))
-                .unwrap_or_default();
-
-            // Write installed line
-            multi_progress.println(format!(
-                "{} {}{}",
-                "Installed".green(),
-                package_name.clone().bold(),
-                cached_tag,
-            ))?;
-
-            // Inc total progress by 1
-            total_progress.inc(1);
+            self.reporter.report(Event::PackageCached {
+                package,
+                was_cached: is_cached,
+            });
 
             Ok(()) as Result<(), Error>
         }))
         // Use max network concurrency since we download files here
         .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
-        .try_collect()
+        .try_collect::<()>()
         .await?;
 
-        // Remove progress
-        multi_progress.clear()?;
+        self.reporter.report(Event::CacheFinished);
 
         Ok(())
     }
 
-    /// Blit the packages to a filesystem root
-    async fn blit_root(
+    /// Returns the installed size of `package`, if it's cached
+    ///
+    /// Packages cached before sizes were recorded are measured in the asset cache
+    pub async fn installed_size(&self, package: &Package) -> Result<Option<u64>, Error> {
+        if let Some(size) = package.meta.installed_size {
+            return Ok(Some(size));
+        }
+
+        let layouts = self.layout_db.query(&package.id).await?;
+
+        if layouts.is_empty() {
+            return Ok(None);
+        }
+
+        let sizes = self
+            .asset_sizes(regular_digests(&layouts).into_iter().unique())
+            .await?;
+
+        Ok(Some(sizes.values().sum()))
+    }
+
+    /// Returns the disk usage of the cached `packages`
+    pub async fn footprint(
         &self,
         packages: impl IntoIterator<Item = &package::Id>,
-        state_id: Option<state::Id>,
-    ) -> Result<(), Error> {
-        let progress = ProgressBar::new(1).with_style(
-            ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {msg}")
-                .unwrap()
-                .progress_chars("■≡=- "),
-        );
-        progress.set_message("Blitting filesystem");
-        progress.enable_steady_tick(Duration::from_millis(150));
-        progress.tick();
+    ) -> Result<Footprint, Error> {
+        let digests = stream::iter(packages)
+            .then(|id| async move {
+                let layouts = self.layout_db.query(id).await?;
+                Ok::<_, Error>(
+                    regular_digests(&layouts)
+                        .into_iter()
+                        .collect::<HashSet<_>>(),
+                )
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let sizes = self
+            .asset_sizes(digests.iter().flatten().copied().unique())
+            .await?;
+
+        Ok(Footprint {
+            total: digests
+                .iter()
+                .flatten()
+                .filter_map(|digest| sizes.get(digest))
+                .sum(),
+            unique: sizes.values().sum(),
+        })
+    }
+
+    /// Size of the unpacked assets of a package, from the ranges of it's index
+    /// payload. Assets a delta doesn't carry are measured in the asset cache.
+    async fn unpacked_size(&self, payloads: &[PayloadKind]) -> Result<u64, Error> {
+        let indexed = payloads
+            .iter()
+            .filter_map(PayloadKind::index)
+            .flat_map(|p| &p.body)
+            .map(|index| (index.digest, index.end - index.start))
+            .collect::<HashMap<_, _>>();
+        let digests = regular_digests(
+            payloads
+                .iter()
+                .filter_map(PayloadKind::layout)
+                .flat_map(|p| &p.body),
+        )
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+        let cached = self
+            .asset_sizes(
+                digests
+                    .iter()
+                    .filter(|digest| !indexed.contains_key(digest))
+                    .copied(),
+            )
+            .await?;
+
+        Ok(digests
+            .iter()
+            .filter_map(|digest| indexed.get(digest).or_else(|| cached.get(digest)))
+            .sum())
+    }
+
+    /// Size of each of the cached assets, skipping those which aren't cached
+    async fn asset_sizes(
+        &self,
+        digests: impl IntoIterator<Item = u128>,
+    ) -> Result<HashMap<u128, u64>, Error> {
+        stream::iter(digests)
+            .map(|digest| async move {
+                match fs::metadata(cache::cached_asset(&self.installation, digest)).await {
+                    Ok(metadata) => Ok(Some((digest, metadata.len()))),
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                    Err(error) => Err(Error::Io(error)),
+                }
+            })
+            .buffer_unordered(environment::MAX_DISK_CONCURRENCY)
+            .try_filter_map(|size| async move { Ok(size) })
+            .try_collect()
+            .await
+    }
+
+    /// Returns the layout of `package`, reading it from the stone
+    /// when the package isn't cached
+    pub async fn layouts(&self, package: &Package) -> Result<Vec<layout::Layout>, Error> {
+        let layouts = self.layout_db.query(&package.id).await?;
+
+        if !layouts.is_empty() {
+            return Ok(layouts);
+        }
 
-        let mut tbuild = TreeBuilder::new();
+        // A delta holds the full layout of it's target, so the download is reused
+        // when the package is cached instead of fetching the whole stone
+        for (meta, urls) in self.cached_deltas(package).await? {
+            match self.read_layouts(&meta, &urls).await {
+                Ok(layouts) => return Ok(layouts),
+                Err(error) => self.reporter.report(Event::DeltaFailed {
+                    package,
+                    error: &error,
+                }),
+            }
+        }
+
+        let mirrors = self.repositories.package_urls(package).await?;
+
+        self.read_layouts(&package.meta, &mirrors).await
+    }
+
+    /// Fetch the stone described by `meta` & read it's layout
+    async fn read_layouts(
+        &self,
+        meta: &package::Meta,
+        mirrors: &[Url],
+    ) -> Result<Vec<layout::Layout>, Error> {
+        let download = cache::fetch(
+            meta,
+            mirrors,
+            &self.installation,
+            |url, error| self.reporter.report(Event::MirrorFailed { url, error }),
+            |_| {},
+        )
+        .await?;
+
+        let (_, payloads) = crate::stone::stream_payloads(download.path()).await?;
+
+        let payloads = payloads.try_collect::<Vec<_>>().await?;
+
+        Ok(payloads
+            .iter()
+            .filter_map(PayloadKind::layout)
+            .flat_map(|p| p.body.iter().cloned())
+            .collect())
+    }
+
+    /// Fetch & unpack the full stone of `package`
+    async fn fetch_package(
+        &self,
+        package: &Package,
+    ) -> Result<(cache::UnpackedAsset, bool), Error> {
+        let mirrors = self.repositories.package_urls(package).await?;
+
+        // Download and update progress
+        let download = cache::fetch(
+            &package.meta,
+            &mirrors,
+            &self.installation,
+            |url, error| self.reporter.report(Event::MirrorFailed { url, error }),
+            |progress| {
+                self.reporter
+                    .report(Event::DownloadProgress { package, progress });
+            },
+        )
+        .await?;
+
+        let is_cached = download.was_cached;
+
+        self.reporter.report(Event::DownloadFinished {
+            package,
+            was_cached: is_cached,
+        });
+
+        Ok((self.unpack(package, download).await?, is_cached))
+    }
+
+    /// Fetch & unpack a delta of `package` from a release whose assets are already
+    /// cached, so only changed files are downloaded
+    ///
+    /// Returns `None` if no delta applies or it failed, so the full stone is used instead
+    async fn fetch_delta(
+        &self,
+        package: &Package,
+    ) -> Result<Option<(cache::UnpackedAsset, bool)>, Error> {
+        for (meta, urls) in self.cached_deltas(package).await? {
+            let result = async {
+                let download = cache::fetch(
+                    &meta,
+                    &urls,
+                    &self.installation,
+                    |url, error| self.reporter.report(Event::MirrorFailed { url, error }),
+                    |progress| {
+                        self.reporter
+                            .report(Event::DownloadProgress { package, progress });
+                    },
+                )
+                .await?;
+
+                let is_cached = download.was_cached;
+                let unpacked = self.unpack(package, download).await?;
+
+                // The base must provide every file the delta doesn't
+                let digests = regular_digests(
+                    unpacked
+                        .payloads
+                        .iter()
+                        .filter_map(PayloadKind::layout)
+                        .flat_map(|p| &p.body),
+                );
+                if !cache::check_assets_exist(&digests, &self.installation).await {
+                    return Err(Error::CorruptedPackage);
+                }
+
+                Ok::<_, Error>((unpacked, is_cached))
+            }
+            .await;
+
+            match result {
+                Ok((unpacked, is_cached)) => {
+                    self.reporter.report(Event::DownloadFinished {
+                        package,
+                        was_cached: is_cached,
+                    });
+                    return Ok(Some((unpacked, is_cached)));
+                }
+                // Don't reuse a bad download
+                Err(error) => {
+                    self.reporter.report(Event::DeltaFailed {
+                        package,
+                        error: &error,
+                    });
+
+                    if let Some(hash) = &meta.hash {
+                        if let Ok(path) = cache::download_path(&self.installation, hash).await {
+                            let _ = remove_file(path).await;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the meta to fetch each delta of `package` by along with it's
+    /// mirrors, for deltas whose base release is cached
+    async fn cached_deltas(
+        &self,
+        package: &Package,
+    ) -> Result<Vec<(package::Meta, Vec<Url>)>, Error> {
+        let mut deltas = vec![];
+
+        for (delta, urls) in self.repositories.package_deltas(package).await? {
+            if !self.is_cached(&package::Id::from(delta.base)).await? {
+                continue;
+            }
+
+            // Deltas are cached by their own hash
+            let meta = package::Meta {
+                uri: None,
+                hash: Some(delta.hash),
+                download_size: delta.download_size,
+                ..package.meta.clone()
+            };
+
+            deltas.push((meta, urls));
+        }
+
+        Ok(deltas)
+    }
+
+    /// Returns true if every file of the package `id` is in the asset cache
+    async fn is_cached(&self, id: &package::Id) -> Result<bool, Error> {
+        let layouts = self.layout_db.query(id).await?;
+
+        Ok(!layouts.is_empty()
+            && cache::check_assets_exist(&regular_digests(&layouts), &self.installation).await)
+    }
+
+    /// Unpack a `download` of `package` and update progress
+    async fn unpack(
+        &self,
+        package: &Package,
+        download: cache::Download,
+    ) -> Result<cache::UnpackedAsset, Error> {
+        Ok(download
+            .unpack({
+                let reporter = self.reporter.clone();
+                let package = package.clone();
+
+                move |progress| {
+                    reporter.report(Event::UnpackProgress {
+                        package: &package,
+                        progress,
+                    });
+                }
+            })
+            .await?)
+    }
+
+    /// Returns the file conflicts between `packages`, resolved by the
+    /// [`Policy`] or an error if it doesn't permit them
+    ///
+    /// Only layouts already in the layout db are checked, nothing is fetched.
+    /// Packages without one are returned alongside as unchecked.
+    pub async fn conflicts<'a>(
+        &self,
+        packages: &'a [Package],
+    ) -> Result<(Vec<Conflict>, Vec<&'a Package>), Error> {
+        let mut unchecked = vec![];
+        let mut tbuild = TreeBuilder::new().with_policy(self.conflict_policy.clone());
+        for package in packages {
+            let layouts = self.layout_db.query(&package.id).await?;
+            if layouts.is_empty() {
+                unchecked.push(package);
+            }
+            for layout in layouts {
+                tbuild.push(PendingFile {
+                    package: package.meta.name.to_string(),
+                    layout,
+                });
+            }
+        }
+        tbuild.bake();
+        Ok((tbuild.tree()?.conflicts().to_vec(), unchecked))
+    }
+
+    /// Build the filesystem tree of the cached `packages`
+    async fn build_tree(
+        &self,
+        packages: impl IntoIterator<Item = &package::Id>,
+    ) -> Result<vfs::tree::Tree<PendingFile>, Error> {
+        let mut tbuild = TreeBuilder::new().with_policy(self.conflict_policy.clone());
         for id in packages.into_iter() {
+            // Conflicts are reported by package name
+            let meta = self.install_db.get(id).await?;
             let layouts = self.layout_db.query(id).await?;
             for layout in layouts {
                 tbuild.push(PendingFile {
-                    id: id.clone(),
+                    package: meta.name.to_string(),
                     layout,
                 });
             }
         }
         tbuild.bake();
-        let tree = tbuild.tree()?;
-        progress.set_length(tree.len());
-        progress.set_position(0_u64);
+        Ok(tbuild.tree()?)
+    }
+
+    /// Blit the packages to a filesystem root
+    ///
+    /// Returns the file conflicts resolved by the [`Policy`]
+    async fn blit_root(
+        &self,
+        packages: impl IntoIterator<Item = &package::Id>,
+        state_id: Option<state::Id>,
+    ) -> Result<Vec<Conflict>, Error> {
+        let tree = self.build_tree(packages).await?;
+        let conflicts = tree.conflicts().to_vec();
+
+        let mut progress = BlitProgress {
+            completed: 0,
+            total: tree.len(),
+        };
+        let mut linker = blit::Linker::new(self.blit_mode);
+        self.reporter
+            .report(Event::BlitStarted { total: tree.len() });
 
         let cache_dir = self.installation.assets_path("v2");
         let cache_fd = fcntl::open(
@@ -502,14 +940,18 @@ impl Client {
 
             if let Element::Directory(_, _, children) = root {
                 for child in children {
-                    self.blit_element(root_dir, cache_fd, child, &progress)?;
+                    self.blit_element(root_dir, cache_fd, child, &mut linker, &mut progress)?;
                 }
             }
 
             close(root_dir)?;
         }
 
-        Ok(())
+        self.reporter.report(Event::BlitFinished {
+            used: linker.used(),
+        });
+
+        Ok(conflicts)
     }
 
     /// blit an element to the disk.
@@ -518,13 +960,18 @@ impl Client {
         parent: RawFd,
         cache: RawFd,
         element: Element<PendingFile>,
-        progress: &ProgressBar,
+        linker: &mut blit::Linker,
+        progress: &mut BlitProgress,
     ) -> Result<(), Error> {
-        progress.inc(1);
+        progress.completed += 1;
+        self.reporter.report(Event::BlitProgress {
+            completed: progress.completed,
+            total: progress.total,
+        });
         match element {
             Element::Directory(name, item, children) => {
                 // Construct within the parent
-                self.blit_element_item(parent, cache, &name, item)?;
+                self.blit_element_item(parent, cache, &name, item, linker)?;
 
                 // open the new dir
                 let newdir = fcntl::openat(
@@ -534,13 +981,13 @@ impl Client {
                     Mode::empty(),
                 )?;
                 for child in children.into_iter() {
-                    self.blit_element(newdir, cache, child, progress)?;
+                    self.blit_element(newdir, cache, child, linker, progress)?;
                 }
                 close(newdir)?;
                 Ok(())
             }
             Element::Child(name, item) => {
-                self.blit_element_item(parent, cache, &name, item)?;
+                self.blit_element_item(parent, cache, &name, item, linker)?;
                 Ok(())
             }
         }
@@ -553,6 +1000,7 @@ impl Client {
         cache: RawFd,
         subpath: &str,
         item: PendingFile,
+        linker: &mut blit::Linker,
     ) -> Result<(), Error> {
         match item.layout.entry {
             layout::Entry::Regular(id, _) => {
@@ -567,20 +1015,12 @@ impl Client {
 
                 // Link relative from cache to target
                 let fp = directory.join(hash);
-                linkat(
-                    Some(cache),
+                linker.create(
+                    cache,
                     fp.to_str().unwrap(),
-                    Some(parent),
-                    subpath,
-                    nix::unistd::LinkatFlags::NoSymlinkFollow,
-                )?;
-
-                // Fix permissions
-                fchmodat(
-                    Some(parent),
+                    parent,
                     subpath,
-                    Mode::from_bits_truncate(item.layout.mode),
-                    nix::sys::stat::FchmodatFlags::NoFollowSymlink,
+                    item.layout.mode,
                 )?;
             }
             layout::Entry::Symlink(source, _) => {
@@ -601,17 +1041,39 @@ impl Client {
     }
 }
 
-/// Add root symlinks & os-release file
+/// Digests of the regular files in `layouts`
+fn regular_digests<'a>(layouts: impl IntoIterator<Item = &'a layout::Layout>) -> Vec<u128> {
+    layouts
+        .into_iter()
+        .filter_map(|layout| match &layout.entry {
+            layout::Entry::Regular(digest, _) => Some(*digest),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Disk usage of a set of packages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Footprint {
+    /// Sum of the installed size of each package
+    pub total: u64,
+    /// Size of the unique assets, since files shared
+    /// between packages are hardlinked from the same asset
+    pub unique: u64,
+}
+
+/// Symlinks from the root into `/usr`, as (source, target)
+const ROOT_LINKS: [(&str, &str); 5] = [
+    ("usr/sbin", "sbin"),
+    ("usr/bin", "bin"),
+    ("usr/lib", "lib"),
+    ("usr/lib", "lib64"),
+    ("usr/lib32", "lib32"),
+];
+
+/// Add root symlinks
 async fn create_root_links(root: &Path) -> Result<(), Error> {
-    let links = vec![
-        ("usr/sbin", "sbin"),
-        ("usr/bin", "bin"),
-        ("usr/lib", "lib"),
-        ("usr/lib", "lib64"),
-        ("usr/lib32", "lib32"),
-    ];
-
-    'linker: for (source, target) in links.into_iter() {
+    'linker: for (source, target) in ROOT_LINKS {
         let final_target = root.join(target);
         let staging_target = root.join(format!("{target}.next"));
 
@@ -634,7 +1096,18 @@ async fn create_root_links(root: &Path) -> Result<(), Error> {
 
 /// Record the operating system release info
 async fn record_os_release(root: &Path, state_id: Option<state::Id>) -> Result<(), Error> {
-    let os_release = format!(
+    // Not every package set provides `/usr/lib`
+    let lib = root.join("usr").join("lib");
+    fs::create_dir_all(&lib).await?;
+
+    fs::write(lib.join("os-release"), os_release(state_id)).await?;
+
+    Ok(())
+}
+
+/// Operating system release info for `state_id`
+fn os_release(state_id: Option<state::Id>) -> String {
+    format!(
         r#"NAME="Serpent OS"
 VERSION="{version}"
 ID="serpentos"
@@ -647,11 +1120,7 @@ BUG_REPORT_URL="https://github.com/serpent-os""#,
         version = environment::VERSION,
         // TODO: Better id for ephemeral transactions
         tx = state_id.unwrap_or_default()
-    );
-
-    fs::write(root.join("usr").join("lib").join("os-release"), os_release).await?;
-
-    Ok(())
+    )
 }
 
 enum Scope {
@@ -665,10 +1134,17 @@ impl Scope {
     }
 }
 
+/// Tracks the number of blitted entries
+struct BlitProgress {
+    completed: u64,
+    total: u64,
+}
+
 /// A pending file for blitting
 #[derive(Debug, Clone)]
 struct PendingFile {
-    id: package::Id,
+    /// Name of the package providing the file
+    package: String,
     layout: layout::Layout,
 }
 
@@ -684,7 +1160,7 @@ impl BlitFile for PendingFile {
 
     /// Return ID for conflict
     fn id(&self) -> String {
-        self.id.clone().into()
+        self.package.clone()
     }
 
     /// Resolve the target path, including the missing `/usr` prefix
@@ -721,7 +1197,7 @@ impl BlitFile for PendingFile {
 impl From<PathBuf> for PendingFile {
     fn from(value: PathBuf) -> Self {
         PendingFile {
-            id: Default::default(),
+            package: Default::default(),
             layout: layout::Layout {
                 uid: 0,
                 gid: 0,
@@ -735,6 +1211,7 @@ impl From<PathBuf> for PendingFile {
 
 async fn build_registry(
     installation: &Installation,
+    architectures: &Architectures,
     repositories: &repository::Manager,
     installdb: &db::meta::Database,
     statedb: &db::state::Database,
@@ -744,16 +1221,13 @@ async fn build_registry(
         None => None,
     };
 
-    let mut registry = Registry::default();
+    let mut registry = Registry::default().with_architectures(architectures.clone());
 
-    registry.add_plugin(Plugin::Cobble(plugin::Cobble::default()));
-    registry.add_plugin(Plugin::Active(plugin::Active::new(
-        state,
-        installdb.clone(),
-    )));
+    registry.add_plugin(Box::new(plugin::Cobble::default()));
+    registry.add_plugin(Box::new(plugin::Active::new(state, installdb.clone())));
 
-    for repo in repositories.active() {
-        registry.add_plugin(Plugin::Repository(plugin::Repository::new(repo)));
+    for repo in repositories.active().filter(|repo| repo.repository.enabled) {
+        registry.add_plugin(Box::new(plugin::Repository::new(repo)));
     }
 
     Ok(registry)
@@ -777,16 +1251,24 @@ pub enum Error {
     Repository(#[from] repository::manager::Error),
     #[error("meta db")]
     Meta(#[from] db::meta::Error),
+    #[error("registry")]
+    Registry(#[from] registry::Error),
     #[error("layout db")]
     Layout(#[from] db::layout::Error),
     #[error("state db")]
     State(#[from] db::state::Error),
     #[error("prune")]
     Prune(#[from] prune::Error),
+    #[error("export")]
+    Export(#[from] export::Error),
     #[error("io")]
     Io(#[from] io::Error),
     #[error("filesystem")]
     Filesystem(#[from] vfs::tree::Error),
     #[error("blit")]
     Blit(#[from] Errno),
+    #[error("read stone")]
+    ReadStone(#[from] stone::read::Error),
+    #[error("blit file")]
+    BlitFile(#[from] blit::Error),
 }
@@ -0,0 +1,959 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Planning of changes to an installation
+//!
+//! A [`Plan`] describes every change an operation will make without
+//! touching the installation, so front-ends can present (or reject) it
+//! before passing it to [`Client::execute`].
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+use futures::{future::join_all, stream, StreamExt, TryStreamExt};
+use itertools::{Either, Itertools};
+use thiserror::Error;
+
+use crate::{
+    client::{self, Client},
+    environment,
+    package::{self, spec, Flags, Spec},
+    registry::{self, transaction},
+    state::Selection,
+    Architectures, Package, Provider,
+};
+use vfs::tree::Conflict;
+
+/// The operation a [`Plan`] was created for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Install,
+    Remove,
+    Sync,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Install => write!(f, "Install"),
+            Kind::Remove => write!(f, "Remove"),
+            Kind::Sync => write!(f, "Sync"),
+        }
+    }
+}
+
+/// An installed package replaced by a candidate of the same name
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    pub from: Package,
+    pub to: Package,
+}
+
+/// A set of changes to be applied to an installation
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub kind: Kind,
+    /// Packages which will be added
+    pub additions: Vec<Package>,
+    /// Packages which will be removed
+    pub removals: Vec<Package>,
+    /// Packages which will replace an installed package of the same name
+    pub upgrades: Vec<Upgrade>,
+    /// Requested packages which are already installed
+    pub unchanged: Vec<Package>,
+    /// Sum of the download size of all added & upgraded packages
+    pub download_size: u64,
+    /// Selections of the resulting state
+    pub selections: Vec<Selection>,
+    /// File conflicts between the packages of the resulting
+    /// state, as resolved by the client's conflict policy
+    pub conflicts: Vec<Conflict>,
+    /// Packages to be cached whose layout isn't known yet, so weren't
+    /// checked for conflicts. They're checked when the plan is executed.
+    pub unchecked: Vec<Package>,
+}
+
+impl Plan {
+    fn new(
+        kind: Kind,
+        additions: Vec<Package>,
+        removals: Vec<Package>,
+        upgrades: Vec<Upgrade>,
+        unchanged: Vec<Package>,
+        selections: Vec<Selection>,
+    ) -> Self {
+        let download_size = additions
+            .iter()
+            .chain(upgrades.iter().map(|u| &u.to))
+            .filter_map(|p| p.meta.download_size)
+            .sum();
+
+        Self {
+            kind,
+            additions,
+            removals,
+            upgrades,
+            unchanged,
+            download_size,
+            selections,
+            conflicts: vec![],
+            unchecked: vec![],
+        }
+    }
+
+    /// Check the resulting state for file conflicts before anything is cached,
+    /// failing if the client's conflict policy doesn't permit them
+    ///
+    /// Only packages with a known layout are checked, see [`Plan::unchecked`]
+    async fn check_conflicts(self, client: &Client) -> Result<Self, Error> {
+        let packages = client
+            .resolve_packages(self.selections.iter().map(|s| &s.package))
+            .await?;
+        let (conflicts, unchecked) = client.conflicts(&packages).await?;
+
+        // Packages without files are known not to conflict
+        let to_cache = self
+            .to_cache()
+            .into_iter()
+            .map(|p| &p.id)
+            .collect::<HashSet<_>>();
+        let unchecked = unchecked
+            .into_iter()
+            .filter(|p| to_cache.contains(&p.id))
+            .cloned()
+            .collect();
+
+        Ok(Self {
+            conflicts,
+            unchecked,
+            ..self
+        })
+    }
+
+    /// Returns true if the plan doesn't add, remove or upgrade any package
+    pub fn is_empty(&self) -> bool {
+        self.additions.is_empty() && self.removals.is_empty() && self.upgrades.is_empty()
+    }
+
+    /// Describe the changes by package name, i.e. `added a, b; removed c`
+    ///
+    /// Returns `None` if the plan is empty
+    pub fn description(&self) -> Option<String> {
+        let names = |packages: &mut dyn Iterator<Item = &Package>| {
+            packages
+                .map(|p| p.meta.name.to_string())
+                .sorted()
+                .join(", ")
+        };
+
+        let changes = [
+            ("added", names(&mut self.additions.iter())),
+            ("removed", names(&mut self.removals.iter())),
+            ("upgraded", names(&mut self.upgrades.iter().map(|u| &u.to))),
+        ];
+
+        let description = changes
+            .into_iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(change, names)| format!("{change} {names}"))
+            .join("; ");
+
+        (!description.is_empty()).then_some(description)
+    }
+
+    /// All packages which must be cached before the plan can be applied
+    pub fn to_cache(&self) -> Vec<&Package> {
+        self.additions
+            .iter()
+            .chain(self.upgrades.iter().map(|u| &u.to))
+            .collect()
+    }
+}
+
+/// Plan the installation of the provided package names / providers. Packages
+/// pinned to a version or release (see [`Spec`]) replace any installed package
+/// of the same name.
+pub async fn install(client: &Client, pkgs: &[&str]) -> Result<Plan, Error> {
+    // Resolve input packages
+    let (input, pinned): (Vec<_>, Vec<_>) = resolve_input(pkgs, client)
+        .await?
+        .into_iter()
+        .map(|(spec, id)| {
+            let pinned = spec.pin.is_some().then(|| id.clone());
+            (id, pinned)
+        })
+        .unzip();
+    let pinned = pinned.into_iter().flatten().collect::<HashSet<_>>();
+
+    // Add all inputs
+    let mut tx = client.registry.transaction()?;
+
+    tx.add(input.clone()).await?;
+
+    // Resolve transaction to metadata
+    let resolved = client.resolve_packages(tx.finalize()).await?;
+
+    // Get installed packages to check against
+    let installed = client
+        .registry
+        .list_installed(Flags::NONE)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // Get missing packages that are:
+    //
+    // Stateful: Not installed
+    // Ephemeral: all
+    //
+    // Otherwise pinned packages replace the installed package of the same name
+    let mut missing = vec![];
+    let mut upgrades = vec![];
+    let mut unchanged = vec![];
+    for package in resolved {
+        let current = installed.iter().find(|i| i.meta.name == package.meta.name);
+
+        match current {
+            _ if client.is_ephemeral() => missing.push(package),
+            None => missing.push(package),
+            Some(from) if from.id != package.id && pinned.contains(&package.id) => {
+                upgrades.push(Upgrade {
+                    from: from.clone(),
+                    to: package,
+                })
+            }
+            Some(_) if input.contains(&package.id) => unchanged.push(package),
+            Some(_) => {}
+        }
+    }
+
+    // Calculate the new state of packages (old_state + missing), swapping
+    // in the replacement of any pinned package
+    let selections = {
+        // Only use previous state in stateful mode
+        let previous_selections = match client.installation.active_state {
+            Some(id) if !client.is_ephemeral() => client.state_db.get(&id).await?.selections,
+            _ => vec![],
+        };
+        let missing_selections = missing.iter().map(|p| Selection {
+            package: p.id.clone(),
+            // Package is explicit if it was one of the input
+            // packages provided by the user
+            explicit: input.contains(&p.id),
+            reason: None,
+        });
+
+        let previous_selections = previous_selections.into_iter().map(|s| {
+            match upgrades.iter().find(|u| u.from.id == s.package) {
+                Some(upgrade) => Selection {
+                    package: upgrade.to.id.clone(),
+                    explicit: true,
+                    ..s
+                },
+                None => s,
+            }
+        });
+
+        missing_selections
+            .chain(previous_selections)
+            .collect::<Vec<_>>()
+    };
+
+    Plan::new(
+        Kind::Install,
+        missing,
+        vec![],
+        upgrades,
+        unchanged,
+        selections,
+    )
+    .check_conflicts(client)
+    .await
+}
+
+/// Plan the removal of the provided package names / providers, along
+/// with all of their reverse dependencies
+pub async fn remove(client: &Client, pkgs: &[&str]) -> Result<Plan, Error> {
+    let pkgs = pkgs
+        .iter()
+        .map(|name| Provider::from_name(name).map_err(|_| Error::NoPackage(name.to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let installed = client
+        .registry
+        .list_installed(Flags::NONE)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let installed_ids = installed
+        .iter()
+        .map(|p| p.id.clone())
+        .collect::<HashSet<_>>();
+
+    // Separate packages between installed / not installed (or invalid)
+    let (for_removal, not_installed): (Vec<_>, Vec<_>) = pkgs.iter().partition_map(|provider| {
+        installed
+            .iter()
+            .find(|i| i.meta.providers.contains(provider))
+            .map(|i| Either::Left(i.id.clone()))
+            .unwrap_or(Either::Right(provider.clone()))
+    });
+
+    // Bail if there's packages not installed
+    if !not_installed.is_empty() {
+        return Err(Error::NotInstalled(not_installed));
+    }
+
+    // Add all installed packages to transaction
+    let mut transaction = client
+        .registry
+        .transaction_with_installed(installed_ids.clone().into_iter().collect())
+        .await?;
+
+    // Remove all pkgs for removal
+    transaction.remove(for_removal).await?;
+
+    // Finalized tx has all reverse deps removed
+    let finalized = transaction.finalize().cloned().collect::<HashSet<_>>();
+
+    // Resolve all removed packages, where removed is (installed - finalized)
+    let removed = client
+        .resolve_packages(installed_ids.difference(&finalized))
+        .await?;
+
+    // Map finalized state to a [`Selection`] by referencing
+    // it's value from the previous state
+    let selections = {
+        let previous_selections = match client.installation.active_state {
+            Some(id) => client.state_db.get(&id).await?.selections,
+            None => vec![],
+        };
+
+        finalized
+            .into_iter()
+            .map(|id| {
+                previous_selections
+                    .iter()
+                    .find(|s| s.package == id)
+                    .cloned()
+                    // Should be unreachable since new state from removal
+                    // is always a subset of the previous state
+                    .unwrap_or(Selection {
+                        package: id,
+                        explicit: false,
+                        reason: None,
+                    })
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Removing packages can't add conflicts
+    Ok(Plan::new(
+        Kind::Remove,
+        vec![],
+        removed,
+        vec![],
+        vec![],
+        selections,
+    ))
+}
+
+/// Options controlling which changes are considered by [`sync`]
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    /// Only sync packages that have a version upgrade
+    pub upgrade_only: bool,
+    /// Allow targets pinned to a version or release to sync to
+    /// an older release than is installed
+    pub allow_downgrade: bool,
+    /// Only sync the named packages, along with the dependencies of their
+    /// candidates. Every package is considered when empty.
+    pub targets: Vec<Spec>,
+}
+
+impl SyncOptions {
+    /// Returns the target [`Spec`] for the package named `name`
+    fn target(&self, name: &package::Name) -> Option<&Spec> {
+        self.targets.iter().find(|t| t.name == name.as_ref())
+    }
+}
+
+/// Names of the packages which may be sync'd, all if `None`
+type Scope = Option<HashSet<package::Name>>;
+
+/// Returns the [`Scope`] of a sync, the targets along with every package their
+/// candidates depend on so installed dependencies are sync'd with them
+async fn sync_scope(client: &Client, options: &SyncOptions) -> Result<Scope, Error> {
+    if options.targets.is_empty() {
+        return Ok(None);
+    }
+
+    let mut candidates = vec![];
+
+    for spec in &options.targets {
+        match find_package(spec, client).await? {
+            Some(candidate) => candidates.push(candidate.id),
+            None => return Err(no_candidate(spec, client).await),
+        }
+    }
+
+    // Resolve the dependencies of each candidate against available packages
+    let mut tx = client.registry.transaction()?;
+    tx.add(candidates).await?;
+
+    let closure = client.resolve_packages(tx.finalize()).await?;
+
+    Ok(Some(closure.into_iter().map(|p| p.meta.name).collect()))
+}
+
+/// Plan a sync of package selections with candidates from the
+/// highest priority repository
+pub async fn sync(client: &Client, options: &SyncOptions) -> Result<Plan, Error> {
+    // Grab all the existing installed packages
+    let installed = client
+        .registry
+        .list_installed(Flags::NONE)
+        .try_collect::<Vec<_>>()
+        .await?;
+    if installed.is_empty() {
+        return Err(Error::NoInstall);
+    }
+
+    // All targets must be installed
+    if let Some(target) = options
+        .targets
+        .iter()
+        .find(|t| !installed.iter().any(|i| i.meta.name.as_ref() == t.name))
+    {
+        return Err(Error::NameNotInstalled(target.name.clone().into()));
+    }
+
+    // Resolve the finalized state w/ 2 passes.
+    //
+    // 1. Resolve a new state based on all explicit packages with sync applied
+    // 2. Resolve a new state based on `1`, this ensures applicable transitive
+    //    sync is applied
+    //
+    // By resolving only explicit first, this ensures any "orphaned" transitive deps
+    // are naturally dropped from the final state.
+    //
+    // Packages outside the scope of the targets keep their current version, as
+    // dependencies are resolved against the installed set before looking at candidates.
+    let scope = sync_scope(client, options).await?;
+    let first_pass =
+        resolve_with_sync(client, Resolution::Explicit, options, &scope, &installed).await?;
+    let finalized =
+        resolve_with_sync(client, Resolution::All, options, &scope, &first_pass).await?;
+
+    // Map finalized state to a [`Selection`] by referencing
+    // it's value from the previous state
+    let selections = {
+        let previous_selections = match client.installation.active_state {
+            Some(id) => client.state_db.get(&id).await?.selections,
+            None => vec![],
+        };
+
+        finalized
+            .iter()
+            .map(|p| {
+                // Use old version id to lookup previous selection
+                let lookup_id = installed
+                    .iter()
+                    .find_map(|i| (i.meta.name == p.meta.name).then_some(&i.id))
+                    .unwrap_or(&p.id);
+
+                previous_selections
+                    .iter()
+                    .find(|s| s.package == *lookup_id)
+                    .cloned()
+                    // Use prev reason / explicit flag & new id
+                    .map(|s| Selection {
+                        package: p.id.clone(),
+                        ..s
+                    })
+                    // Must be transitive
+                    .unwrap_or(Selection {
+                        package: p.id.clone(),
+                        explicit: false,
+                        reason: None,
+                    })
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Ephemeral blits everything to a fresh root
+    if client.is_ephemeral() {
+        return Plan::new(Kind::Sync, finalized, vec![], vec![], vec![], selections)
+            .check_conflicts(client)
+            .await;
+    }
+
+    let finalized_names = finalized
+        .iter()
+        .map(|p| p.meta.name.clone())
+        .collect::<HashSet<_>>();
+    let removals = installed
+        .iter()
+        .filter(|i| !finalized_names.contains(&i.meta.name))
+        .cloned()
+        .collect();
+
+    // Synced are packages not yet installed, either replacing an
+    // installed package of the same name or newly added
+    let mut additions = vec![];
+    let mut upgrades = vec![];
+    for package in finalized {
+        if installed.iter().any(|i| i.id == package.id) {
+            continue;
+        }
+
+        match installed.iter().find(|i| i.meta.name == package.meta.name) {
+            Some(from) => upgrades.push(Upgrade {
+                from: from.clone(),
+                to: package,
+            }),
+            None => additions.push(package),
+        }
+    }
+
+    Plan::new(
+        Kind::Sync,
+        additions,
+        removals,
+        upgrades,
+        vec![],
+        selections,
+    )
+    .check_conflicts(client)
+    .await
+}
+
+/// Resolves the package arguments as valid input packages. Returns an error
+/// if any args are invalid.
+async fn resolve_input(pkgs: &[&str], client: &Client) -> Result<Vec<(Spec, package::Id)>, Error> {
+    let specs = pkgs
+        .iter()
+        .map(|p| p.parse::<Spec>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Parse pkg args into valid / invalid sets
+    let queried = join_all(specs.into_iter().map(|spec| async {
+        let package = find_package(&spec, client).await?;
+        Ok::<_, Error>((spec, package))
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let mut results = vec![];
+
+    for (spec, pkg) in queried {
+        if let Some(pkg) = pkg {
+            results.push((spec, pkg.id))
+        } else {
+            return Err(no_candidate(&spec, client).await);
+        }
+    }
+
+    Ok(results)
+}
+
+/// Resolve a [`Spec`] to the first available package. Pinned specs are looked
+/// up by name, otherwise by provider.
+async fn find_package(spec: &Spec, client: &Client) -> Result<Option<Package>, Error> {
+    if spec.pin.is_some() {
+        let name = package::Name::from(spec.name.clone());
+
+        return Ok(client
+            .registry
+            .by_name(&name, Flags::AVAILABLE)
+            .try_filter(|p| {
+                let matches = spec.matches(p);
+                async move { matches }
+            })
+            .boxed()
+            .try_next()
+            .await?);
+    }
+
+    let Ok(provider) = Provider::from_name(&spec.name) else {
+        return Ok(None);
+    };
+
+    // First only, pre-sorted
+    let package = client
+        .registry
+        .by_provider(&provider, Flags::AVAILABLE)
+        .boxed()
+        .try_next()
+        .await?;
+
+    Ok(package)
+}
+
+/// Error for a [`Spec`] without candidates, explaining when it's only
+/// built for an unsupported architecture
+async fn no_candidate(spec: &Spec, client: &Client) -> Error {
+    let (Some(architectures), Ok(provider)) = (
+        client.registry.architectures(),
+        Provider::from_name(&spec.name),
+    ) else {
+        return Error::NoPackage(spec.to_string());
+    };
+
+    let unsupported = client
+        .registry
+        .unsupported_by_provider(&provider, Flags::AVAILABLE)
+        .try_filter(|p| {
+            let matches = spec.matches(p);
+            async move { matches }
+        })
+        .boxed()
+        .try_next()
+        .await;
+
+    match unsupported {
+        Ok(Some(package)) => Error::Architecture {
+            spec: spec.to_string(),
+            architecture: package.meta.architecture,
+            supported: architectures.clone(),
+        },
+        Ok(None) => Error::NoPackage(spec.to_string()),
+        Err(error) => error.into(),
+    }
+}
+
+enum Resolution {
+    Explicit,
+    All,
+}
+
+/// Return a fully resolved package set w/ sync'd changes swapped in
+/// using the provided `packages` at the requested [`Resolution`]
+async fn resolve_with_sync(
+    client: &Client,
+    resolution: Resolution,
+    options: &SyncOptions,
+    scope: &Scope,
+    packages: &[Package],
+) -> Result<Vec<Package>, Error> {
+    let all_ids = packages.iter().map(|p| &p.id).collect::<BTreeSet<_>>();
+
+    // For each package, replace it w/ it's sync'd change (if available)
+    // or return the original package
+    let with_sync = stream::iter(packages.iter())
+        .filter(|p| async {
+            match resolution {
+                Resolution::Explicit => p.flags.contains(Flags::EXPLICIT),
+                Resolution::All => true,
+            }
+        })
+        .map(|p| async {
+            // Leave packages outside the scope as-is
+            if scope
+                .as_ref()
+                .is_some_and(|scope| !scope.contains(&p.meta.name))
+            {
+                return Ok(Cow::Borrowed(p));
+            }
+
+            // Pinned targets must resolve to their matching release
+            if let Some(spec) = options.target(&p.meta.name).filter(|t| t.pin.is_some()) {
+                let Some(lookup) = find_package(spec, client).await? else {
+                    return Err(no_candidate(spec, client).await);
+                };
+
+                if lookup.meta.source_release < p.meta.source_release && !options.allow_downgrade {
+                    return Err(Error::Downgrade(spec.to_string()));
+                }
+
+                return if all_ids.contains(&lookup.id) {
+                    Ok(Cow::Borrowed(p))
+                } else {
+                    Ok(Cow::Owned(lookup))
+                };
+            }
+
+            // Get first available = use highest priority
+            if let Some(lookup) = client
+                .registry
+                .by_name(&p.meta.name, Flags::AVAILABLE)
+                .boxed()
+                .try_next()
+                .await?
+            {
+                let upgrade_check = if options.upgrade_only {
+                    lookup.meta.source_release > p.meta.source_release
+                } else {
+                    true
+                };
+
+                if !all_ids.contains(&lookup.id) && upgrade_check {
+                    Ok(Cow::Owned(lookup))
+                } else {
+                    Ok(Cow::Borrowed(p))
+                }
+            } else {
+                Err(Error::NameNotFound(p.meta.name.clone()))
+            }
+        })
+        .buffer_unordered(environment::MAX_DISK_CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // Build a new tx from this sync'd package set
+    let mut tx = client.registry.transaction()?;
+    tx.add(with_sync.iter().map(|p| p.id.clone()).collect())
+        .await?;
+
+    // Resolve the tx
+    Ok(client.resolve_packages(tx.finalize()).await?)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no package found: {0}")]
+    NoPackage(String),
+
+    #[error("{spec} is built for {architecture}, this installation supports {supported}")]
+    Architecture {
+        spec: String,
+        architecture: String,
+        supported: Architectures,
+    },
+
+    #[error("invalid package")]
+    Spec(#[from] spec::ParseError),
+
+    #[error("{0} is a downgrade, use --allow-downgrade to permit it")]
+    Downgrade(String),
+
+    #[error("packages not installed: {0:?}")]
+    NotInstalled(Vec<Provider>),
+
+    #[error("unknown package name: {0}")]
+    NameNotFound(package::Name),
+
+    #[error("package not installed: {0}")]
+    NameNotInstalled(package::Name),
+
+    #[error("no installation")]
+    NoInstall,
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("transaction")]
+    Transaction(#[from] transaction::Error),
+
+    #[error("registry")]
+    Registry(#[from] registry::Error),
+
+    #[error("state db")]
+    StateDB(#[from] crate::db::state::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use stone::payload::{layout, Layout};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        client::progress,
+        dependency::{self, Dependency},
+        registry::{plugin, Registry},
+    };
+
+    /// Release `release` of `name`, providing it's name & depending on `dependencies`
+    fn package(name: &str, release: u64, flags: Flags, dependencies: &[&str]) -> Package {
+        let mut package = plugin::test::package(name, flags);
+        package.id = package::Id::from(format!("{name}-{release}"));
+        package.meta.source_release = release;
+        package
+            .meta
+            .providers
+            .insert(Provider::from_name(name).unwrap());
+        package.meta.dependencies = dependencies
+            .iter()
+            .map(|name| Dependency {
+                kind: dependency::Kind::PackageName,
+                name: name.to_string(),
+            })
+            .collect();
+        package
+    }
+
+    /// A client of an empty root whose registry only holds `packages`
+    ///
+    /// The root is removed when the returned [`TempDir`] is dropped
+    async fn client(packages: Vec<Package>) -> (TempDir, Client) {
+        let root = tempfile::tempdir().unwrap();
+
+        let mut client = Client::new("test", root.path())
+            .await
+            .unwrap()
+            .with_reporter(progress::Silent);
+
+        // Record layouts so conflicts are checked when planning
+        for (digest, package) in packages.iter().enumerate() {
+            let layout = Layout {
+                uid: 0,
+                gid: 0,
+                mode: 0o100644,
+                tag: 0,
+                entry: layout::Entry::Regular(
+                    digest as u128,
+                    format!("usr/share/{}", package.meta.name),
+                ),
+            };
+            client
+                .layout_db
+                .add(package.id.clone(), layout)
+                .await
+                .unwrap();
+        }
+
+        client.registry = Registry::default();
+        client
+            .registry
+            .add_plugin(Box::new(plugin::Test::new(1, packages)));
+
+        (root, client)
+    }
+
+    fn ids<'a>(packages: impl IntoIterator<Item = &'a Package>) -> Vec<String> {
+        packages
+            .into_iter()
+            .map(|p| String::from(p.id.clone()))
+            .sorted()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_install() {
+        let (_root, client) = client(vec![
+            package("a", 1, Flags::AVAILABLE, &["b"]),
+            package("b", 1, Flags::AVAILABLE, &[]),
+            package("c", 1, Flags::INSTALLED, &[]),
+            package("c", 1, Flags::AVAILABLE, &[]),
+        ])
+        .await;
+
+        let plan = client.plan_install(&["a", "c"]).await.unwrap();
+
+        assert_eq!(plan.kind, Kind::Install);
+        assert_eq!(ids(&plan.additions), ["a-1", "b-1"]);
+        assert_eq!(ids(&plan.unchanged), ["c-1"]);
+        assert!(plan.removals.is_empty());
+        assert!(plan.upgrades.is_empty());
+        assert_eq!(plan.description().as_deref(), Some("added a, b"));
+
+        // Only requested packages are explicit
+        let explicit = |id: &str| {
+            plan.selections
+                .iter()
+                .find(|s| s.package == package::Id::from(id.to_string()))
+                .map(|s| s.explicit)
+        };
+        assert_eq!(explicit("a-1"), Some(true));
+        assert_eq!(explicit("b-1"), Some(false));
+
+        assert!(matches!(
+            client.plan_install(&["d"]).await,
+            Err(Error::NoPackage(name)) if name == "d"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let (_root, client) = client(vec![
+            package("a", 1, Flags::INSTALLED, &["b"]),
+            package("b", 1, Flags::INSTALLED, &[]),
+            package("c", 1, Flags::INSTALLED, &[]),
+        ])
+        .await;
+
+        // Reverse dependencies are removed too
+        let plan = client.plan_remove(&["b"]).await.unwrap();
+
+        assert_eq!(plan.kind, Kind::Remove);
+        assert_eq!(ids(&plan.removals), ["a-1", "b-1"]);
+        assert!(plan.additions.is_empty());
+        assert_eq!(
+            plan.selections
+                .iter()
+                .map(|s| String::from(s.package.clone()))
+                .collect::<Vec<_>>(),
+            ["c-1"]
+        );
+
+        assert!(matches!(
+            client.plan_remove(&["d"]).await,
+            Err(Error::NotInstalled(providers)) if providers.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let (_root, client) = client(vec![
+            package("a", 1, Flags::INSTALLED | Flags::EXPLICIT, &["b"]),
+            package("b", 1, Flags::INSTALLED, &[]),
+            package("c", 1, Flags::INSTALLED, &[]),
+            package("a", 1, Flags::AVAILABLE, &["b"]),
+            package("a", 2, Flags::AVAILABLE, &["b"]),
+            package("b", 2, Flags::AVAILABLE, &[]),
+        ])
+        .await;
+
+        let plan = client.plan_sync(&SyncOptions::default()).await.unwrap();
+
+        assert_eq!(plan.kind, Kind::Sync);
+        assert_eq!(ids(plan.upgrades.iter().map(|u| &u.from)), ["a-1", "b-1"]);
+        assert_eq!(ids(plan.upgrades.iter().map(|u| &u.to)), ["a-2", "b-2"]);
+        // Orphaned transitive packages are dropped
+        assert_eq!(ids(&plan.removals), ["c-1"]);
+        assert_eq!(
+            plan.description().as_deref(),
+            Some("removed c; upgraded a, b")
+        );
+
+        let options = SyncOptions {
+            targets: vec!["d".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            client.plan_sync(&options).await,
+            Err(Error::NameNotInstalled(name)) if name.as_ref() == "d"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sync_targets() {
+        let (_root, client) = client(vec![
+            package("a", 1, Flags::INSTALLED | Flags::EXPLICIT, &["b"]),
+            package("b", 1, Flags::INSTALLED, &[]),
+            package("c", 1, Flags::INSTALLED | Flags::EXPLICIT, &[]),
+            package("a", 2, Flags::AVAILABLE, &["b"]),
+            package("b", 2, Flags::AVAILABLE, &[]),
+            package("c", 2, Flags::AVAILABLE, &[]),
+        ])
+        .await;
+
+        let options = SyncOptions {
+            targets: vec!["a".parse().unwrap()],
+            ..Default::default()
+        };
+        let plan = client.plan_sync(&options).await.unwrap();
+
+        // Installed dependencies of the target are sync'd along with it
+        assert_eq!(ids(plan.upgrades.iter().map(|u| &u.to)), ["a-2", "b-2"]);
+        assert!(plan.removals.is_empty());
+        assert!(plan
+            .selections
+            .iter()
+            .any(|s| s.package == package::Id::from("c-1".to_string())));
+    }
+}
@@ -0,0 +1,518 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Progress reporting for [`Client`] operations & repository indexing
+//!
+//! The [`Client`] emits typed [`Event`]s to a [`Reporter`] so library
+//! consumers can decide how (or if) progress is rendered.
+//!
+//! [`Client`]: super::Client
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tui::{MultiProgress, ProgressBar, ProgressStyle, Stylize};
+
+use url::Url;
+use vfs::tree::Conflict;
+
+use crate::{
+    client::{self, blit, cache},
+    package, request, Package, State,
+};
+
+/// An event emitted by the [`Client`], or while indexing a repository
+///
+/// [`Client`]: super::Client
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// Caching of `total` packages has begun
+    CacheStarted { total: usize },
+    /// Download of a package has started
+    DownloadStarted { package: &'a Package },
+    /// Download of a package has progressed
+    DownloadProgress {
+        package: &'a Package,
+        progress: cache::Progress,
+    },
+    /// Download of a package has finished, `was_cached` if no
+    /// network transfer was needed
+    DownloadFinished {
+        package: &'a Package,
+        was_cached: bool,
+    },
+    /// Unpacking of a downloaded package has progressed
+    UnpackProgress {
+        package: &'a Package,
+        progress: cache::Progress,
+    },
+    /// The layout of a package is being stored in the layout db
+    LayoutStore { package: &'a Package },
+    /// A package has been fully cached & recorded in the install db
+    PackageCached {
+        package: &'a Package,
+        was_cached: bool,
+    },
+    /// All packages have been cached
+    CacheFinished,
+    /// Blitting of `total` filesystem entries has begun
+    BlitStarted { total: u64 },
+    /// Blitting has progressed
+    BlitProgress { completed: u64, total: u64 },
+    /// Blitting has finished, regular files were created with the `used` modes
+    BlitFinished { used: blit::Used },
+    /// Files provided by more than one package were resolved by the conflict policy
+    FileConflicts { conflicts: &'a [Conflict] },
+    /// A new state has been applied, `None` for ephemeral clients
+    StateApplied { state: Option<&'a State> },
+    /// A mirror failed, the next one will be tried
+    MirrorFailed {
+        url: &'a Url,
+        error: &'a request::Error,
+    },
+    /// A delta of a package failed, the full package will be fetched instead
+    DeltaFailed {
+        package: &'a Package,
+        error: &'a client::Error,
+    },
+    /// Indexing of `total` stones in a repository directory has begun
+    IndexStarted { total: usize },
+    /// Hashing of `size` bytes at `path`, relative to the repository, has begun
+    HashStarted { path: &'a str, size: u64 },
+    /// Hashing of the file at `path` has progressed
+    HashProgress {
+        path: &'a str,
+        progress: cache::Progress,
+    },
+    /// The metadata of the stone at `path` is being read
+    ReadMeta { path: &'a str },
+    /// The stone at `path` has been indexed
+    StoneIndexed { path: &'a str },
+    /// Creation of `total` deltas has begun
+    DeltasStarted { total: usize },
+    /// Creation of the delta at `path` has begun
+    DeltaStarted { path: &'a str },
+    /// The delta at `path` has been created with `files` changed files
+    DeltaCreated { path: &'a str, files: usize },
+    /// The index files of the repository are being written
+    IndexWriting,
+    /// Indexing has finished
+    IndexFinished,
+}
+
+/// Receives [`Event`]s emitted by the [`Client`]
+///
+/// [`Client`]: super::Client
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: Event<'_>);
+}
+
+/// A [`Reporter`] which discards all events
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Silent;
+
+impl Reporter for Silent {
+    fn report(&self, _event: Event<'_>) {}
+}
+
+/// A [`Reporter`] which renders progress bars to the terminal
+#[derive(Default)]
+pub struct Tui {
+    multi_progress: MultiProgress,
+    total: Mutex<Option<ProgressBar>>,
+    packages: Mutex<HashMap<package::Id, ProgressBar>>,
+    blit: Mutex<Option<ProgressBar>>,
+    files: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl Tui {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the progress bar for `package`, if one is active
+    fn package_bar(&self, package: &Package) -> Option<ProgressBar> {
+        self.packages.lock().unwrap().get(&package.id).cloned()
+    }
+
+    /// Add a bar to track the total count of `total` items
+    fn start_total(&self, total: usize) {
+        let total_progress = self.multi_progress.add(
+            ProgressBar::new(total as u64).with_style(
+                ProgressStyle::with_template("\n|{bar:20.cyan/blue}| {pos}/{len}")
+                    .unwrap()
+                    .progress_chars("■≡=- "),
+            ),
+        );
+        total_progress.tick();
+
+        *self.total.lock().unwrap() = Some(total_progress);
+    }
+
+    /// Return the spinner for the file at `path`, adding it if needed
+    fn file_bar(&self, path: &str) -> ProgressBar {
+        self.files
+            .lock()
+            .unwrap()
+            .entry(path.to_string())
+            .or_insert_with(|| {
+                let bar = match self.total.lock().unwrap().as_ref() {
+                    Some(total) => self
+                        .multi_progress
+                        .insert_before(total, ProgressBar::new_spinner()),
+                    None => self.multi_progress.add(ProgressBar::new_spinner()),
+                };
+                bar.enable_steady_tick(Duration::from_millis(150));
+                bar
+            })
+            .clone()
+    }
+
+    /// Remove the spinner for the file at `path`, printing `line` in it's place
+    fn finish_file(&self, path: &str, line: String) {
+        if let Some(bar) = self.files.lock().unwrap().remove(path) {
+            bar.finish();
+            self.multi_progress.remove(&bar);
+        }
+
+        let _ = self.multi_progress.println(line);
+
+        if let Some(total) = self.total.lock().unwrap().as_ref() {
+            total.inc(1);
+        }
+    }
+}
+
+impl Reporter for Tui {
+    fn report(&self, event: Event<'_>) {
+        match event {
+            Event::CacheStarted { total } => {
+                // Add bar to track total package counts
+                self.start_total(total);
+            }
+            Event::DownloadStarted { package } => {
+                let bar = ProgressBar::new(package.meta.download_size.unwrap_or_default())
+                    .with_message(format!(
+                        "{} {}",
+                        "Downloading".blue(),
+                        package.meta.name.to_string().bold(),
+                    ))
+                    .with_style(
+                        ProgressStyle::with_template(
+                            " {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ",
+                        )
+                        .unwrap()
+                        .tick_chars("--=≡■≡=--"),
+                    );
+
+                let progress_bar = match self.total.lock().unwrap().as_ref() {
+                    Some(total) => self.multi_progress.insert_before(total, bar),
+                    None => self.multi_progress.add(bar),
+                };
+                progress_bar.enable_steady_tick(Duration::from_millis(150));
+
+                self.packages
+                    .lock()
+                    .unwrap()
+                    .insert(package.id.clone(), progress_bar);
+            }
+            Event::DownloadProgress { package, progress } => {
+                if let Some(progress_bar) = self.package_bar(package) {
+                    // A delta is smaller than the package
+                    progress_bar.set_length(progress.total);
+                    progress_bar.inc(progress.delta);
+                }
+            }
+            Event::DownloadFinished { package, .. } => {
+                if let Some(progress_bar) = self.package_bar(package) {
+                    // Set progress to unpacking
+                    progress_bar.set_message(format!(
+                        "{} {}",
+                        "Unpacking".yellow(),
+                        package.meta.name.to_string().bold(),
+                    ));
+                    progress_bar.set_length(1000);
+                    progress_bar.set_position(0);
+                }
+            }
+            Event::UnpackProgress { package, progress } => {
+                if let Some(progress_bar) = self.package_bar(package) {
+                    progress_bar.set_position((progress.pct() * 1000.0) as u64);
+                }
+            }
+            Event::LayoutStore { package } => {
+                if let Some(progress_bar) = self.package_bar(package) {
+                    progress_bar.set_message(format!(
+                        "{} {}",
+                        "Store layout".white(),
+                        package.meta.name.to_string().bold()
+                    ));
+                }
+            }
+            Event::PackageCached {
+                package,
+                was_cached,
+            } => {
+                // Remove this progress bar
+                if let Some(progress_bar) = self.packages.lock().unwrap().remove(&package.id) {
+                    progress_bar.finish();
+                    self.multi_progress.remove(&progress_bar);
+                }
+
+                let cached_tag = if was_cached {
+                    format!("{}", " (cached)".dim())
+                } else {
+                    String::default()
+                };
+
+                // Write installed line
+                let _ = self.multi_progress.println(format!(
+                    "{} {}{}",
+                    "Installed".green(),
+                    package.meta.name.to_string().bold(),
+                    cached_tag,
+                ));
+
+                // Inc total progress by 1
+                if let Some(total) = self.total.lock().unwrap().as_ref() {
+                    total.inc(1);
+                }
+            }
+            Event::CacheFinished => {
+                self.packages.lock().unwrap().clear();
+                self.total.lock().unwrap().take();
+
+                // Remove progress
+                let _ = self.multi_progress.clear();
+            }
+            Event::BlitStarted { total } => {
+                let progress = ProgressBar::new(total).with_style(
+                    ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {msg}")
+                        .unwrap()
+                        .progress_chars("■≡=- "),
+                );
+                progress.set_message("Blitting filesystem");
+                progress.enable_steady_tick(Duration::from_millis(150));
+                progress.tick();
+
+                *self.blit.lock().unwrap() = Some(progress);
+            }
+            Event::BlitProgress { completed, total } => {
+                if let Some(progress) = self.blit.lock().unwrap().as_ref() {
+                    progress.set_length(total);
+                    progress.set_position(completed);
+                }
+            }
+            Event::BlitFinished { used } => {
+                if let Some(progress) = self.blit.lock().unwrap().take() {
+                    if used.modes().next().is_some() {
+                        progress.set_message(format!("Blitted filesystem ({used})"));
+                    } else {
+                        progress.set_message("Blitted filesystem");
+                    }
+                    progress.finish();
+                }
+            }
+            Event::FileConflicts { conflicts } => {
+                for conflict in conflicts {
+                    let _ = self
+                        .multi_progress
+                        .println(format!("{} file conflict: {conflict}", "Warning".yellow(),));
+                }
+            }
+            Event::StateApplied { .. } => {}
+            Event::MirrorFailed { url, error } => {
+                let _ = self.multi_progress.println(format!(
+                    "{} mirror {url} failed ({}), trying next",
+                    "Warning".yellow(),
+                    error_chain(error),
+                ));
+            }
+            Event::DeltaFailed { package, error } => {
+                let _ = self.multi_progress.println(format!(
+                    "{} delta of {} failed ({}), fetching full package",
+                    "Warning".yellow(),
+                    package.meta.name.to_string().bold(),
+                    error_chain(error),
+                ));
+            }
+            Event::IndexStarted { total } => {
+                let _ = self
+                    .multi_progress
+                    .println(format!("Indexing {total} files\n"));
+                self.start_total(total);
+            }
+            Event::HashStarted { path, size } => {
+                let bar = self.file_bar(path);
+                bar.set_length(size);
+                bar.set_position(0);
+                bar.set_message(format!("{} {}", "Hashing".blue(), path.bold()));
+                bar.set_style(
+                    ProgressStyle::with_template(
+                        " {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ",
+                    )
+                    .unwrap()
+                    .tick_chars("--=≡■≡=--"),
+                );
+            }
+            Event::HashProgress { path, progress } => {
+                if let Some(bar) = self.files.lock().unwrap().get(path) {
+                    bar.set_position(progress.completed);
+                }
+            }
+            Event::ReadMeta { path } => {
+                let bar = self.file_bar(path);
+                bar.set_message(format!("{} {}", "Indexing".yellow(), path.bold()));
+                bar.set_style(
+                    ProgressStyle::with_template(" {spinner} {wide_msg}")
+                        .unwrap()
+                        .tick_chars("--=≡■≡=--"),
+                );
+            }
+            Event::StoneIndexed { path } => {
+                self.finish_file(path, format!("{} {}", "Indexed".green(), path.bold()));
+            }
+            Event::DeltasStarted { total } => {
+                if let Some(total_progress) = self.total.lock().unwrap().as_ref() {
+                    total_progress.inc_length(total as u64);
+                }
+            }
+            Event::DeltaStarted { path } => {
+                self.file_bar(path)
+                    .set_message(format!("{} {}", "Creating".yellow(), path.bold()));
+            }
+            Event::DeltaCreated { path, files } => {
+                self.finish_file(
+                    path,
+                    format!("{} {} ({files} files)", "Created".green(), path.bold()),
+                );
+            }
+            Event::IndexWriting => {
+                if let Some(total) = self.total.lock().unwrap().as_ref() {
+                    total.set_message("Writing index file");
+                    total.set_style(
+                        ProgressStyle::with_template("\n {spinner} {wide_msg}")
+                            .unwrap()
+                            .tick_chars("--=≡■≡=--"),
+                    );
+                    total.enable_steady_tick(Duration::from_millis(150));
+                }
+            }
+            Event::IndexFinished => {
+                self.files.lock().unwrap().clear();
+                self.total.lock().unwrap().take();
+
+                let _ = self.multi_progress.clear();
+            }
+        }
+    }
+}
+
+/// Format `error` and all of it's sources
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+
+    while let Some(error) = source {
+        chain.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+
+    chain
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        client::Client,
+        registry::{plugin, Registry},
+    };
+
+    /// Records the name of each [`Event`] in the order it's reported
+    #[derive(Default, Clone)]
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Reporter for Recorder {
+        fn report(&self, event: Event<'_>) {
+            let name = match event {
+                Event::CacheStarted { .. } => "CacheStarted",
+                Event::DownloadStarted { .. } => "DownloadStarted",
+                Event::DownloadProgress { .. } => "DownloadProgress",
+                Event::DownloadFinished { .. } => "DownloadFinished",
+                Event::UnpackProgress { .. } => "UnpackProgress",
+                Event::LayoutStore { .. } => "LayoutStore",
+                Event::PackageCached { .. } => "PackageCached",
+                Event::CacheFinished => "CacheFinished",
+                Event::BlitStarted { .. } => "BlitStarted",
+                Event::BlitProgress { .. } => "BlitProgress",
+                Event::BlitFinished { .. } => "BlitFinished",
+                Event::FileConflicts { .. } => "FileConflicts",
+                Event::StateApplied { .. } => "StateApplied",
+                Event::MirrorFailed { .. } => "MirrorFailed",
+                Event::DeltaFailed { .. } => "DeltaFailed",
+                Event::IndexStarted { .. } => "IndexStarted",
+                Event::HashStarted { .. } => "HashStarted",
+                Event::HashProgress { .. } => "HashProgress",
+                Event::ReadMeta { .. } => "ReadMeta",
+                Event::StoneIndexed { .. } => "StoneIndexed",
+                Event::DeltasStarted { .. } => "DeltasStarted",
+                Event::DeltaStarted { .. } => "DeltaStarted",
+                Event::DeltaCreated { .. } => "DeltaCreated",
+                Event::IndexWriting => "IndexWriting",
+                Event::IndexFinished => "IndexFinished",
+            };
+
+            let mut events = self.0.lock().unwrap();
+
+            // Progress is reported any number of times, only record it once
+            if events.last() != Some(&name) {
+                events.push(name);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_order() {
+        let root = tempfile::tempdir().unwrap();
+
+        let recorder = Recorder::default();
+        let mut client = Client::new("test", root.path())
+            .await
+            .unwrap()
+            .with_reporter(recorder.clone());
+
+        client.registry = Registry::default();
+        client.registry.add_plugin(Box::new(plugin::Test::new(
+            1,
+            vec![plugin::test::stone_package()],
+        )));
+
+        // Planning doesn't fetch the stone to check it for conflicts
+        let plan = client.plan_install(&["bash-completion"]).await.unwrap();
+        assert_eq!(plan.unchecked.len(), 1);
+        assert!(recorder.0.lock().unwrap().is_empty());
+
+        client.execute(&plan).await.unwrap();
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "CacheStarted",
+                "DownloadStarted",
+                "DownloadProgress",
+                "DownloadFinished",
+                "UnpackProgress",
+                "LayoutStore",
+                "PackageCached",
+                "CacheFinished",
+                "BlitStarted",
+                "BlitProgress",
+                "BlitFinished",
+                "StateApplied",
+            ]
+        );
+    }
+}
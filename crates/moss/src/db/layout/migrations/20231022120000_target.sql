@@ -0,0 +1,10 @@
+-- Index the installed path of each entry for ownership queries
+ALTER TABLE layout ADD COLUMN target TEXT GENERATED ALWAYS AS (
+    CASE entry_type
+        WHEN 'regular' THEN entry_value2
+        WHEN 'symlink' THEN entry_value2
+        ELSE entry_value1
+    END
+) VIRTUAL;
+
+CREATE INDEX IF NOT EXISTS layout_target ON layout (target);
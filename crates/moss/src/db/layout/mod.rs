@@ -59,34 +59,63 @@ impl Database {
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(layouts
-            .into_iter()
-            .filter_map(|layout| {
-                let encoding::Layout {
-                    package_id,
-                    uid,
-                    gid,
-                    mode,
-                    tag,
-                    entry_type,
-                    entry_value1,
-                    entry_value2,
-                } = layout;
+        Ok(decode_layouts(layouts))
+    }
 
-                let entry = encoding::decode_entry(entry_type, entry_value1, entry_value2)?;
+    /// Retrieve all entries installed to `target`, relative to `/usr`
+    pub async fn by_target(
+        &self,
+        target: &str,
+    ) -> Result<Vec<(package::Id, payload::Layout)>, Error> {
+        let layouts = sqlx::query_as::<_, encoding::Layout>(
+            "
+            SELECT package_id,
+                   uid,
+                   gid,
+                   mode,
+                   tag,
+                   entry_type,
+                   entry_value1,
+                   entry_value2
+            FROM layout
+            WHERE target = ?;
+            ",
+        )
+        .bind(target)
+        .fetch_all(&self.pool)
+        .await?;
 
-                Some((
-                    package_id.0,
-                    payload::Layout {
-                        uid,
-                        gid,
-                        mode,
-                        tag,
-                        entry,
-                    },
-                ))
-            })
-            .collect())
+        Ok(decode_layouts(layouts))
+    }
+
+    /// Retrieve all entries installed beneath `prefix`, relative to `/usr`
+    pub async fn by_target_prefix(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<(package::Id, payload::Layout)>, Error> {
+        // GLOB is case sensitive so it can use the target index,
+        // `[` is the only special character left to escape
+        let pattern = format!("{}*", prefix.replace('[', "[[]"));
+
+        let layouts = sqlx::query_as::<_, encoding::Layout>(
+            "
+            SELECT package_id,
+                   uid,
+                   gid,
+                   mode,
+                   tag,
+                   entry_type,
+                   entry_value1,
+                   entry_value2
+            FROM layout
+            WHERE target GLOB ?;
+            ",
+        )
+        .bind(pattern)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(decode_layouts(layouts))
     }
 
     pub async fn file_hashes(&self) -> Result<HashSet<String>, Error> {
@@ -227,6 +256,37 @@ impl Database {
     }
 }
 
+fn decode_layouts(layouts: Vec<encoding::Layout>) -> Vec<(package::Id, payload::Layout)> {
+    layouts
+        .into_iter()
+        .filter_map(|layout| {
+            let encoding::Layout {
+                package_id,
+                uid,
+                gid,
+                mode,
+                tag,
+                entry_type,
+                entry_value1,
+                entry_value2,
+            } = layout;
+
+            let entry = encoding::decode_entry(entry_type, entry_value1, entry_value2)?;
+
+            Some((
+                package_id.0,
+                payload::Layout {
+                    uid,
+                    gid,
+                    mode,
+                    tag,
+                    entry,
+                },
+            ))
+        })
+        .collect()
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("sqlx")]
@@ -334,5 +394,17 @@ mod test {
         let all = database.all().await.unwrap();
 
         assert_eq!(count, all.len());
+
+        let owners = database
+            .by_target("share/bash-completion/helpers/python")
+            .await
+            .unwrap();
+        assert_eq!(owners.len(), 1);
+
+        let helpers = database
+            .by_target_prefix("share/bash-completion/helpers/")
+            .await
+            .unwrap();
+        assert!(helpers.len() > 1);
     }
 }
@@ -0,0 +1,8 @@
+-- State of the index the db was last refreshed from
+CREATE TABLE IF NOT EXISTS meta_index (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    hash TEXT NOT NULL,
+    etag TEXT NULL,
+    last_modified TEXT NULL,
+    refreshed BIGINT NOT NULL DEFAULT (unixepoch())
+);
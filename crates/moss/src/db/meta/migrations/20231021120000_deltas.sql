@@ -0,0 +1,10 @@
+-- Delta stones which produce a package from the assets of a base package
+CREATE TABLE IF NOT EXISTS meta_deltas (
+    package TEXT NOT NULL,
+    base TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    download_size BIGINT NULL,
+    PRIMARY KEY (package, base),
+    FOREIGN KEY (package) REFERENCES meta(package) ON DELETE CASCADE
+);
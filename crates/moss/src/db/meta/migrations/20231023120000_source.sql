@@ -0,0 +1,16 @@
+-- Source provenance & build relations of the stone metadata
+ALTER TABLE meta ADD COLUMN source_uri TEXT NULL;
+ALTER TABLE meta ADD COLUMN source_path TEXT NULL;
+ALTER TABLE meta ADD COLUMN source_ref TEXT NULL;
+
+CREATE TABLE IF NOT EXISTS meta_build_dependencies (
+    package TEXT NOT NULL,
+    dependency TEXT NOT NULL,
+    FOREIGN KEY (package) REFERENCES meta(package) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS meta_conflicts (
+    package TEXT NOT NULL,
+    conflict TEXT NOT NULL,
+    FOREIGN KEY (package) REFERENCES meta(package) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Size of the package once unpacked, only known after caching
+ALTER TABLE meta ADD COLUMN installed_size BIGINT NULL;
@@ -0,0 +1,12 @@
+-- Repository dbs only add packages they don't have yet, and skip indexes whose
+-- hash is unchanged, so the build dependencies, conflicts & source columns of
+-- existing packages are never filled in. Wipe repository dbs, which are the
+-- only ones with an index, so the next refresh reloads them in full.
+DELETE FROM meta_licenses WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta_dependencies WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta_providers WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta_build_dependencies WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta_conflicts WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta_deltas WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta WHERE EXISTS (SELECT 1 FROM meta_index);
+DELETE FROM meta_index;
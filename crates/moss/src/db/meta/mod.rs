@@ -5,13 +5,14 @@
 use std::collections::HashSet;
 use std::path::Path;
 
+use chrono::{DateTime, Utc};
 use sqlx::{sqlite::SqliteConnectOptions, Acquire, Pool, Sqlite};
 use sqlx::{Executor, QueryBuilder};
 use thiserror::Error;
 
 use crate::db::Encoding;
 use crate::package::{self, Meta};
-use crate::{Dependency, Provider};
+use crate::{environment, Dependency, Provider};
 
 #[derive(Debug, Clone, Copy)]
 enum Table {
@@ -19,6 +20,8 @@ enum Table {
     Licenses,
     Dependencies,
     Providers,
+    BuildDependencies,
+    Conflicts,
 }
 
 #[derive(Debug)]
@@ -98,6 +101,16 @@ impl Filter {
     }
 }
 
+/// State of the index a [`Database`] was last refreshed from
+#[derive(Debug, Clone)]
+pub struct Index {
+    /// Sha256 hash of the index file
+    pub hash: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub refreshed: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Database {
     pool: Pool<Sqlite>,
@@ -122,9 +135,21 @@ impl Database {
         Ok(Self { pool })
     }
 
+    /// Remove all packages along with the recorded [`Index`], so the
+    /// next refresh fetches the index in full
     pub async fn wipe(&self) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
         // Other tables cascade delete so we only need to truncate `meta`
-        sqlx::query("DELETE FROM meta;").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM meta;")
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("DELETE FROM meta_index;")
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
         Ok(())
     }
 
@@ -143,7 +168,11 @@ impl Database {
                    homepage,
                    uri,
                    hash,
-                   download_size
+                   download_size,
+                   source_uri,
+                   source_path,
+                   source_ref,
+                   installed_size
             FROM meta
             ",
         );
@@ -169,14 +198,30 @@ impl Database {
             ",
         );
 
+        let mut build_dependencies_query = sqlx::QueryBuilder::new(
+            "
+            SELECT package, dependency
+            FROM meta_build_dependencies
+            ",
+        );
+
+        let mut conflicts_query = sqlx::QueryBuilder::new(
+            "
+            SELECT package, conflict
+            FROM meta_conflicts
+            ",
+        );
+
         if let Some(filter) = filter {
             filter.append(Table::Meta, &mut entry_query);
             filter.append(Table::Licenses, &mut licenses_query);
             filter.append(Table::Dependencies, &mut dependencies_query);
             filter.append(Table::Providers, &mut providers_query);
+            filter.append(Table::BuildDependencies, &mut build_dependencies_query);
+            filter.append(Table::Conflicts, &mut conflicts_query);
         }
 
-        let (entries, licenses, dependencies, providers) = futures::try_join!(
+        let (entries, licenses, dependencies, providers, build_dependencies, conflicts) = futures::try_join!(
             entry_query
                 .build_query_as::<encoding::Entry>()
                 .fetch_all(&self.pool),
@@ -189,6 +234,12 @@ impl Database {
             providers_query
                 .build_query_as::<encoding::Provider>()
                 .fetch_all(&self.pool),
+            build_dependencies_query
+                .build_query_as::<encoding::Dependency>()
+                .fetch_all(&self.pool),
+            conflicts_query
+                .build_query_as::<encoding::Conflict>()
+                .fetch_all(&self.pool),
         )?;
 
         Ok(entries
@@ -221,9 +272,23 @@ impl Database {
                             .filter(|l| l.id.0 == entry.id.0)
                             .map(|p| p.provider.0.clone())
                             .collect(),
+                        build_dependencies: build_dependencies
+                            .iter()
+                            .filter(|l| l.id.0 == entry.id.0)
+                            .map(|d| d.dependency.0.clone())
+                            .collect(),
+                        conflicts: conflicts
+                            .iter()
+                            .filter(|l| l.id.0 == entry.id.0)
+                            .map(|c| c.conflict.0.clone())
+                            .collect(),
+                        source_uri: entry.source_uri,
+                        source_path: entry.source_path,
+                        source_ref: entry.source_ref,
                         uri: entry.uri,
                         hash: entry.hash,
                         download_size: entry.download_size.map(|i| i as u64),
+                        installed_size: entry.installed_size.map(|i| i as u64),
                     },
                 )
             })
@@ -245,7 +310,11 @@ impl Database {
                    homepage,
                    uri,
                    hash,
-                   download_size
+                   download_size,
+                   source_uri,
+                   source_path,
+                   source_ref,
+                   installed_size
             FROM meta
             WHERE package = ?;
             ",
@@ -279,11 +348,31 @@ impl Database {
         )
         .bind(package.encode());
 
-        let (entry, licenses, dependencies, providers) = futures::try_join!(
+        let build_dependencies_query = sqlx::query_as::<_, encoding::Dependency>(
+            "
+            SELECT package, dependency
+            FROM meta_build_dependencies
+            WHERE package = ?;
+            ",
+        )
+        .bind(package.encode());
+
+        let conflicts_query = sqlx::query_as::<_, encoding::Conflict>(
+            "
+            SELECT package, conflict
+            FROM meta_conflicts
+            WHERE package = ?;
+            ",
+        )
+        .bind(package.encode());
+
+        let (entry, licenses, dependencies, providers, build_dependencies, conflicts) = futures::try_join!(
             entry_query.fetch_one(&self.pool),
             licenses_query.fetch_all(&self.pool),
             dependencies_query.fetch_all(&self.pool),
             providers_query.fetch_all(&self.pool),
+            build_dependencies_query.fetch_all(&self.pool),
+            conflicts_query.fetch_all(&self.pool),
         )?;
 
         Ok(Meta {
@@ -299,9 +388,18 @@ impl Database {
             licenses: licenses.into_iter().map(|l| l.license).collect(),
             dependencies: dependencies.into_iter().map(|d| d.dependency.0).collect(),
             providers: providers.into_iter().map(|p| p.provider.0).collect(),
+            build_dependencies: build_dependencies
+                .into_iter()
+                .map(|d| d.dependency.0)
+                .collect(),
+            conflicts: conflicts.into_iter().map(|c| c.conflict.0).collect(),
+            source_uri: entry.source_uri,
+            source_path: entry.source_path,
+            source_ref: entry.source_ref,
             uri: entry.uri,
             hash: entry.hash,
             download_size: entry.download_size.map(|i| i as u64),
+            installed_size: entry.installed_size.map(|i| i as u64),
         })
     }
 
@@ -326,16 +424,178 @@ impl Database {
     pub async fn batch_add(&self, packages: Vec<(package::Id, Meta)>) -> Result<(), Error> {
         let mut transaction = self.pool.begin().await?;
 
-        // Remove package (other tables cascade)
-        batch_remove_impl(
-            packages.iter().map(|(id, _)| id),
-            transaction.acquire().await?,
+        batch_add_impl(&packages, &mut transaction).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Returns the [`package::Delta`]s which produce `package`
+    pub async fn deltas(&self, package: &package::Id) -> Result<Vec<package::Delta>, Error> {
+        let deltas = sqlx::query_as::<_, encoding::Delta>(
+            "
+            SELECT package, base, uri, hash, download_size
+            FROM meta_deltas
+            WHERE package = ?;
+            ",
         )
+        .bind(package.encode())
+        .fetch_all(&self.pool)
         .await?;
 
-        // Create entry
-        sqlx::QueryBuilder::new(
+        Ok(deltas
+            .into_iter()
+            .map(|delta| package::Delta {
+                target: delta.package,
+                base: delta.base,
+                uri: delta.uri,
+                hash: delta.hash,
+                download_size: delta.download_size.map(|i| i as u64),
+            })
+            .collect())
+    }
+
+    /// Returns the [`Index`] this db was last refreshed from, if any
+    pub async fn index(&self) -> Result<Option<Index>, Error> {
+        let index = sqlx::query_as::<_, encoding::Index>(
             "
+            SELECT hash, etag, last_modified, refreshed
+            FROM meta_index
+            WHERE id = 0;
+            ",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(index.map(|index| Index {
+            hash: index.hash,
+            etag: index.etag,
+            last_modified: index.last_modified,
+            refreshed: index.refreshed,
+        }))
+    }
+
+    /// Replace the contents of the db with `packages` & `deltas` from `index`
+    ///
+    /// Only packages which aren't already present are added and only those
+    /// missing from `packages` are removed. As ids are the hash of the stone,
+    /// unchanged ids have unchanged metadata. Deltas are cheap so they're
+    /// replaced wholesale, skipping any whose target isn't in `packages`.
+    /// Everything happens within a single transaction so readers never
+    /// observe a partially applied refresh.
+    pub async fn apply_diff(
+        &self,
+        packages: Vec<(package::Id, Meta)>,
+        deltas: Vec<package::Delta>,
+        index: &Index,
+    ) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let existing = sqlx::query_as::<_, encoding::ProviderPackage>(
+            "
+            SELECT package
+            FROM meta;
+            ",
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|entry| entry.package.0)
+        .collect::<HashSet<_>>();
+        let incoming = packages.iter().map(|(id, _)| id).collect::<HashSet<_>>();
+
+        let removed = existing
+            .iter()
+            .filter(|id| !incoming.contains(id))
+            .collect::<Vec<_>>();
+        let added = packages
+            .iter()
+            .filter(|(id, _)| !existing.contains(id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for chunk in removed.chunks(environment::DB_BATCH_SIZE) {
+            batch_remove_impl(chunk.iter().copied(), &mut *transaction).await?;
+        }
+
+        for chunk in added.chunks(ADD_BATCH_SIZE) {
+            batch_add_impl(chunk, &mut transaction).await?;
+        }
+
+        replace_deltas_impl(deltas, &incoming, &mut transaction).await?;
+        set_index_impl(index, &mut *transaction).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Replace all deltas with `deltas` & record `index`, for a refresh
+    /// where the packages are unchanged
+    pub async fn replace_deltas(
+        &self,
+        deltas: Vec<package::Delta>,
+        index: &Index,
+    ) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let existing = sqlx::query_as::<_, encoding::ProviderPackage>(
+            "
+            SELECT package
+            FROM meta;
+            ",
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        .into_iter()
+        .map(|entry| entry.package.0)
+        .collect::<HashSet<_>>();
+
+        replace_deltas_impl(deltas, &existing.iter().collect(), &mut transaction).await?;
+        set_index_impl(index, &mut *transaction).await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn remove(&self, package: &package::Id) -> Result<(), Error> {
+        self.batch_remove(Some(package)).await
+    }
+
+    pub async fn batch_remove(
+        &self,
+        packages: impl IntoIterator<Item = &package::Id>,
+    ) -> Result<(), Error> {
+        batch_remove_impl(packages, &self.pool).await
+    }
+}
+
+/// Sqlite supports up to 32k parametized query binds
+const MAX_BINDS: usize = 32_766;
+
+/// Binds per package when inserting into `meta`, must match [`batch_add_impl`]
+const META_BINDS: usize = 17;
+
+/// Packages added per query. Only half of the binds go to the `meta` insert,
+/// leaving headroom for the related tables which bind per entry of a package
+const ADD_BATCH_SIZE: usize = MAX_BINDS / 2 / META_BINDS;
+
+async fn batch_add_impl(
+    packages: &[(package::Id, Meta)],
+    transaction: &mut sqlx::Transaction<'_, Sqlite>,
+) -> Result<(), Error> {
+    // Remove package (other tables cascade)
+    batch_remove_impl(
+        packages.iter().map(|(id, _)| id),
+        transaction.acquire().await?,
+    )
+    .await?;
+
+    // Create entry
+    sqlx::QueryBuilder::new(
+        "
             INSERT INTO meta (
                 package,
                 name,
@@ -349,121 +609,217 @@ impl Database {
                 homepage,
                 uri,
                 hash,
-                download_size                
+                download_size,
+                source_uri,
+                source_path,
+                source_ref,
+                installed_size
             )
             ",
+    )
+    .push_values(packages, |mut b, (id, meta)| {
+        let Meta {
+            name,
+            version_identifier,
+            source_release,
+            build_release,
+            architecture,
+            summary,
+            description,
+            source_id,
+            homepage,
+            uri,
+            hash,
+            download_size,
+            source_uri,
+            source_path,
+            source_ref,
+            installed_size,
+            ..
+        } = meta;
+
+        b.push_bind(id.encode())
+            .push_bind(name.encode())
+            .push_bind(version_identifier)
+            .push_bind(*source_release as i64)
+            .push_bind(*build_release as i64)
+            .push_bind(architecture)
+            .push_bind(summary)
+            .push_bind(description)
+            .push_bind(source_id)
+            .push_bind(homepage)
+            .push_bind(uri)
+            .push_bind(hash)
+            .push_bind(download_size.map(|i| i as i64))
+            .push_bind(source_uri)
+            .push_bind(source_path)
+            .push_bind(source_ref)
+            .push_bind(installed_size.map(|i| i as i64));
+    })
+    .build()
+    .execute(transaction.acquire().await?)
+    .await?;
+
+    // Licenses
+    let licenses = packages
+        .iter()
+        .flat_map(|(id, meta)| meta.licenses.iter().map(move |license| (id, license)))
+        .collect::<Vec<_>>();
+    if !licenses.is_empty() {
+        sqlx::QueryBuilder::new(
+            "
+                INSERT INTO meta_licenses (package, license)
+                ",
         )
-        .push_values(&packages, |mut b, (id, meta)| {
-            let Meta {
-                name,
-                version_identifier,
-                source_release,
-                build_release,
-                architecture,
-                summary,
-                description,
-                source_id,
-                homepage,
-                uri,
-                hash,
-                download_size,
-                ..
-            } = meta;
-
-            b.push_bind(id.encode())
-                .push_bind(name.encode())
-                .push_bind(version_identifier)
-                .push_bind(*source_release as i64)
-                .push_bind(*build_release as i64)
-                .push_bind(architecture)
-                .push_bind(summary)
-                .push_bind(description)
-                .push_bind(source_id)
-                .push_bind(homepage)
-                .push_bind(uri)
-                .push_bind(hash)
-                .push_bind(download_size.map(|i| i as i64));
+        .push_values(licenses, |mut b, (id, license)| {
+            b.push_bind(id.encode()).push_bind(license);
         })
         .build()
         .execute(transaction.acquire().await?)
         .await?;
+    }
 
-        // Licenses
-        let licenses = packages
-            .iter()
-            .flat_map(|(id, meta)| meta.licenses.iter().map(move |license| (id, license)))
-            .collect::<Vec<_>>();
-        if !licenses.is_empty() {
-            sqlx::QueryBuilder::new(
-                "
-                INSERT INTO meta_licenses (package, license)
-                ",
-            )
-            .push_values(licenses, |mut b, (id, license)| {
-                b.push_bind(id.encode()).push_bind(license);
-            })
-            .build()
-            .execute(transaction.acquire().await?)
-            .await?;
-        }
-
-        // Dependencies
-        let dependencies = packages
-            .iter()
-            .flat_map(|(id, meta)| {
-                meta.dependencies
-                    .iter()
-                    .map(move |dependency| (id, dependency))
-            })
-            .collect::<Vec<_>>();
-        if !dependencies.is_empty() {
-            sqlx::QueryBuilder::new(
-                "
+    // Dependencies
+    let dependencies = packages
+        .iter()
+        .flat_map(|(id, meta)| {
+            meta.dependencies
+                .iter()
+                .map(move |dependency| (id, dependency))
+        })
+        .collect::<Vec<_>>();
+    if !dependencies.is_empty() {
+        sqlx::QueryBuilder::new(
+            "
                 INSERT INTO meta_dependencies (package, dependency)
                 ",
-            )
-            .push_values(dependencies, |mut b, (id, dependency)| {
-                b.push_bind(id.encode()).push_bind(dependency.encode());
-            })
-            .build()
-            .execute(transaction.acquire().await?)
-            .await?;
-        }
+        )
+        .push_values(dependencies, |mut b, (id, dependency)| {
+            b.push_bind(id.encode()).push_bind(dependency.encode());
+        })
+        .build()
+        .execute(transaction.acquire().await?)
+        .await?;
+    }
 
-        // Providers
-        let providers = packages
-            .iter()
-            .flat_map(|(id, meta)| meta.providers.iter().map(move |provider| (id, provider)))
-            .collect::<Vec<_>>();
-        if !providers.is_empty() {
-            sqlx::QueryBuilder::new(
-                "
+    // Providers
+    let providers = packages
+        .iter()
+        .flat_map(|(id, meta)| meta.providers.iter().map(move |provider| (id, provider)))
+        .collect::<Vec<_>>();
+    if !providers.is_empty() {
+        sqlx::QueryBuilder::new(
+            "
                 INSERT INTO meta_providers (package, provider)
                 ",
-            )
-            .push_values(providers, |mut b, (id, provider)| {
-                b.push_bind(id.encode()).push_bind(provider.encode());
-            })
-            .build()
-            .execute(transaction.acquire().await?)
-            .await?;
-        }
-
-        transaction.commit().await?;
+        )
+        .push_values(providers, |mut b, (id, provider)| {
+            b.push_bind(id.encode()).push_bind(provider.encode());
+        })
+        .build()
+        .execute(transaction.acquire().await?)
+        .await?;
+    }
 
-        Ok(())
+    // Build dependencies
+    let build_dependencies = packages
+        .iter()
+        .flat_map(|(id, meta)| {
+            meta.build_dependencies
+                .iter()
+                .map(move |dependency| (id, dependency))
+        })
+        .collect::<Vec<_>>();
+    if !build_dependencies.is_empty() {
+        sqlx::QueryBuilder::new(
+            "
+                INSERT INTO meta_build_dependencies (package, dependency)
+                ",
+        )
+        .push_values(build_dependencies, |mut b, (id, dependency)| {
+            b.push_bind(id.encode()).push_bind(dependency.encode());
+        })
+        .build()
+        .execute(transaction.acquire().await?)
+        .await?;
     }
 
-    pub async fn remove(&self, package: &package::Id) -> Result<(), Error> {
-        self.batch_remove(Some(package)).await
+    // Conflicts
+    let conflicts = packages
+        .iter()
+        .flat_map(|(id, meta)| meta.conflicts.iter().map(move |conflict| (id, conflict)))
+        .collect::<Vec<_>>();
+    if !conflicts.is_empty() {
+        sqlx::QueryBuilder::new(
+            "
+                INSERT INTO meta_conflicts (package, conflict)
+                ",
+        )
+        .push_values(conflicts, |mut b, (id, conflict)| {
+            b.push_bind(id.encode()).push_bind(conflict.encode());
+        })
+        .build()
+        .execute(transaction.acquire().await?)
+        .await?;
     }
 
-    pub async fn batch_remove(
-        &self,
-        packages: impl IntoIterator<Item = &package::Id>,
-    ) -> Result<(), Error> {
-        batch_remove_impl(packages, &self.pool).await
+    Ok(())
+}
+
+/// Replace all deltas with those of `deltas` whose target is in `packages`
+async fn replace_deltas_impl(
+    deltas: Vec<package::Delta>,
+    packages: &HashSet<&package::Id>,
+    transaction: &mut sqlx::Transaction<'_, Sqlite>,
+) -> Result<(), Error> {
+    sqlx::query("DELETE FROM meta_deltas;")
+        .execute(&mut **transaction)
+        .await?;
+
+    let deltas = deltas
+        .into_iter()
+        .filter(|delta| packages.contains(&package::Id::from(delta.target.clone())))
+        .collect::<Vec<_>>();
+
+    for chunk in deltas.chunks(environment::DB_BATCH_SIZE) {
+        sqlx::QueryBuilder::new(
+            "
+            INSERT OR IGNORE INTO meta_deltas (package, base, uri, hash, download_size)
+            ",
+        )
+        .push_values(chunk, |mut b, delta| {
+            b.push_bind(&delta.target)
+                .push_bind(&delta.base)
+                .push_bind(&delta.uri)
+                .push_bind(&delta.hash)
+                .push_bind(delta.download_size.map(|i| i as i64));
+        })
+        .build()
+        .execute(&mut **transaction)
+        .await?;
     }
+
+    Ok(())
+}
+
+async fn set_index_impl<'a>(
+    index: &Index,
+    connection: impl Executor<'a, Database = Sqlite>,
+) -> Result<(), Error> {
+    sqlx::query(
+        "
+        INSERT OR REPLACE INTO meta_index (id, hash, etag, last_modified, refreshed)
+        VALUES (0, ?, ?, ?, ?);
+        ",
+    )
+    .bind(&index.hash)
+    .bind(&index.etag)
+    .bind(&index.last_modified)
+    .bind(index.refreshed.timestamp())
+    .execute(connection)
+    .await?;
+
+    Ok(())
 }
 
 async fn batch_remove_impl<'a>(
@@ -498,6 +854,31 @@ pub enum Error {
     Migrate(#[from] sqlx::migrate::MigrateError),
 }
 
+impl Error {
+    /// Returns true if the db file is corrupt or not a database at all, in
+    /// which case it can only be recreated
+    pub fn is_corrupt(&self) -> bool {
+        // Primary sqlite result codes
+        const SQLITE_CORRUPT: i32 = 11;
+        const SQLITE_NOTADB: i32 = 26;
+
+        let error = match self {
+            Error::Sqlx(error) | Error::Migrate(sqlx::migrate::MigrateError::Execute(error)) => {
+                error
+            }
+            _ => return false,
+        };
+
+        match error {
+            sqlx::Error::Database(error) => error
+                .code()
+                .and_then(|code| code.parse::<i32>().ok())
+                .is_some_and(|code| matches!(code & 0xff, SQLITE_CORRUPT | SQLITE_NOTADB)),
+            _ => false,
+        }
+    }
+}
+
 impl From<sqlx::Error> for Error {
     fn from(error: sqlx::Error) -> Self {
         match error {
@@ -508,6 +889,7 @@ impl From<sqlx::Error> for Error {
 }
 
 mod encoding {
+    use chrono::{DateTime, Utc};
     use sqlx::FromRow;
 
     use crate::db::Decoder;
@@ -529,6 +911,10 @@ mod encoding {
         pub uri: Option<String>,
         pub hash: Option<String>,
         pub download_size: Option<i64>,
+        pub source_uri: Option<String>,
+        pub source_path: Option<String>,
+        pub source_ref: Option<String>,
+        pub installed_size: Option<i64>,
     }
 
     #[derive(FromRow)]
@@ -552,6 +938,30 @@ mod encoding {
         pub provider: Decoder<crate::Provider>,
     }
 
+    #[derive(FromRow)]
+    pub struct Conflict {
+        #[sqlx(rename = "package")]
+        pub id: Decoder<package::Id>,
+        pub conflict: Decoder<crate::Provider>,
+    }
+
+    #[derive(FromRow)]
+    pub struct Index {
+        pub hash: String,
+        pub etag: Option<String>,
+        pub last_modified: Option<String>,
+        pub refreshed: DateTime<Utc>,
+    }
+
+    #[derive(FromRow)]
+    pub struct Delta {
+        pub package: String,
+        pub base: String,
+        pub uri: String,
+        pub hash: String,
+        pub download_size: Option<i64>,
+    }
+
     #[derive(FromRow)]
     pub struct ProviderPackage {
         pub package: Decoder<package::Id>,
@@ -586,12 +996,33 @@ mod test {
             .collect::<Result<Vec<_>, _>>()
             .unwrap();
         let meta_payload = payloads.iter().find_map(PayloadKind::meta).unwrap();
-        let meta = Meta::from_stone_payload(&meta_payload.body).unwrap();
+        let mut meta = Meta::from_stone_payload(&meta_payload.body).unwrap();
+        meta.build_dependencies.insert(Dependency {
+            kind: Kind::PkgConfig,
+            name: "zlib".to_string(),
+        });
+        meta.conflicts.insert(Provider {
+            kind: Kind::PackageName,
+            name: "bash-completion-legacy".to_string(),
+        });
+        meta.source_uri = Some("https://github.com/scop/bash-completion".to_string());
+        meta.source_path = Some("b/bash-completion/stone.yml".to_string());
+        meta.source_ref = Some("2.11".to_string());
+
+        // Roundtrips through the stone payload
+        let payload = meta.clone().to_stone_payload();
+        assert_eq!(Meta::from_stone_payload(&payload).unwrap(), meta);
+
+        // Only recorded in the db once cached
+        meta.installed_size = Some(872_273);
 
         let id = package::Id::from("test".to_string());
 
         database.add(id.clone(), meta.clone()).await.unwrap();
 
+        // Roundtrips through the db
+        assert_eq!(database.get(&id).await.unwrap(), meta);
+
         assert_eq!(&meta.name, &"bash-completion".to_string().into());
 
         // Now retrieve by provider.
@@ -613,5 +1044,6 @@ mod test {
         database.wipe().await.unwrap();
         let result = database.get(&id).await;
         assert!(result.is_err());
+        assert!(database.index().await.unwrap().is_none());
     }
 }
@@ -15,7 +15,7 @@ mod encoding {
     use sqlx::{Sqlite, Type};
     use thiserror::Error;
 
-    use crate::{dependency, package, state, Dependency, Provider};
+    use crate::{dependency, package, repository, state, Dependency, Provider};
 
     /// Decode from a database type using [`Encoding::decode`]
     #[derive(Debug, Clone, Copy)]
@@ -58,7 +58,6 @@ mod encoding {
     }
 
     /** Encoding on external types */
-
     /// Encoding of package identity (String)
     impl<'a> Encoding<'a> for package::Id {
         type Encoded = &'a str;
@@ -115,6 +114,20 @@ mod encoding {
         }
     }
 
+    /// Encoding of repository identity (String)
+    impl<'a> Encoding<'a> for repository::Id {
+        type Encoded = &'a str;
+        type Error = Infallible;
+
+        fn decode(encoded: &'a str) -> Result<Self, Self::Error> {
+            Ok(repository::Id::new(encoded.to_owned()))
+        }
+
+        fn encode(&'a self) -> &'a str {
+            self.as_ref()
+        }
+    }
+
     impl<'a> Encoding<'a> for state::Id {
         type Encoded = i64;
         type Error = Infallible;
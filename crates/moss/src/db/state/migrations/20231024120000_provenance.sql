@@ -0,0 +1,13 @@
+-- Audit info on how each state was created
+ALTER TABLE state ADD COLUMN command_line TEXT NULL;
+ALTER TABLE state ADD COLUMN user TEXT NULL;
+ALTER TABLE state ADD COLUMN uid INTEGER NULL;
+ALTER TABLE state ADD COLUMN version TEXT NULL;
+ALTER TABLE state ADD COLUMN duration BIGINT NULL;
+
+CREATE TABLE IF NOT EXISTS state_repositories (
+    state_id INTEGER NOT NULL,
+    repository TEXT NOT NULL,
+    index_hash TEXT NULL,
+    FOREIGN KEY(state_id) REFERENCES state(id) ON DELETE CASCADE
+);
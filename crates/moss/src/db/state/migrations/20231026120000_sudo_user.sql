@@ -0,0 +1,3 @@
+-- SUDO_USER is recorded separately from the user of the real uid, as any
+-- process can set it
+ALTER TABLE state ADD COLUMN sudo_user TEXT NULL;
@@ -2,13 +2,15 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+use std::time::Duration;
+
 use chrono::{DateTime, Utc};
 use sqlx::sqlite::SqliteConnectOptions;
 use sqlx::{Acquire, Executor, Pool, Sqlite};
 use thiserror::Error;
 
 use crate::db::Encoding;
-use crate::state::{self, Id, Selection};
+use crate::state::{self, Id, Provenance, RepositoryIndex, Selection};
 use crate::{Installation, State};
 
 #[derive(Debug)]
@@ -56,12 +58,20 @@ impl Database {
     pub async fn get(&self, id: &Id) -> Result<State, Error> {
         let state_query = sqlx::query_as::<_, encoding::State>(
             "
-            SELECT id, type, created, summary, description
+            SELECT id, type, created, summary, description, command_line, user, uid, sudo_user, version, duration
             FROM state
             WHERE id = ?;
             ",
         )
         .bind(id.encode());
+        let repositories_query = sqlx::query_as::<_, encoding::Repository>(
+            "
+            SELECT repository, index_hash
+            FROM state_repositories
+            WHERE state_id = ?;
+            ",
+        )
+        .bind(id.encode());
         let selections_query = sqlx::query_as::<_, encoding::Selection>(
             "
             SELECT package_id,
@@ -73,9 +83,10 @@ impl Database {
         )
         .bind(id.encode());
 
-        let (state, selections_rows) = futures::try_join!(
+        let (state, selections_rows, repositories_rows) = futures::try_join!(
             state_query.fetch_one(&self.pool),
-            selections_query.fetch_all(&self.pool)
+            selections_query.fetch_all(&self.pool),
+            repositories_query.fetch_all(&self.pool),
         )?;
 
         let selections = selections_rows
@@ -87,6 +98,23 @@ impl Database {
             })
             .collect();
 
+        // Only recorded since the provenance migration
+        let provenance = state.version.map(|version| Provenance {
+            command_line: state.command_line.unwrap_or_default(),
+            user: state.user,
+            uid: state.uid.unwrap_or_default() as u32,
+            sudo_user: state.sudo_user,
+            version,
+            repositories: repositories_rows
+                .into_iter()
+                .map(|row| RepositoryIndex {
+                    repository: row.repository.0,
+                    hash: row.index_hash,
+                })
+                .collect(),
+            duration: Duration::from_millis(state.duration.unwrap_or_default() as u64),
+        });
+
         Ok(State {
             id: state.id.0,
             summary: state.summary,
@@ -94,6 +122,7 @@ impl Database {
             selections,
             created: state.created,
             kind: state.kind.0,
+            provenance,
         })
     }
 
@@ -102,19 +131,26 @@ impl Database {
         selections: &[Selection],
         summary: Option<String>,
         description: Option<String>,
+        provenance: Option<&Provenance>,
     ) -> Result<State, Error> {
         let mut transaction = self.pool.begin().await?;
 
         let encoding::StateId { id } = sqlx::query_as::<_, encoding::StateId>(
             "
-            INSERT INTO state (type, summary, description)
-            VALUES (?, ?, ?)
+            INSERT INTO state (type, summary, description, command_line, user, uid, sudo_user, version, duration)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             RETURNING id;
             ",
         )
         .bind(state::Kind::Transaction.encode())
         .bind(summary)
         .bind(description)
+        .bind(provenance.map(|p| p.command_line.as_str()))
+        .bind(provenance.and_then(|p| p.user.as_deref()))
+        .bind(provenance.map(|p| p.uid as i64))
+        .bind(provenance.and_then(|p| p.sudo_user.as_deref()))
+        .bind(provenance.map(|p| p.version.as_str()))
+        .bind(provenance.map(|p| p.duration.as_millis() as i64))
         .fetch_one(transaction.acquire().await?)
         .await?;
 
@@ -137,6 +173,28 @@ impl Database {
                 .await?;
         }
 
+        let repositories = provenance
+            .map(|p| p.repositories.as_slice())
+            .unwrap_or_default();
+
+        if !repositories.is_empty() {
+            transaction
+                .execute(
+                    sqlx::QueryBuilder::new(
+                        "
+                    INSERT INTO state_repositories (state_id, repository, index_hash)
+                    ",
+                    )
+                    .push_values(repositories, |mut b, index| {
+                        b.push_bind(id.0.encode())
+                            .push_bind(index.repository.encode())
+                            .push_bind(index.hash.as_deref());
+                    })
+                    .build(),
+                )
+                .await?;
+        }
+
         transaction.commit().await?;
 
         let state = self.get(&id.0).await?;
@@ -184,7 +242,7 @@ mod encoding {
     use sqlx::FromRow;
 
     use super::{state, Id};
-    use crate::{db::Decoder, package};
+    use crate::{db::Decoder, package, repository};
 
     #[derive(FromRow)]
     pub struct Created {
@@ -200,6 +258,18 @@ mod encoding {
         pub created: DateTime<Utc>,
         pub summary: Option<String>,
         pub description: Option<String>,
+        pub command_line: Option<String>,
+        pub user: Option<String>,
+        pub uid: Option<i64>,
+        pub sudo_user: Option<String>,
+        pub version: Option<String>,
+        pub duration: Option<i64>,
+    }
+
+    #[derive(FromRow)]
+    pub struct Repository {
+        pub repository: Decoder<repository::Id>,
+        pub index_hash: Option<String>,
     }
 
     #[derive(FromRow)]
@@ -237,11 +307,25 @@ mod test {
             Selection::explicit(package::Id::from("pkg a".to_string())),
         ];
 
+        let provenance = Provenance {
+            command_line: "moss install 'pkg a'".to_string(),
+            user: Some("root".to_string()),
+            uid: 0,
+            sudo_user: Some("user".to_string()),
+            version: "0.1.0".to_string(),
+            repositories: vec![RepositoryIndex {
+                repository: crate::repository::Id::new("volatile".to_string()),
+                hash: Some("abc".to_string()),
+            }],
+            duration: Duration::from_millis(1500),
+        };
+
         let state = database
             .add(
                 &selections,
                 Some("test".to_string()),
                 Some("test".to_string()),
+                Some(&provenance),
             )
             .await
             .unwrap();
@@ -258,5 +342,6 @@ mod test {
         assert_eq!(state.description.as_deref(), Some("test"));
 
         assert_eq!(state.selections, selections);
+        assert_eq!(state.provenance, Some(provenance));
     }
 }
@@ -4,6 +4,7 @@
 
 use std::{fmt, str::FromStr};
 
+use itertools::Itertools;
 use stone::payload;
 use thiserror::Error;
 
@@ -37,6 +38,21 @@ pub enum Kind {
     PkgConfig32,
 }
 
+impl Kind {
+    /// Every known kind
+    pub const ALL: [Kind; 9] = [
+        Kind::PackageName,
+        Kind::SharedLibary,
+        Kind::PkgConfig,
+        Kind::Interpreter,
+        Kind::CMake,
+        Kind::Python,
+        Kind::Binary,
+        Kind::SystemBinary,
+        Kind::PkgConfig32,
+    ];
+}
+
 /// Custom pretty-print, i.e `pkgconfig(zlib)`
 impl fmt::Display for Kind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -69,7 +85,7 @@ impl FromStr for Kind {
             "binary" => Kind::Binary,
             "sysbinary" => Kind::SystemBinary,
             "pkgconfig32" => Kind::PkgConfig32,
-            _ => return Err(ParseError(s.to_string())),
+            _ => return Err(ParseError::UnknownKind(s.to_string())),
         })
     }
 }
@@ -171,10 +187,12 @@ impl FromStr for Provider {
 }
 
 fn parse(s: &str) -> Result<(Kind, String), ParseError> {
-    let (kind, rest) = s.split_once('(').ok_or(ParseError(s.to_string()))?;
+    let (kind, rest) = s
+        .split_once('(')
+        .ok_or(ParseError::Malformed(s.to_string()))?;
 
     if !rest.ends_with(')') {
-        return Err(ParseError(s.to_string()));
+        return Err(ParseError::Malformed(s.to_string()));
     }
 
     let kind = kind.parse()?;
@@ -185,5 +203,9 @@ fn parse(s: &str) -> Result<(Kind, String), ParseError> {
 }
 
 #[derive(Debug, Error)]
-#[error("Invalid dependency type: {0}")]
-pub struct ParseError(String);
+pub enum ParseError {
+    #[error("Invalid dependency {0}, expected kind(name) i.e. pkgconfig(zlib)")]
+    Malformed(String),
+    #[error("Unknown dependency kind {0}, expected one of: {}", Kind::ALL.iter().join(", "))]
+    UnknownKind(String),
+}
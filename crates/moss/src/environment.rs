@@ -14,3 +14,11 @@ pub const FILE_READ_BUFFER_SIZE: usize = 4 * 1024 * 1024;
 pub const FILE_READ_CHUNK_THRESHOLD: usize = 16 * 1024;
 /// DB batch size
 pub const DB_BATCH_SIZE: usize = 1000;
+/// Default seconds to wait for a connection, unless configured
+pub const CONNECT_TIMEOUT: u64 = 30;
+/// Default seconds to wait for a response or response chunk, unless configured
+pub const READ_TIMEOUT: u64 = 60;
+/// Default number of retries after a transient request error, unless configured
+pub const REQUEST_RETRIES: u32 = 2;
+/// Default milliseconds before the first retry, unless configured
+pub const REQUEST_RETRY_DELAY: u64 = 250;
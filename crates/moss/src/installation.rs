@@ -3,12 +3,13 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    fmt, fs,
+    fmt, fs, io,
     path::{Path, PathBuf},
 };
 
 use log::{trace, warn};
 use nix::unistd::{access, AccessFlags, Uid};
+use thiserror::Error;
 
 use crate::state;
 
@@ -86,6 +87,41 @@ impl Installation {
         }
     }
 
+    /// Create a new Installation at `root`, which may already exist
+    /// if it's empty or only holds moss directories without a state
+    ///
+    /// Unlike [`Installation::open`], failing to create the moss
+    /// directories is an error.
+    pub fn init(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let root: PathBuf = root.into();
+
+        if read_state_id(&root).is_some() {
+            return Err(Error::AlreadyInitialized(root));
+        }
+
+        match fs::read_dir(&root) {
+            Ok(entries) => {
+                let mut names = entries
+                    .map(|entry| entry.map(|entry| entry.file_name()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| Error::Read(root.clone(), e))?;
+                names.retain(|name| name != ".moss");
+
+                if !names.is_empty() {
+                    return Err(Error::NotEmpty(root));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::Read(root, e)),
+        }
+
+        for path in moss_dirs(&root) {
+            fs::create_dir_all(&path).map_err(|e| Error::CreateDir(path, e))?;
+        }
+
+        Ok(Self::open(root))
+    }
+
     /// Return true if we lack write access
     pub fn read_only(&self) -> bool {
         matches!(self.mutability, Mutability::ReadOnly)
@@ -166,15 +202,32 @@ fn read_legacy_state_id(usr_target: &Path) -> Option<state::Id> {
 
 /// Ensures moss directories are created
 fn ensure_dirs_exist(root: &Path) {
+    for path in moss_dirs(root) {
+        let _ = fs::create_dir_all(path);
+    }
+}
+
+/// Directories moss expects within `root`
+fn moss_dirs(root: &Path) -> [PathBuf; 5] {
     let moss = root.join(".moss");
 
-    for path in [
+    [
         moss.join("db"),
         moss.join("cache"),
         moss.join("assets"),
         moss.join("repo"),
         moss.join("root").join("staging"),
-    ] {
-        let _ = fs::create_dir_all(path);
-    }
+    ]
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} already has an active state")]
+    AlreadyInitialized(PathBuf),
+    #[error("{0} isn't empty and isn't a moss root")]
+    NotEmpty(PathBuf),
+    #[error("read {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("create directory {0}")]
+    CreateDir(PathBuf, #[source] io::Error),
 }
@@ -5,6 +5,7 @@
 // TODO: Remove once everything is hooked up
 #![allow(unused_variables, dead_code)]
 
+pub use self::architecture::Architectures;
 pub use self::client::Client;
 pub use self::dependency::{Dependency, Provider};
 pub use self::installation::Installation;
@@ -13,6 +14,7 @@ pub use self::registry::Registry;
 pub use self::repository::Repository;
 pub use self::state::State;
 
+pub mod architecture;
 pub mod client;
 pub mod db;
 pub mod dependency;
@@ -22,5 +24,6 @@ pub mod package;
 pub mod registry;
 pub mod repository;
 pub mod request;
+pub mod signature;
 pub mod state;
 pub mod stone;
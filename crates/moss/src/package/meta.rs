@@ -20,7 +20,7 @@ impl fmt::Display for Id {
 }
 
 /// The name of a [`Package`]
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Name(String);
 
 impl From<String> for Name {
@@ -74,12 +74,24 @@ pub struct Meta {
     pub dependencies: HashSet<Dependency>,
     /// All providers, including name()
     pub providers: HashSet<Provider>,
+    /// Dependencies required to build the source
+    pub build_dependencies: HashSet<Dependency>,
+    /// Providers which can't be installed alongside this package
+    pub conflicts: HashSet<Provider>,
+    /// Upstream uri of the source
+    pub source_uri: Option<String>,
+    /// Relative path of the source recipe within the upstream uri
+    pub source_path: Option<String>,
+    /// Ref / commit of the upstream source
+    pub source_ref: Option<String>,
     /// If relevant: uri to fetch from
     pub uri: Option<String>,
     /// If relevant: hash for the download
     pub hash: Option<String>,
     /// How big is this package in the repo..?
     pub download_size: Option<u64>,
+    /// Size of the unpacked assets, only known once cached
+    pub installed_size: Option<u64>,
 }
 
 impl Meta {
@@ -98,15 +110,29 @@ impl Meta {
         let uri = find_meta_string(payload, payload::meta::Tag::PackageURI).ok();
         let hash = find_meta_string(payload, payload::meta::Tag::PackageHash).ok();
         let download_size = find_meta_u64(payload, payload::meta::Tag::PackageSize).ok();
+        let source_uri = find_meta_string(payload, payload::meta::Tag::SourceURI).ok();
+        let source_path = find_meta_string(payload, payload::meta::Tag::SourcePath).ok();
+        let source_ref = find_meta_string(payload, payload::meta::Tag::SourceRef).ok();
 
         let licenses = payload
             .iter()
             .filter_map(|meta| meta_string(meta, payload::meta::Tag::License))
             .collect();
-        let dependencies = payload.iter().filter_map(meta_dependency).collect();
+        let dependencies = payload
+            .iter()
+            .filter_map(|meta| meta_dependency(meta, payload::meta::Tag::Depends))
+            .collect();
+        let build_dependencies = payload
+            .iter()
+            .filter_map(|meta| meta_dependency(meta, payload::meta::Tag::BuildDepends))
+            .collect();
+        let conflicts = payload
+            .iter()
+            .filter_map(|meta| meta_provider(meta, payload::meta::Tag::Conflicts))
+            .collect();
         let providers = payload
             .iter()
-            .filter_map(meta_provider)
+            .filter_map(|meta| meta_provider(meta, payload::meta::Tag::Provides))
             // Add package name as provider
             .chain(Some(Provider {
                 kind: dependency::Kind::PackageName,
@@ -127,9 +153,15 @@ impl Meta {
             licenses,
             dependencies,
             providers,
+            build_dependencies,
+            conflicts,
+            source_uri,
+            source_path,
+            source_ref,
             uri,
             hash,
             download_size,
+            installed_size: None,
         })
     }
 
@@ -148,6 +180,15 @@ impl Meta {
             (Tag::Homepage, Kind::String(self.homepage)),
         ]
         .into_iter()
+        .chain(
+            self.source_uri
+                .map(|uri| (Tag::SourceURI, Kind::String(uri))),
+        )
+        .chain(
+            self.source_path
+                .map(|path| (Tag::SourcePath, Kind::String(path))),
+        )
+        .chain(self.source_ref.map(|r| (Tag::SourceRef, Kind::String(r))))
         .chain(self.uri.map(|uri| (Tag::PackageURI, Kind::String(uri))))
         .chain(self.hash.map(|hash| (Tag::PackageHash, Kind::String(hash))))
         .chain(
@@ -176,6 +217,18 @@ impl Meta {
                     )
                 }),
         )
+        .chain(self.build_dependencies.into_iter().map(|dep| {
+            (
+                Tag::BuildDepends,
+                Kind::Dependency(dep.kind.into(), dep.name),
+            )
+        }))
+        .chain(self.conflicts.into_iter().map(|conflict| {
+            (
+                Tag::Conflicts,
+                Kind::Provider(conflict.kind.into(), conflict.name),
+            )
+        }))
         .map(|(tag, kind)| payload::Meta { tag, kind })
         .collect()
     }
@@ -189,6 +242,54 @@ impl Meta {
     }
 }
 
+/// Index record of a delta stone, which produces the `target`
+/// package from the cached assets of the `base` package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta {
+    /// Hash of the package this delta produces
+    pub target: String,
+    /// Hash of the package this delta applies to
+    pub base: String,
+    /// Relative uri to fetch from
+    pub uri: String,
+    /// Hash of the delta stone
+    pub hash: String,
+    /// Size of the delta stone
+    pub download_size: Option<u64>,
+}
+
+impl Delta {
+    pub fn from_stone_payload(
+        payload: &[stone::payload::Meta],
+    ) -> Result<Self, MissingMetaFieldError> {
+        Ok(Self {
+            target: find_meta_string(payload, payload::meta::Tag::DeltaTarget)?,
+            base: find_meta_string(payload, payload::meta::Tag::DeltaBase)?,
+            uri: find_meta_string(payload, payload::meta::Tag::PackageURI)?,
+            hash: find_meta_string(payload, payload::meta::Tag::PackageHash)?,
+            download_size: find_meta_u64(payload, payload::meta::Tag::PackageSize).ok(),
+        })
+    }
+
+    pub fn to_stone_payload(self) -> Vec<payload::Meta> {
+        use payload::meta::{Kind, Tag};
+
+        vec![
+            (Tag::DeltaTarget, Kind::String(self.target)),
+            (Tag::DeltaBase, Kind::String(self.base)),
+            (Tag::PackageURI, Kind::String(self.uri)),
+            (Tag::PackageHash, Kind::String(self.hash)),
+        ]
+        .into_iter()
+        .chain(
+            self.download_size
+                .map(|size| (Tag::PackageSize, Kind::Uint64(size))),
+        )
+        .map(|(tag, kind)| payload::Meta { tag, kind })
+        .collect()
+    }
+}
+
 fn find_meta_string(
     meta: &[payload::Meta],
     tag: payload::meta::Tag,
@@ -232,25 +333,31 @@ fn meta_string(meta: &payload::Meta, tag: payload::meta::Tag) -> Option<String>
     }
 }
 
-fn meta_dependency(meta: &payload::Meta) -> Option<Dependency> {
-    if let payload::meta::Kind::Dependency(kind, name) = meta.kind.clone() {
-        Some(Dependency {
-            kind: dependency::Kind::from(kind),
-            name,
-        })
-    } else {
-        None
+fn meta_dependency(meta: &payload::Meta, tag: payload::meta::Tag) -> Option<Dependency> {
+    match (meta.tag, meta.kind.clone()) {
+        (meta_tag, payload::meta::Kind::Dependency(kind, name)) if meta_tag == tag => {
+            Some(Dependency {
+                kind: dependency::Kind::from(kind),
+                name,
+            })
+        }
+        _ => None,
     }
 }
 
-fn meta_provider(meta: &payload::Meta) -> Option<Provider> {
-    if let payload::meta::Kind::Provider(kind, name) = meta.kind.clone() {
-        Some(Provider {
-            kind: dependency::Kind::from(kind),
-            name,
-        })
-    } else {
-        None
+fn meta_provider(meta: &payload::Meta, tag: payload::meta::Tag) -> Option<Provider> {
+    match (meta.tag, meta.kind.clone()) {
+        // Conflicts may be encoded as either kind
+        (meta_tag, payload::meta::Kind::Provider(kind, name))
+        | (meta_tag, payload::meta::Kind::Dependency(kind, name))
+            if meta_tag == tag =>
+        {
+            Some(Provider {
+                kind: dependency::Kind::from(kind),
+                name,
+            })
+        }
+        _ => None,
     }
 }
 
@@ -2,13 +2,19 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
+use std::{fmt, path::PathBuf};
+
 use bitflags::bitflags;
 use itertools::Itertools;
 
-pub use self::meta::{Meta, MissingMetaFieldError, Name};
+use crate::repository;
+
+pub use self::meta::{Delta, Meta, MissingMetaFieldError, Name};
+pub use self::spec::Spec;
 
 pub mod meta;
 pub mod render;
+pub mod spec;
 
 /// Unique ID of a [`Package`]
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -49,6 +55,46 @@ pub struct Package {
     pub id: Id,
     pub meta: Meta,
     pub flags: Flags,
+    pub source: Source,
+}
+
+/// Where a [`Package`] originates from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Installed in the active state
+    Installed,
+    /// A local stone file added to Cobble
+    Cobble(PathBuf),
+    /// Every repository providing the package, in order of priority
+    Repository(Vec<repository::Id>),
+}
+
+impl Source {
+    /// Record the repositories of `other` as additional origins of
+    /// this source. Returns `false` if either source isn't a repository.
+    pub fn merge(&mut self, other: &Source) -> bool {
+        match (self, other) {
+            (Source::Repository(ids), Source::Repository(others)) => {
+                for id in others {
+                    if !ids.contains(id) {
+                        ids.push(id.clone());
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Installed => write!(f, "installed"),
+            Source::Cobble(path) => write!(f, "{}", path.display()),
+            Source::Repository(ids) => write!(f, "{}", ids.iter().join(", ")),
+        }
+    }
 }
 
 impl PartialOrd for Package {
@@ -9,7 +9,7 @@ use tui::{
     Stylize,
 };
 
-use crate::Package;
+use crate::{package::Source, Package};
 
 /// We always pad columns by 4 spaces to just not jank up the output
 const COLUMN_PADDING: usize = 4;
@@ -25,11 +25,12 @@ impl ColumnDisplay for Package {
     }
 }
 
-impl<'a> ColumnDisplay for &'a Package {
+impl ColumnDisplay for &Package {
     fn get_display_width(&self) -> usize {
         self.meta.name.to_string().len()
             + self.meta.version_identifier.len()
             + self.meta.source_release.to_string().len()
+            + source_tag(self).len()
             + COLUMN_PADDING
     }
 
@@ -37,20 +38,31 @@ impl<'a> ColumnDisplay for &'a Package {
         let _ = match col {
             Column::Last => write!(
                 writer,
-                "{} {:width$}{}-{}",
+                "{} {:width$}{}-{}{}",
                 self.meta.name.to_string().bold(),
                 " ",
                 self.meta.version_identifier.clone().magenta(),
                 self.meta.source_release.to_string().dim(),
+                source_tag(self).blue(),
             ),
             _ => write!(
                 writer,
-                "{} {:width$}{}-{}   ",
+                "{} {:width$}{}-{}{}   ",
                 self.meta.name.to_string().bold(),
                 " ",
                 self.meta.version_identifier.clone().magenta(),
                 self.meta.source_release.to_string().dim(),
+                source_tag(self).blue(),
             ),
         };
     }
 }
+
+/// Installed packages are implied, otherwise show where
+/// the package is coming from
+fn source_tag(package: &Package) -> String {
+    match &package.source {
+        Source::Installed => String::default(),
+        source => format!(" [{source}]"),
+    }
+}
@@ -0,0 +1,106 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use super::Package;
+
+/// A user request for a package, optionally pinned to a specific
+/// version (`name@version`) or release (`name#release`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spec {
+    /// Package name, or provider when not pinned
+    pub name: String,
+    pub pin: Option<Pin>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    /// Matches the `version_identifier` of a package
+    Version(String),
+    /// Matches the `source_release` of a package
+    Release(u64),
+}
+
+impl Spec {
+    /// Returns true if `package` satisfies the pin of this spec
+    pub fn matches(&self, package: &Package) -> bool {
+        match &self.pin {
+            Some(Pin::Version(version)) => package.meta.version_identifier == *version,
+            Some(Pin::Release(release)) => package.meta.source_release == *release,
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.pin {
+            Some(Pin::Version(version)) => write!(f, "{}@{version}", self.name),
+            Some(Pin::Release(release)) => write!(f, "{}#{release}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl FromStr for Spec {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, pin) = if let Some((name, release)) = s.rsplit_once('#') {
+            let release = release
+                .parse()
+                .map_err(|_| ParseError::InvalidRelease(release.to_string()))?;
+            (name, Some(Pin::Release(release)))
+        } else if let Some((name, version)) = s.rsplit_once('@') {
+            if version.is_empty() {
+                return Err(ParseError::MissingVersion(s.to_string()));
+            }
+            (name, Some(Pin::Version(version.to_string())))
+        } else {
+            (s, None)
+        };
+
+        if name.is_empty() {
+            return Err(ParseError::MissingName(s.to_string()));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            pin,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("missing package name: {0}")]
+    MissingName(String),
+    #[error("missing version: {0}")]
+    MissingVersion(String),
+    #[error("invalid release number: {0}")]
+    InvalidRelease(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_spec() {
+        let spec = |s: &str| s.parse::<Spec>().unwrap();
+
+        assert_eq!(spec("nano").pin, None);
+        assert_eq!(spec("binary(nano)").name, "binary(nano)");
+        assert_eq!(spec("nano@7.2").pin, Some(Pin::Version("7.2".into())));
+        assert_eq!(spec("nano#12").pin, Some(Pin::Release(12)));
+        assert_eq!(spec("nano#12").name, "nano");
+
+        assert!("nano#latest".parse::<Spec>().is_err());
+        assert!("nano@".parse::<Spec>().is_err());
+        assert!("#12".parse::<Spec>().is_err());
+    }
+}
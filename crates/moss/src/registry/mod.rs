@@ -5,13 +5,15 @@
 //! Defines an encapsulation of "query plugins", including an interface
 //! for managing and using them.
 
+use std::collections::HashMap;
+
 use futures::{stream, Future, Stream, StreamExt};
 use itertools::Itertools;
 
 use crate::package::{self, Package};
-use crate::Provider;
+use crate::{Architectures, Provider};
 
-pub use self::plugin::Plugin;
+pub use self::plugin::{Error, Plugin};
 pub use self::transaction::Transaction;
 
 pub mod job;
@@ -23,37 +25,97 @@ pub mod transaction;
 #[derive(Debug, Default)]
 pub struct Registry {
     /// Ordered set of plugins
-    plugins: Vec<Plugin>,
+    plugins: Vec<Box<dyn Plugin>>,
+    /// Architectures candidates must be built for, all if unset
+    architectures: Option<Architectures>,
 }
 
 impl Registry {
+    /// Only yield candidates built for one of the [`Architectures`].
+    /// Installed packages are always yielded.
+    pub fn with_architectures(mut self, architectures: Architectures) -> Self {
+        self.architectures = Some(architectures);
+        self
+    }
+
+    /// The [`Architectures`] candidates are filtered by, if any
+    pub fn architectures(&self) -> Option<&Architectures> {
+        self.architectures.as_ref()
+    }
+
     /// Add a [`Plugin`] to the [`Registry`]
-    pub fn add_plugin(&mut self, plugin: Plugin) {
+    pub fn add_plugin(&mut self, plugin: Box<dyn Plugin>) {
         self.plugins.push(plugin);
     }
 
+    /// Returns true if `package` is installed or built for a supported architecture
+    fn is_supported(&self, package: &Package) -> bool {
+        package.flags.contains(package::Flags::INSTALLED)
+            || self
+                .architectures
+                .as_ref()
+                .is_none_or(|architectures| architectures.is_supported(&package.meta.architecture))
+    }
+
+    /// Query all plugins in order of priority, each plugin is only queried
+    /// once the packages of the previous plugin have been consumed
+    ///
+    /// A failing plugin yields its error in place of its packages, so
+    /// callers can tell a failure apart from an empty result.
     fn query<'a: 'b, 'b, F, I>(
         &'a self,
-        query: impl Fn(&'b Plugin) -> F + Copy + 'b,
-    ) -> impl Stream<Item = Package> + 'b
+        query: impl Fn(&'b dyn Plugin) -> F + Copy + 'b,
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b
     where
-        F: Future<Output = I>,
+        F: Future<Output = Result<I, Error>> + 'b,
+        I: IntoIterator<Item = Package>,
+    {
+        self.query_filtered(move |package| self.is_supported(package), query)
+    }
+
+    /// Query all plugins as per [`Registry::query`], only keeping packages
+    /// which match `filter`
+    fn query_filtered<'a: 'b, 'b, F, I>(
+        &'a self,
+        filter: impl Fn(&Package) -> bool + 'b,
+        query: impl Fn(&'b dyn Plugin) -> F + Copy + 'b,
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b
+    where
+        F: Future<Output = Result<I, Error>> + 'b,
         I: IntoIterator<Item = Package>,
     {
         stream::iter(
             self.plugins
                 .iter()
-                .sorted_by(|a, b| a.priority().cmp(&b.priority()).reverse())
-                .map(move |p| {
-                    stream::once(async move {
-                        let packages = query(p).await;
-
-                        stream::iter(packages)
-                    })
-                    .flatten()
-                }),
+                .map(Box::as_ref)
+                .sorted_by(|a, b| a.priority().cmp(&b.priority()).reverse()),
         )
-        .flatten()
+        .then(query)
+        .flat_map(move |result| {
+            let results = match result {
+                Ok(packages) => package::Sorted::new(packages.into_iter().filter(&filter))
+                    .into_iter()
+                    .map(Ok)
+                    .collect(),
+                Err(error) => vec![Err(error)],
+            };
+
+            stream::iter(results)
+        })
+    }
+
+    /// Query all plugins as per [`Registry::query`], deduplicating the
+    /// results with [`dedupe`]
+    fn query_deduped<'a: 'b, 'b, F, I>(
+        &'a self,
+        query: impl Fn(&'b dyn Plugin) -> F + Copy + 'b,
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b
+    where
+        F: Future<Output = Result<I, Error>> + 'b,
+        I: IntoIterator<Item = Package>,
+    {
+        stream::once(self.query(query).collect::<Vec<_>>())
+            .flat_map(|results| stream::iter(dedupe(results)))
     }
 
     /// Return a sorted stream of [`Package`] by provider
@@ -61,38 +123,62 @@ impl Registry {
         &'a self,
         provider: &'b Provider,
         flags: package::Flags,
-    ) -> impl Stream<Item = Package> + 'b {
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b {
         self.query(move |plugin| plugin.query_provider(provider, flags))
     }
 
+    /// Return a sorted stream of [`Package`] by provider which are only
+    /// excluded because they're built for an unsupported architecture
+    pub fn unsupported_by_provider<'a: 'b, 'b>(
+        &'a self,
+        provider: &'b Provider,
+        flags: package::Flags,
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b {
+        self.query_filtered(
+            move |package| !self.is_supported(package),
+            move |plugin| plugin.query_provider(provider, flags),
+        )
+    }
+
     /// Return a sorted stream of [`Package`] by name
     pub fn by_name<'a: 'b, 'b>(
         &'a self,
         package_name: &'b package::Name,
         flags: package::Flags,
-    ) -> impl Stream<Item = Package> + 'b {
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b {
         self.query(move |plugin| plugin.query_name(package_name, flags))
     }
 
-    /// Return a sorted stream of [`Package`] by id
-    pub fn by_id<'a: 'b, 'b>(&'a self, id: &'b package::Id) -> impl Stream<Item = Package> + 'b {
-        self.query(move |plugin| plugin.package(id))
+    /// Return a sorted stream of [`Package`] by id, deduplicated
+    /// so the [`package::Source`] records every repository
+    pub fn by_id<'a: 'b, 'b>(
+        &'a self,
+        id: &'b package::Id,
+    ) -> impl Stream<Item = Result<Package, Error>> + 'b {
+        self.query_deduped(move |plugin| plugin.package(id))
     }
 
-    /// Return a sorted stream of [`Package`] matching the given [`Flags`]
+    /// Return a sorted stream of [`Package`] matching the given [`Flags`],
+    /// deduplicated so the [`package::Source`] records every repository
     ///
     /// [`Flags`]: package::Flags
-    pub fn list(&self, flags: package::Flags) -> impl Stream<Item = Package> + '_ {
-        self.query(move |plugin| plugin.list(flags))
+    pub fn list(&self, flags: package::Flags) -> impl Stream<Item = Result<Package, Error>> + '_ {
+        self.query_deduped(move |plugin| plugin.list(flags))
     }
 
     /// Return a sorted stream of installed [`Package`]
-    pub fn list_installed(&self, flags: package::Flags) -> impl Stream<Item = Package> + '_ {
+    pub fn list_installed(
+        &self,
+        flags: package::Flags,
+    ) -> impl Stream<Item = Result<Package, Error>> + '_ {
         self.list(flags | package::Flags::INSTALLED)
     }
 
     /// Return a sorted stream of available [`Package`]
-    pub fn list_available(&self, flags: package::Flags) -> impl Stream<Item = Package> + '_ {
+    pub fn list_available(
+        &self,
+        flags: package::Flags,
+    ) -> impl Stream<Item = Result<Package, Error>> + '_ {
         self.list(flags | package::Flags::AVAILABLE)
     }
 
@@ -110,46 +196,60 @@ impl Registry {
     }
 }
 
+/// Merge packages with the same id from several repositories into the first,
+/// highest priority match, recording every repository in it's [`package::Source`]
+pub fn dedupe<E>(
+    packages: impl IntoIterator<Item = Result<Package, E>>,
+) -> Vec<Result<Package, E>> {
+    let mut results = Vec::<Result<Package, E>>::new();
+    let mut indices = HashMap::<package::Id, usize>::new();
+
+    for package in packages {
+        if let Ok(package) = &package {
+            if let Some(&index) = indices.get(&package.id) {
+                if let Ok(existing) = &mut results[index] {
+                    if existing.flags == package.flags && existing.source.merge(&package.source) {
+                        continue;
+                    }
+                }
+            } else {
+                indices.insert(package.id.clone(), results.len());
+            }
+        }
+
+        results.push(package);
+    }
+
+    results
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
 
+    use futures::TryStreamExt;
+
     use super::*;
+    use crate::repository;
 
     #[tokio::test]
     async fn test_ordering() {
         let mut registry = Registry::default();
 
-        let package = |id: &str, release| Package {
-            id: package::Id::from(id.to_string()),
-            meta: package::Meta {
-                name: package::Name::from(id.to_string()),
-                version_identifier: Default::default(),
-                source_release: release,
-                build_release: Default::default(),
-                architecture: Default::default(),
-                summary: Default::default(),
-                description: Default::default(),
-                source_id: Default::default(),
-                homepage: Default::default(),
-                licenses: Default::default(),
-                dependencies: Default::default(),
-                providers: Default::default(),
-                uri: Default::default(),
-                hash: Default::default(),
-                download_size: Default::default(),
-            },
-            flags: package::Flags::NONE,
+        let package = |id: &str, release| {
+            let mut package = plugin::test::package(id, package::Flags::NONE);
+            package.meta.source_release = release;
+            package
         };
 
-        registry.add_plugin(Plugin::Test(plugin::Test::new(
+        registry.add_plugin(Box::new(plugin::Test::new(
             // Priority
             1,
             // Id / release number
             vec![package("a", 0), package("b", 100)],
         )));
 
-        registry.add_plugin(Plugin::Test(plugin::Test::new(
+        registry.add_plugin(Box::new(plugin::Test::new(
             50,
             vec![package("c", 50), package("d", 1)],
         )));
@@ -158,6 +258,7 @@ mod test {
 
         // Packages are sorted by plugin priority, desc -> release number, desc
         while let Some((idx, package)) = query.next().await {
+            let package = package.unwrap();
             let id = |id: &str| package::Id::from(id.to_string());
 
             match idx {
@@ -174,29 +275,9 @@ mod test {
     async fn test_flags() {
         let mut registry = Registry::default();
 
-        let package = |id: &str, flags| Package {
-            id: package::Id::from(id.to_string()),
-            meta: package::Meta {
-                name: package::Name::from(id.to_string()),
-                version_identifier: Default::default(),
-                source_release: Default::default(),
-                build_release: Default::default(),
-                architecture: Default::default(),
-                summary: Default::default(),
-                description: Default::default(),
-                source_id: Default::default(),
-                homepage: Default::default(),
-                licenses: Default::default(),
-                dependencies: Default::default(),
-                providers: Default::default(),
-                uri: Default::default(),
-                hash: Default::default(),
-                download_size: Default::default(),
-            },
-            flags,
-        };
+        let package = plugin::test::package;
 
-        registry.add_plugin(Plugin::Test(plugin::test::Test::new(
+        registry.add_plugin(Box::new(plugin::test::Test::new(
             1,
             vec![
                 package("a", package::Flags::INSTALLED),
@@ -209,20 +290,24 @@ mod test {
 
         let installed = registry
             .list_installed(package::Flags::NONE)
-            .collect()
-            .await;
+            .try_collect()
+            .await
+            .unwrap();
         let available = registry
             .list_available(package::Flags::NONE)
-            .collect()
-            .await;
+            .try_collect()
+            .await
+            .unwrap();
         let installed_source = registry
             .list_installed(package::Flags::SOURCE)
-            .collect()
-            .await;
+            .try_collect()
+            .await
+            .unwrap();
         let available_source = registry
             .list_available(package::Flags::SOURCE)
-            .collect()
-            .await;
+            .try_collect()
+            .await
+            .unwrap();
 
         fn matches(actual: Vec<Package>, expected: &[&'static str]) -> bool {
             let actual = actual
@@ -242,4 +327,93 @@ mod test {
         assert!(matches(installed_source, &["d"]));
         assert!(matches(available_source, &["e"]));
     }
+
+    #[tokio::test]
+    async fn test_dedupe() {
+        let mut registry = Registry::default();
+
+        let package = |id: &str, repo: &str| Package {
+            source: package::Source::Repository(vec![repository::Id::new(repo.to_string())]),
+            ..plugin::test::package(id, package::Flags::AVAILABLE)
+        };
+
+        registry.add_plugin(Box::new(plugin::Test::new(
+            1,
+            vec![package("a", "low"), package("b", "low")],
+        )));
+        registry.add_plugin(Box::new(plugin::Test::new(50, vec![package("a", "high")])));
+
+        let packages = registry
+            .list(package::Flags::AVAILABLE)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let id = |id: &str| repository::Id::new(id.to_string());
+
+        // Same id is merged into the highest priority package
+        assert_eq!(packages.len(), 2);
+        assert_eq!(
+            packages[0].source,
+            package::Source::Repository(vec![id("high"), id("low")])
+        );
+        assert_eq!(
+            packages[1].source,
+            package::Source::Repository(vec![id("low")])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_architectures() {
+        let package = |id: &str, architecture: &str, flags| {
+            let mut package = plugin::test::package(id, flags);
+            package.meta.architecture = architecture.to_string();
+            package
+        };
+
+        let mut registry = Registry::default().with_architectures(Architectures::from_config(
+            crate::architecture::Config {
+                native: Some("x86_64".into()),
+                foreign: vec!["x86".into()],
+            },
+        ));
+
+        registry.add_plugin(Box::new(plugin::Test::new(
+            1,
+            vec![
+                package("a", "x86_64", package::Flags::AVAILABLE),
+                package("b", "x86", package::Flags::AVAILABLE),
+                package("c", "aarch64", package::Flags::AVAILABLE),
+                package("d", "aarch64", package::Flags::INSTALLED),
+            ],
+        )));
+
+        let names = |packages: Vec<Package>| {
+            packages
+                .into_iter()
+                .map(|p| String::from(p.meta.name))
+                .collect::<HashSet<_>>()
+        };
+
+        let listed = registry
+            .list(package::Flags::NONE)
+            .try_collect()
+            .await
+            .unwrap();
+        let unsupported = registry
+            .query_filtered(
+                |package| !registry.is_supported(package),
+                |plugin| plugin.list(package::Flags::NONE),
+            )
+            .try_collect()
+            .await
+            .unwrap();
+
+        // Installed packages are kept, whatever their architecture
+        assert_eq!(
+            names(listed),
+            HashSet::from(["a".into(), "b".into(), "d".into()])
+        );
+        assert_eq!(names(unsupported), HashSet::from(["c".into()]));
+    }
 }
@@ -2,9 +2,9 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use log::warn;
-
-use crate::{db, package, Package, Provider, State};
+use super::{self as plugin, Plugin};
+use crate::{db, package, registry::job::Job, Package, Provider, State};
+use futures::{future::BoxFuture, FutureExt};
 
 // TODO:
 #[derive(Debug, Clone)]
@@ -27,31 +27,20 @@ impl Active {
         Self { state, db }
     }
 
-    /// Query the given package
-    pub async fn package(&self, id: &package::Id) -> Option<Package> {
-        match self.db.get(id).await {
-            Ok(meta) => self.installed_package(id.clone(), meta),
-            Err(db::meta::Error::RowNotFound) => None,
-            Err(error) => {
-                warn!("failed to query installed package: {error}");
-                None
-            }
-        }
-    }
-
     /// Query, restricted to state
-    async fn query(&self, flags: package::Flags, filter: Option<db::meta::Filter>) -> Vec<Package> {
+    async fn query(
+        &self,
+        flags: package::Flags,
+        filter: Option<db::meta::Filter>,
+    ) -> Result<Vec<Package>, plugin::Error> {
         if flags.contains(package::Flags::INSTALLED) || flags == package::Flags::NONE {
-            // TODO: Error handling
-            let packages = match self.db.query(filter).await {
-                Ok(packages) => packages,
-                Err(error) => {
-                    warn!("failed to query repository packages: {error}");
-                    return vec![];
-                }
-            };
+            let packages = self
+                .db
+                .query(filter)
+                .await
+                .map_err(plugin::Error::Installed)?;
 
-            packages
+            Ok(packages
                 .into_iter()
                 .filter_map(|(id, meta)| self.installed_package(id, meta))
                 // Filter for explicit only packages, if applicable
@@ -62,37 +51,12 @@ impl Active {
                         true
                     }
                 })
-                .collect()
+                .collect())
         } else {
-            vec![]
+            Ok(vec![])
         }
     }
 
-    /// List, restricted to state
-    pub async fn list(&self, flags: package::Flags) -> Vec<Package> {
-        self.query(flags, None).await
-    }
-
-    /// Query all packages that match the given provider identity
-    pub async fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
-        self.query(flags, Some(db::meta::Filter::Provider(provider.clone())))
-            .await
-    }
-
-    /// Query matching by name
-    pub async fn query_name(
-        &self,
-        package_name: &package::Name,
-        flags: package::Flags,
-    ) -> Vec<Package> {
-        self.query(flags, Some(db::meta::Filter::Name(package_name.clone())))
-            .await
-    }
-
-    pub fn priority(&self) -> u64 {
-        u64::MAX
-    }
-
     fn installed_package(&self, id: package::Id, meta: package::Meta) -> Option<Package> {
         match &self.state {
             Some(st) => st
@@ -107,8 +71,63 @@ impl Active {
                     } else {
                         package::Flags::INSTALLED
                     },
+                    source: package::Source::Installed,
                 }),
             None => None,
         }
     }
 }
+
+impl Plugin for Active {
+    /// Query the given package
+    fn package<'a>(
+        &'a self,
+        id: &'a package::Id,
+    ) -> BoxFuture<'a, Result<Option<Package>, plugin::Error>> {
+        async move {
+            match self.db.get(id).await {
+                Ok(meta) => Ok(self.installed_package(id.clone(), meta)),
+                Err(db::meta::Error::RowNotFound) => Ok(None),
+                Err(error) => Err(plugin::Error::Installed(error)),
+            }
+        }
+        .boxed()
+    }
+
+    /// List, restricted to state
+    fn list(&self, flags: package::Flags) -> BoxFuture<'_, Result<Vec<Package>, plugin::Error>> {
+        self.query(flags, None).boxed()
+    }
+
+    /// Query all packages that match the given provider identity
+    fn query_provider<'a>(
+        &'a self,
+        provider: &'a Provider,
+        flags: package::Flags,
+    ) -> BoxFuture<'a, Result<Vec<Package>, plugin::Error>> {
+        self.query(flags, Some(db::meta::Filter::Provider(provider.clone())))
+            .boxed()
+    }
+
+    /// Query matching by name
+    fn query_name<'a>(
+        &'a self,
+        package_name: &'a package::Name,
+        flags: package::Flags,
+    ) -> BoxFuture<'a, Result<Vec<Package>, plugin::Error>> {
+        self.query(flags, Some(db::meta::Filter::Name(package_name.clone())))
+            .boxed()
+    }
+
+    fn priority(&self) -> u64 {
+        u64::MAX
+    }
+
+    /// Installed packages are already local, so there's nothing to fetch
+    fn fetch_item<'a>(
+        &'a self,
+        _id: &'a package::Id,
+    ) -> BoxFuture<'a, Result<Option<Job>, plugin::Error>> {
+        async { Ok(None) }.boxed()
+    }
+}
@@ -4,11 +4,12 @@
 
 use std::{collections::HashMap, path::PathBuf};
 
+use super::{self as plugin, Plugin};
 use crate::package::{self, meta, Meta, MissingMetaFieldError, Package};
-use crate::registry::job::Job;
+use crate::registry::job::{CheckType, Domain, Job, Origin};
 use crate::{stone, Provider};
 use ::stone::read::PayloadKind;
-use futures::StreamExt;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
 use thiserror::Error;
 
 // TODO:
@@ -48,14 +49,6 @@ impl Cobble {
         Ok(ret)
     }
 
-    pub fn package(&self, id: &package::Id) -> Option<Package> {
-        let meta_id = meta::Id::from(id.clone());
-
-        self.packages
-            .get(&meta_id)
-            .map(|state| state.package(id.clone()))
-    }
-
     fn query(&self, flags: package::Flags, filter: impl Fn(&Meta) -> bool) -> Vec<Package> {
         if flags.contains(package::Flags::AVAILABLE) {
             self.packages
@@ -67,25 +60,65 @@ impl Cobble {
             vec![]
         }
     }
+}
+
+impl Plugin for Cobble {
+    fn package<'a>(
+        &'a self,
+        id: &'a package::Id,
+    ) -> BoxFuture<'a, Result<Option<Package>, plugin::Error>> {
+        let meta_id = meta::Id::from(id.clone());
+
+        let package = self
+            .packages
+            .get(&meta_id)
+            .map(|state| state.package(id.clone()));
+
+        async move { Ok(package) }.boxed()
+    }
 
-    pub fn list(&self, flags: package::Flags) -> Vec<Package> {
-        self.query(flags, |_| true)
+    fn list(&self, flags: package::Flags) -> BoxFuture<'_, Result<Vec<Package>, plugin::Error>> {
+        let packages = self.query(flags, |_| true);
+        async move { Ok(packages) }.boxed()
     }
 
-    pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
-        self.query(flags, |meta| meta.providers.contains(provider))
+    fn query_provider<'a>(
+        &'a self,
+        provider: &'a Provider,
+        flags: package::Flags,
+    ) -> BoxFuture<'a, Result<Vec<Package>, plugin::Error>> {
+        let packages = self.query(flags, |meta| meta.providers.contains(provider));
+        async move { Ok(packages) }.boxed()
     }
 
-    pub fn query_name(&self, package_name: &package::Name, flags: package::Flags) -> Vec<Package> {
-        self.query(flags, |meta| meta.name == *package_name)
+    fn query_name<'a>(
+        &'a self,
+        package_name: &'a package::Name,
+        flags: package::Flags,
+    ) -> BoxFuture<'a, Result<Vec<Package>, plugin::Error>> {
+        let packages = self.query(flags, |meta| meta.name == *package_name);
+        async move { Ok(packages) }.boxed()
     }
 
-    pub fn priority(&self) -> u64 {
+    fn priority(&self) -> u64 {
         u64::MAX
     }
 
-    pub fn fetch_item(&self, id: &package::Id) -> Job {
-        todo!()
+    fn fetch_item<'a>(
+        &'a self,
+        id: &'a package::Id,
+    ) -> BoxFuture<'a, Result<Option<Job>, plugin::Error>> {
+        let job = self
+            .packages
+            .get(&meta::Id::from(id.clone()))
+            .map(|state| Job {
+                domain: Domain::Package(id.clone()),
+                origin: Origin::LocalFile(state.path.clone()),
+                check: state.meta.hash.clone().map(CheckType::Sha256),
+                size: state.meta.download_size.unwrap_or_default(),
+            });
+
+        async move { Ok(job) }.boxed()
     }
 }
 
@@ -102,6 +135,7 @@ impl State {
             meta: self.meta.clone(),
             // TODO: Is this correct flag?
             flags: package::Flags::AVAILABLE,
+            source: package::Source::Cobble(self.path.clone()),
         }
     }
 }
@@ -117,3 +151,25 @@ pub enum Error {
     #[error("metadata")]
     Metadata(#[from] MissingMetaFieldError),
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_item() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test/bash-completion-2.11-1-1-x86_64.stone");
+
+        let mut cobble = Cobble::default();
+        let id = package::Id::from(cobble.add_package(&path).await.unwrap());
+
+        let job = cobble.fetch_item(&id).await.unwrap().unwrap();
+        assert!(matches!(job.origin, Origin::LocalFile(local) if local == path));
+
+        let unknown = package::Id::from("unknown".to_string());
+        assert!(cobble.fetch_item(&unknown).await.unwrap().is_none());
+    }
+}
@@ -10,8 +10,13 @@
 //!
 //! [`Registry`]: super::Registry
 
+use std::fmt;
+
+use futures::future::BoxFuture;
+use thiserror::Error;
+
 use crate::registry::package::{self, Package};
-use crate::Provider;
+use crate::{db, Provider};
 
 pub use self::active::Active;
 pub use self::cobble::Cobble;
@@ -27,106 +32,77 @@ mod repository;
 
 /// A [`Registry`] plugin that enables querying [`Package`] information.
 ///
+/// Queries are async, returning boxed futures so plugins can be added
+/// to the [`Registry`] as trait objects. Results don't need to be sorted.
+///
 /// [`Registry`]: super::Registry
-#[derive(Debug, PartialEq, Eq)]
-pub enum Plugin {
-    Active(Active),
-    Cobble(Cobble),
-    Repository(Repository),
-
-    #[cfg(test)]
-    Test(Test),
-}
-
-impl Plugin {
+pub trait Plugin: fmt::Debug + Send + Sync {
     /// Return a package for the given [`package::Id`]. Returns `None` if
     /// the `package` cannot be located.
-    pub async fn package(&self, id: &package::Id) -> Option<Package> {
-        match self {
-            Plugin::Active(plugin) => plugin.package(id).await,
-            Plugin::Cobble(plugin) => plugin.package(id),
-            Plugin::Repository(plugin) => plugin.package(id).await,
-
-            #[cfg(test)]
-            Plugin::Test(plugin) => plugin.package(id),
-        }
-    }
+    fn package<'a>(&'a self, id: &'a package::Id) -> BoxFuture<'a, Result<Option<Package>, Error>>;
 
     /// List all packages with matching `flags`
-    pub async fn list(&self, flags: package::Flags) -> package::Sorted<Vec<Package>> {
-        package::Sorted::new(match self {
-            Plugin::Active(plugin) => plugin.list(flags).await,
-            Plugin::Cobble(plugin) => plugin.list(flags),
-            Plugin::Repository(plugin) => plugin.list(flags).await,
-
-            #[cfg(test)]
-            Plugin::Test(plugin) => plugin.list(flags),
-        })
-    }
+    fn list(&self, flags: package::Flags) -> BoxFuture<'_, Result<Vec<Package>, Error>>;
 
     /// Returns a list of packages with matching `provider` and `flags`
-    pub async fn query_provider(
-        &self,
-        provider: &Provider,
+    fn query_provider<'a>(
+        &'a self,
+        provider: &'a Provider,
         flags: package::Flags,
-    ) -> package::Sorted<Vec<Package>> {
-        package::Sorted::new(match self {
-            Plugin::Active(plugin) => plugin.query_provider(provider, flags).await,
-            Plugin::Cobble(plugin) => plugin.query_provider(provider, flags),
-            Plugin::Repository(plugin) => plugin.query_provider(provider, flags).await,
-
-            #[cfg(test)]
-            Plugin::Test(plugin) => plugin.query_provider(provider, flags),
-        })
-    }
+    ) -> BoxFuture<'a, Result<Vec<Package>, Error>>;
 
     /// Returns a list of packages with matching `package_name` and `flags`
-    pub async fn query_name(
-        &self,
-        package_name: &package::Name,
+    fn query_name<'a>(
+        &'a self,
+        package_name: &'a package::Name,
         flags: package::Flags,
-    ) -> package::Sorted<Vec<Package>> {
-        package::Sorted::new(match self {
-            Plugin::Active(plugin) => plugin.query_name(package_name, flags).await,
-            Plugin::Cobble(plugin) => plugin.query_name(package_name, flags),
-            Plugin::Repository(plugin) => plugin.query_name(package_name, flags).await,
-
-            #[cfg(test)]
-            Plugin::Test(plugin) => plugin.query_name(package_name, flags),
-        })
-    }
+    ) -> BoxFuture<'a, Result<Vec<Package>, Error>>;
 
     /// Plugin priority
     ///
     /// Higher priority = better chance of selection
-    pub fn priority(&self) -> u64 {
-        match self {
-            Plugin::Active(plugin) => plugin.priority(),
-            Plugin::Cobble(plugin) => plugin.priority(),
-            Plugin::Repository(plugin) => plugin.priority(),
-
-            #[cfg(test)]
-            Plugin::Test(plugin) => plugin.priority,
-        }
-    }
+    fn priority(&self) -> u64;
 
     /// Request that the item is fetched from its location into a storage
-    /// medium.
-    pub fn fetch_item(&self, id: &package::Id) -> Job {
-        match self {
-            Plugin::Active(_) => panic!("Active plugin queried for fetch"),
-            Plugin::Cobble(plugin) => plugin.fetch_item(id),
-            Plugin::Repository(plugin) => plugin.fetch_item(id),
+    /// medium. Returns `None` if the plugin can't fetch the package.
+    fn fetch_item<'a>(&'a self, id: &'a package::Id) -> BoxFuture<'a, Result<Option<Job>, Error>>;
+}
 
-            #[cfg(test)]
-            Plugin::Test(plugin) => plugin.fetch_item(id),
+/// A failed [`Plugin`] query
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("installed packages db")]
+    Installed(#[source] db::meta::Error),
+
+    #[error("meta db of repository {0}")]
+    Repository(crate::repository::Id, #[source] db::meta::Error),
+
+    #[error("plugin")]
+    Other(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    /// Returns the repository whose meta db is corrupt, if that's
+    /// the cause of this error
+    pub fn corrupt_repository(&self) -> Option<&crate::repository::Id> {
+        match self {
+            Error::Repository(id, error) if error.is_corrupt() => Some(id),
+            _ => None,
         }
     }
 }
 
 #[cfg(test)]
 pub mod test {
-    use std::path::PathBuf;
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use futures::FutureExt;
+    use sha2::{Digest, Sha256};
+    use stone::read::PayloadKind;
+    use url::Url;
 
     use super::*;
 
@@ -141,47 +117,128 @@ pub mod test {
             Self { priority, packages }
         }
 
-        pub fn package(&self, package: &package::Id) -> Option<Package> {
-            self.packages.iter().find(|p| p.id == *package).cloned()
-        }
-
-        pub fn list(&self, flags: package::Flags) -> Vec<Package> {
+        fn query(&self, filter: impl Fn(&Package) -> bool) -> Vec<Package> {
             self.packages
                 .iter()
-                .filter(|p| p.flags.contains(flags))
+                .filter(|p| filter(p))
                 .cloned()
                 .collect()
         }
+    }
 
-        pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
-            self.packages
-                .iter()
-                .filter(|p| p.meta.providers.contains(provider) && p.flags.contains(flags))
-                .cloned()
-                .collect()
+    impl Plugin for Test {
+        fn package<'a>(
+            &'a self,
+            id: &'a package::Id,
+        ) -> BoxFuture<'a, Result<Option<Package>, Error>> {
+            let package = self.packages.iter().find(|p| p.id == *id).cloned();
+            async move { Ok(package) }.boxed()
+        }
+
+        fn list(&self, flags: package::Flags) -> BoxFuture<'_, Result<Vec<Package>, Error>> {
+            let packages = self.query(|p| p.flags.contains(flags));
+            async move { Ok(packages) }.boxed()
         }
 
-        pub fn query_name(
-            &self,
-            package_name: &package::Name,
+        fn query_provider<'a>(
+            &'a self,
+            provider: &'a Provider,
             flags: package::Flags,
-        ) -> Vec<Package> {
-            self.packages
-                .iter()
-                .filter(|p| p.meta.name == *package_name && p.flags.contains(flags))
-                .cloned()
-                .collect()
+        ) -> BoxFuture<'a, Result<Vec<Package>, Error>> {
+            let packages =
+                self.query(|p| p.meta.providers.contains(provider) && p.flags.contains(flags));
+            async move { Ok(packages) }.boxed()
+        }
+
+        fn query_name<'a>(
+            &'a self,
+            package_name: &'a package::Name,
+            flags: package::Flags,
+        ) -> BoxFuture<'a, Result<Vec<Package>, Error>> {
+            let packages = self.query(|p| p.meta.name == *package_name && p.flags.contains(flags));
+            async move { Ok(packages) }.boxed()
+        }
+
+        fn priority(&self) -> u64 {
+            self.priority
         }
 
-        pub fn fetch_item(&self, id: &package::Id) -> Job {
-            Job {
+        fn fetch_item<'a>(
+            &'a self,
+            id: &'a package::Id,
+        ) -> BoxFuture<'a, Result<Option<Job>, Error>> {
+            let job = Job {
                 domain: crate::registry::job::Domain::Package(id.clone()),
                 origin: crate::registry::job::Origin::LocalFile(PathBuf::from(
                     "test/bash-completion-2.11-1-1-x86_64.stone",
                 )),
                 check: None,
                 size: 168864,
-            }
+            };
+            async move { Ok(Some(job)) }.boxed()
+        }
+    }
+
+    /// The test stone as an available package, fetched from its path
+    pub fn stone_package() -> Package {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test/bash-completion-2.11-1-1-x86_64.stone")
+            .canonicalize()
+            .unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let hash = hex::encode(Sha256::digest(&bytes));
+
+        let payloads = stone::read_bytes(&bytes)
+            .unwrap()
+            .payloads()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let payload = payloads.iter().find_map(PayloadKind::meta).unwrap();
+
+        let mut meta = package::Meta::from_stone_payload(&payload.body).unwrap();
+        meta.uri = Some(Url::from_file_path(&path).unwrap().to_string());
+        meta.hash = Some(hash.clone());
+        meta.download_size = Some(bytes.len() as u64);
+
+        Package {
+            id: package::Id::from(hash),
+            meta,
+            flags: package::Flags::AVAILABLE,
+            source: package::Source::Cobble(path),
+        }
+    }
+
+    /// An installed package named `name`, identified by it's name, with
+    /// `flags` & otherwise empty metadata
+    pub fn package(name: &str, flags: package::Flags) -> Package {
+        Package {
+            id: package::Id::from(name.to_string()),
+            meta: package::Meta {
+                name: package::Name::from(name.to_string()),
+                version_identifier: Default::default(),
+                source_release: Default::default(),
+                build_release: Default::default(),
+                architecture: Default::default(),
+                summary: Default::default(),
+                description: Default::default(),
+                source_id: Default::default(),
+                homepage: Default::default(),
+                licenses: Default::default(),
+                dependencies: Default::default(),
+                providers: Default::default(),
+                build_dependencies: Default::default(),
+                conflicts: Default::default(),
+                source_uri: Default::default(),
+                source_path: Default::default(),
+                source_ref: Default::default(),
+                uri: Default::default(),
+                hash: Default::default(),
+                download_size: Default::default(),
+                installed_size: Default::default(),
+            },
+            flags,
+            source: package::Source::Installed,
         }
     }
 }
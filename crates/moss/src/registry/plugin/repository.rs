@@ -2,14 +2,15 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use log::warn;
-
+use super::{self as plugin, Plugin};
 use crate::{
     db,
     package::{self, Package},
-    registry::job::Job,
+    registry::job::{CheckType, Domain, Job, Origin},
     repository, Provider,
 };
+use futures::{future::BoxFuture, FutureExt};
+use url::Url;
 
 #[derive(Debug)]
 pub struct Repository {
@@ -21,87 +22,126 @@ impl Repository {
         Self { active }
     }
 
-    pub fn priority(&self) -> u64 {
-        self.active.repository.priority.into()
-    }
-
-    pub async fn package(&self, id: &package::Id) -> Option<Package> {
-        let result = self.active.db.get(id).await;
-
-        match result {
-            Ok(meta) => Some(Package {
-                id: id.clone(),
-                meta: package::Meta {
-                    // TODO: Is there a more type-safe way to do this vs mutation? Can
-                    // a new type help here?
-                    uri: meta
-                        .uri
-                        .and_then(|relative| self.active.repository.uri.join(&relative).ok())
-                        .map(|url| url.to_string()),
-                    ..meta
-                },
-                flags: package::Flags::AVAILABLE,
-            }),
-            Err(db::meta::Error::RowNotFound) => None,
-            Err(error) => {
-                warn!("failed to query repository package: {error}");
-                None
-            }
-        }
+    fn source(&self) -> package::Source {
+        package::Source::Repository(vec![self.active.id.clone()])
     }
 
-    async fn query(&self, flags: package::Flags, filter: Option<db::meta::Filter>) -> Vec<Package> {
+    async fn query(
+        &self,
+        flags: package::Flags,
+        filter: Option<db::meta::Filter>,
+    ) -> Result<Vec<Package>, plugin::Error> {
         if flags.contains(package::Flags::AVAILABLE) || flags == package::Flags::NONE {
-            // TODO: Error handling
-            let packages = match self.active.db.query(filter).await {
-                Ok(packages) => packages,
-                Err(error) => {
-                    warn!("failed to query repository packages: {error}");
-                    return vec![];
-                }
-            };
+            let packages = self
+                .active
+                .db
+                .query(filter)
+                .await
+                .map_err(|error| self.error(error))?;
 
-            packages
+            Ok(packages
                 .into_iter()
                 .map(|(id, meta)| Package {
                     id,
                     meta,
                     flags: package::Flags::AVAILABLE,
+                    source: self.source(),
                 })
-                .collect()
+                .collect())
         } else {
-            vec![]
+            Ok(vec![])
         }
     }
 
-    pub async fn list(&self, flags: package::Flags) -> Vec<Package> {
-        self.query(flags, None).await
+    fn error(&self, error: db::meta::Error) -> plugin::Error {
+        plugin::Error::Repository(self.active.id.clone(), error)
+    }
+}
+
+impl PartialEq for Repository {
+    fn eq(&self, other: &Self) -> bool {
+        self.active.id.eq(&other.active.id)
+    }
+}
+
+impl Eq for Repository {}
+
+impl Plugin for Repository {
+    fn package<'a>(
+        &'a self,
+        id: &'a package::Id,
+    ) -> BoxFuture<'a, Result<Option<Package>, plugin::Error>> {
+        async move {
+            let result = self.active.db.get(id).await;
+
+            match result {
+                Ok(meta) => Ok(Some(Package {
+                    id: id.clone(),
+                    meta: package::Meta {
+                        // TODO: Is there a more type-safe way to do this vs mutation? Can
+                        // a new type help here?
+                        uri: meta
+                            .uri
+                            .and_then(|relative| self.active.repository.uri.join(&relative).ok())
+                            .map(|url| url.to_string()),
+                        ..meta
+                    },
+                    flags: package::Flags::AVAILABLE,
+                    source: self.source(),
+                })),
+                Err(db::meta::Error::RowNotFound) => Ok(None),
+                Err(error) => Err(self.error(error)),
+            }
+        }
+        .boxed()
+    }
+
+    fn list(&self, flags: package::Flags) -> BoxFuture<'_, Result<Vec<Package>, plugin::Error>> {
+        self.query(flags, None).boxed()
     }
 
     /// Query all packages that match the given provider identity
-    pub async fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
+    fn query_provider<'a>(
+        &'a self,
+        provider: &'a Provider,
+        flags: package::Flags,
+    ) -> BoxFuture<'a, Result<Vec<Package>, plugin::Error>> {
         self.query(flags, Some(db::meta::Filter::Provider(provider.clone())))
-            .await
+            .boxed()
     }
 
-    pub async fn query_name(
-        &self,
-        package_name: &package::Name,
+    fn query_name<'a>(
+        &'a self,
+        package_name: &'a package::Name,
         flags: package::Flags,
-    ) -> Vec<Package> {
+    ) -> BoxFuture<'a, Result<Vec<Package>, plugin::Error>> {
         self.query(flags, Some(db::meta::Filter::Name(package_name.clone())))
-            .await
+            .boxed()
     }
 
-    pub fn fetch_item(&self, id: &package::Id) -> Job {
-        todo!()
+    fn priority(&self) -> u64 {
+        self.active.repository.priority.into()
     }
-}
 
-impl PartialEq for Repository {
-    fn eq(&self, other: &Self) -> bool {
-        self.active.id.eq(&other.active.id)
+    fn fetch_item<'a>(
+        &'a self,
+        id: &'a package::Id,
+    ) -> BoxFuture<'a, Result<Option<Job>, plugin::Error>> {
+        async move {
+            let Some(package) = self.package(id).await? else {
+                return Ok(None);
+            };
+            let Some(url) = package.meta.uri.and_then(|uri| uri.parse::<Url>().ok()) else {
+                return Ok(None);
+            };
+
+            Ok(Some(Job {
+                domain: Domain::Package(id.clone()),
+                origin: Origin::RemoteFile(url),
+                check: package.meta.hash.map(CheckType::Sha256),
+                size: package.meta.download_size.unwrap_or_default(),
+            }))
+        }
+        .boxed()
     }
 }
-
-impl Eq for Repository {}
@@ -3,11 +3,11 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use dag::Dag;
-use futures::{StreamExt, TryFutureExt};
+use futures::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{package, Provider, Registry};
+use crate::{package, registry, Provider, Registry};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Id(u64);
@@ -102,9 +102,12 @@ impl<'a> Transaction<'a> {
                 let check_node = self.packages.add_node_or_get_index(check_id.clone());
 
                 // Grab this package in question
-                let matches = self.registry.by_id(check_id).collect::<Vec<_>>().await;
-                let package = matches
-                    .first()
+                let package = self
+                    .registry
+                    .by_id(check_id)
+                    .boxed()
+                    .try_next()
+                    .await?
                     .ok_or(Error::NoCandidate(check_id.clone().into()))?;
                 for dependency in package.meta.dependencies.iter() {
                     let provider = Provider {
@@ -147,51 +150,50 @@ impl<'a> Transaction<'a> {
                 .registry
                 .by_provider(&provider, package::Flags::AVAILABLE)
                 .boxed()
-                .next()
-                .await
+                .try_next()
+                .await?
                 .map(|p| p.id.clone())
                 .ok_or(Error::NoCandidate(provider.to_string())),
             ProviderFilter::InstalledOnly(provider) => self
                 .registry
                 .by_provider(&provider, package::Flags::INSTALLED)
                 .boxed()
-                .next()
-                .await
+                .try_next()
+                .await?
                 .map(|p| p.id.clone())
                 .ok_or(Error::NoCandidate(provider.to_string())),
             ProviderFilter::Selections(provider) => self
                 .registry
                 .by_provider(&provider, package::Flags::NONE)
-                .filter_map(|f| async {
-                    if self.packages.node_exists(&f.id) {
-                        Some(f)
-                    } else {
-                        None
-                    }
+                .try_filter(|f| {
+                    let selected = self.packages.node_exists(&f.id);
+                    async move { selected }
                 })
                 .boxed()
-                .next()
-                .await
+                .try_next()
+                .await?
                 .map(|p| p.id.clone())
                 .ok_or(Error::NoCandidate(provider.to_string())),
         }
     }
 
-    // Try all strategies to resolve a provider for installation
+    // Try all strategies to resolve a provider for installation,
+    // only moving on when the previous one has no candidate
     async fn resolve_installation_provider(
         &self,
         provider: Provider,
     ) -> Result<package::Id, Error> {
-        self.resolve_provider(ProviderFilter::Selections(provider.clone()))
-            .or_else(|_| async {
-                self.resolve_provider(ProviderFilter::InstalledOnly(provider.clone()))
-                    .await
-            })
-            .or_else(|_| async {
-                self.resolve_provider(ProviderFilter::All(provider.clone()))
-                    .await
-            })
-            .await
+        for filter in [
+            ProviderFilter::Selections(provider.clone()),
+            ProviderFilter::InstalledOnly(provider.clone()),
+        ] {
+            match self.resolve_provider(filter).await {
+                Err(Error::NoCandidate(_)) => {}
+                result => return result,
+            }
+        }
+
+        self.resolve_provider(ProviderFilter::All(provider)).await
     }
 }
 
@@ -205,4 +207,7 @@ pub enum Error {
 
     #[error("meta db")]
     Database(#[from] crate::db::meta::Error),
+
+    #[error("registry")]
+    Registry(#[from] registry::Error),
 }
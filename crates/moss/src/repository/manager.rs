@@ -3,16 +3,20 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use futures::{future, StreamExt, TryStreamExt};
+use chrono::{DateTime, Utc};
+use futures::{future, TryStreamExt};
 use thiserror::Error;
 use tokio::{fs, io};
+use url::Url;
 use xxhash_rust::xxh3::xxh3_64;
 
+use crate::client::progress::{self, Event, Reporter};
 use crate::db::meta;
-use crate::{environment, stone};
-use crate::{package, Installation};
+use crate::{environment, request, signature, stone, Package};
+use crate::{package, state, Installation};
 
 use crate::repository::{self, Repository};
 
@@ -38,6 +42,7 @@ pub struct Manager {
     source: Source,
     installation: Installation,
     repositories: HashMap<repository::Id, repository::Active>,
+    reporter: Arc<dyn Reporter>,
 }
 
 impl Manager {
@@ -84,7 +89,7 @@ impl Manager {
         // Open all repo meta dbs and collect into hash map
         let repositories =
             future::try_join_all(configs.into_iter().map(|(id, repository)| async {
-                let db = open_meta_db(source.identifier(), &repository, &installation).await?;
+                let db = open_meta_db(source.identifier(), &id, &repository, &installation).await?;
 
                 Ok::<_, Error>((id.clone(), repository::Active { id, repository, db }))
             }))
@@ -96,10 +101,25 @@ impl Manager {
             source,
             installation,
             repositories,
+            reporter: Arc::new(progress::Tui::new()),
         })
     }
 
+    /// Report failing mirrors to `reporter` instead of the terminal
+    pub fn with_reporter(mut self, reporter: impl Reporter + 'static) -> Self {
+        self.reporter = Arc::new(reporter);
+        self
+    }
+
+    pub(crate) fn set_reporter(&mut self, reporter: Arc<dyn Reporter>) {
+        self.reporter = reporter;
+    }
+
     /// Add a [`Repository`]
+    ///
+    /// If it replaces a repository with a different trusted key or
+    /// insecure flag, it's meta db is wiped as the cached index wasn't
+    /// verified under the new settings & must be fetched in full.
     pub async fn add_repository(
         &mut self,
         id: repository::Id,
@@ -118,7 +138,23 @@ impl Manager {
             config.save(&id, &map).await.map_err(Error::SaveConfig)?;
         }
 
-        let db = open_meta_db(self.source.identifier(), &repository, &self.installation).await?;
+        let db = open_meta_db(
+            self.source.identifier(),
+            &id,
+            &repository,
+            &self.installation,
+        )
+        .await?;
+
+        let trust_changed = self.repositories.get(&id).is_some_and(|previous| {
+            previous.repository.public_key != repository.public_key
+                || previous.repository.insecure != repository.insecure
+        });
+        if trust_changed {
+            db.wipe()
+                .await
+                .map_err(|error| Error::Database(id.clone(), error))?;
+        }
 
         self.repositories
             .insert(id.clone(), repository::Active { id, repository, db });
@@ -126,14 +162,58 @@ impl Manager {
         Ok(())
     }
 
-    /// Refresh all [`Repository`]'s by fetching it's latest index
+    /// Remove the [`Repository`] `id` along with it's cached index & meta db
+    ///
+    /// Fails if the repository isn't configured by a saved config, i.e. only
+    /// by a vendor config
+    pub async fn remove_repository(&mut self, id: &repository::Id) -> Result<(), Error> {
+        let Source::System(config) = &self.source else {
+            return Err(Error::ExplicitUnsupported);
+        };
+
+        let active = self
+            .repositories
+            .get(id)
+            .ok_or_else(|| Error::UnknownRepo(id.clone()))?;
+
+        if !config
+            .delete::<repository::Map>(id)
+            .await
+            .map_err(Error::DeleteConfig)?
+        {
+            return Err(Error::VendorRepo(id.clone()));
+        }
+
+        let dir = cache_dir(
+            self.source.identifier(),
+            &active.repository,
+            &self.installation,
+        );
+
+        self.repositories.remove(id);
+
+        match fs::remove_dir_all(&dir).await {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(Error::RemoveDir(error)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refresh all enabled [`Repository`]'s by fetching it's latest index
     /// file and updating it's associated meta database
     pub async fn refresh_all(&mut self) -> Result<(), Error> {
         // Fetch index file + add to meta_db
         future::try_join_all(
-            self.repositories.iter().map(|(id, state)| {
-                refresh_index(self.source.identifier(), state, &self.installation)
-            }),
+            self.repositories
+                .values()
+                .filter(|state| state.repository.enabled)
+                .map(|state| {
+                    refresh_index(
+                        self.source.identifier(),
+                        state,
+                        &self.installation,
+                        self.reporter.as_ref(),
+                    )
+                }),
         )
         .await?;
 
@@ -143,7 +223,13 @@ impl Manager {
     /// Refresh a [`Repository`] by Id
     pub async fn refresh(&mut self, id: &repository::Id) -> Result<(), Error> {
         if let Some(repo) = self.repositories.get(id) {
-            refresh_index(self.source.identifier(), repo, &self.installation).await
+            refresh_index(
+                self.source.identifier(),
+                repo,
+                &self.installation,
+                self.reporter.as_ref(),
+            )
+            .await
         } else {
             Err(Error::UnknownRepo(id.clone()))
         }
@@ -154,6 +240,135 @@ impl Manager {
         self.repositories.values().cloned()
     }
 
+    /// Return the [`Repository`] for the given Id, if known
+    pub fn get(&self, id: &repository::Id) -> Option<&Repository> {
+        self.repositories.get(id).map(|state| &state.repository)
+    }
+
+    /// Returns when the [`Repository`] was last refreshed, if ever
+    pub async fn last_refreshed(
+        &self,
+        id: &repository::Id,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        let repo = self
+            .repositories
+            .get(id)
+            .ok_or_else(|| Error::UnknownRepo(id.clone()))?;
+
+        Ok(repo
+            .db
+            .index()
+            .await
+            .map_err(|error| Error::Database(id.clone(), error))?
+            .map(|index| index.refreshed))
+    }
+
+    /// Hash of the current index of each enabled [`Repository`], as
+    /// recorded in the provenance of new states
+    pub async fn index_hashes(&self) -> Result<Vec<state::RepositoryIndex>, Error> {
+        let mut indexes = future::try_join_all(
+            self.repositories
+                .values()
+                .filter(|state| state.repository.enabled)
+                .map(|state| async {
+                    let index = state
+                        .db
+                        .index()
+                        .await
+                        .map_err(|error| Error::Database(state.id.clone(), error))?;
+
+                    Ok::<_, Error>(state::RepositoryIndex {
+                        repository: state.id.clone(),
+                        hash: index.map(|index| index.hash),
+                    })
+                }),
+        )
+        .await?;
+
+        indexes.sort_by(|a, b| a.repository.as_ref().cmp(b.repository.as_ref()));
+
+        Ok(indexes)
+    }
+
+    /// Every url `package` can be fetched from, across the mirrors
+    /// of each repository providing it, in order of preference
+    pub(crate) async fn package_urls(&self, package: &Package) -> Result<Vec<Url>, Error> {
+        let package::Source::Repository(ids) = &package.source else {
+            return Ok(vec![]);
+        };
+
+        let mut urls = vec![];
+
+        for (id, state) in ids
+            .iter()
+            .filter_map(|id| Some((id, self.repositories.get(id)?)))
+        {
+            let meta = match state.db.get(&package.id).await {
+                Ok(meta) => meta,
+                Err(meta::Error::RowNotFound) => continue,
+                Err(error) => return Err(Error::Database(id.clone(), error)),
+            };
+
+            // Index uris are relative to the repository
+            let Some(relative) = meta.uri else {
+                continue;
+            };
+
+            for base in state.repository.uris() {
+                if let Ok(url) = base.join(&relative) {
+                    if !urls.contains(&url) {
+                        urls.push(url);
+                    }
+                }
+            }
+        }
+
+        Ok(urls)
+    }
+
+    /// Every delta producing `package` with the urls it can be fetched
+    /// from, across the repositories providing it
+    pub(crate) async fn package_deltas(
+        &self,
+        package: &Package,
+    ) -> Result<Vec<(package::Delta, Vec<Url>)>, Error> {
+        let package::Source::Repository(ids) = &package.source else {
+            return Ok(vec![]);
+        };
+
+        let mut deltas: Vec<(package::Delta, Vec<Url>)> = vec![];
+
+        for (id, state) in ids
+            .iter()
+            .filter_map(|id| Some((id, self.repositories.get(id)?)))
+        {
+            let found = state
+                .db
+                .deltas(&package.id)
+                .await
+                .map_err(|error| Error::Database(id.clone(), error))?;
+
+            for delta in found {
+                // Index uris are relative to the repository
+                let urls = state
+                    .repository
+                    .uris()
+                    .filter_map(|base| base.join(&delta.uri).ok())
+                    .collect::<Vec<_>>();
+
+                match deltas
+                    .iter_mut()
+                    .find(|(existing, _)| existing.hash == delta.hash)
+                {
+                    Some((_, existing)) => existing.extend(urls),
+                    None => deltas.push((delta, urls)),
+                }
+            }
+        }
+
+        Ok(deltas)
+    }
+
     /// List all of the known repositories
     pub fn list(&self) -> impl ExactSizeIterator<Item = (&repository::Id, &Repository)> {
         self.repositories
@@ -162,6 +377,30 @@ impl Manager {
     }
 }
 
+/// Remove the cached index & meta db of the system [`Repository`] `id`, so
+/// it's fetched from scratch on the next refresh
+///
+/// A [`Manager`] can't be created while a meta db is corrupt, so this works
+/// from the configs directly.
+pub async fn discard_cache(
+    config: &config::Manager,
+    installation: &Installation,
+    id: &repository::Id,
+) -> Result<(), Error> {
+    let configs = config.load::<repository::Map>().await.unwrap_or_default();
+
+    let repository = configs
+        .get(id)
+        .ok_or_else(|| Error::UnknownRepo(id.clone()))?;
+
+    let dir = cache_dir(environment::NAME, repository, installation);
+
+    match fs::remove_dir_all(&dir).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(Error::RemoveDir(error)),
+        _ => Ok(()),
+    }
+}
+
 /// Directory for the repo cached data (db & stone index), hashed by identifier & repo URI
 fn cache_dir(identifier: &str, repo: &Repository, installation: &Installation) -> PathBuf {
     let hash = format!(
@@ -175,6 +414,7 @@ fn cache_dir(identifier: &str, repo: &Repository, installation: &Installation) -
 /// directory exists
 async fn open_meta_db(
     identifier: &str,
+    id: &repository::Id,
     repo: &Repository,
     installation: &Installation,
 ) -> Result<meta::Database, Error> {
@@ -182,18 +422,23 @@ async fn open_meta_db(
 
     fs::create_dir_all(&dir).await.map_err(Error::CreateDir)?;
 
-    let db = meta::Database::new(dir.join("db"), installation.read_only()).await?;
+    let db = meta::Database::new(dir.join("db"), installation.read_only())
+        .await
+        .map_err(|error| Error::Database(id.clone(), error))?;
 
     Ok(db)
 }
 
 /// Fetches a stone index file from the repository URL,
 /// saves it to the repo installation path, then
-/// loads it's metadata into the meta db
+/// applies it's changes to the meta db
+///
+/// Nothing is loaded if the index is unchanged since the last refresh
 async fn refresh_index(
     identifier: &str,
     state: &repository::Active,
     installation: &Installation,
+    reporter: &dyn Reporter,
 ) -> Result<(), Error> {
     let out_dir = cache_dir(identifier, &state.repository, installation);
 
@@ -202,58 +447,189 @@ async fn refresh_index(
         .map_err(Error::CreateDir)?;
 
     let out_path = out_dir.join("stone.index");
+    // Only replaces the last good index once verified
+    let part_path = out_dir.join("stone.index.part");
+
+    let db_error = |error| Error::Database(state.id.clone(), error);
+
+    let previous = state.db.index().await.map_err(db_error)?;
+    let validators = previous
+        .as_ref()
+        .map(|index| request::Validators {
+            etag: index.etag.clone(),
+            last_modified: index.last_modified.clone(),
+        })
+        .unwrap_or_default();
+
+    // Fetch index from the first available mirror & write to `part_path`
+    let uris = state.repository.uris().cloned().collect::<Vec<_>>();
+    let (url, fetched) = repository::fetch_index(&uris, &part_path, &validators, |url, error| {
+        reporter.report(Event::MirrorFailed { url, error })
+    })
+    .await?;
+
+    // Everything published alongside the index is fetched from the same mirror
+    // where possible, failing over to the others
+    let urls = repository::preferring(&url, &uris);
+
+    // Deltas are published independently of the index, so they're refreshed
+    // even when the index is unchanged
+    let Some(fetched) = fetched else {
+        if let Some(previous) = previous {
+            let deltas = refresh_deltas(state, &urls, &out_dir, reporter).await?;
+
+            state
+                .db
+                .replace_deltas(
+                    deltas,
+                    &meta::Index {
+                        refreshed: Utc::now(),
+                        ..previous
+                    },
+                )
+                .await
+                .map_err(db_error)?;
+        }
+        return Ok(());
+    };
 
-    // Fetch index & write to `out_path`
-    repository::fetch_index(state.repository.uri.clone(), &out_path).await?;
+    // Verify the index before it's loaded, leaving the db & last good index untouched otherwise
+    if let Err(error) = verify_index(state, &urls, &part_path, reporter).await {
+        let _ = fs::remove_file(&part_path).await;
+        return Err(error);
+    }
 
-    // Wipe db since we're refreshing from a new index file
-    state.db.wipe().await?;
+    fs::rename(&part_path, &out_path)
+        .await
+        .map_err(Error::WriteIndex)?;
+
+    let index = meta::Index {
+        hash: fetched.hash,
+        etag: fetched.validators.etag,
+        last_modified: fetched.validators.last_modified,
+        refreshed: Utc::now(),
+    };
+
+    // Same contents, only the validators & deltas may have changed
+    if previous.is_some_and(|previous| previous.hash == index.hash) {
+        let deltas = refresh_deltas(state, &urls, &out_dir, reporter).await?;
+
+        state
+            .db
+            .replace_deltas(deltas, &index)
+            .await
+            .map_err(db_error)?;
+        return Ok(());
+    }
 
-    // Get a stream of payloads
-    let (_, payloads) = stone::stream_payloads(&out_path).await?;
+    let payloads = read_meta_payloads(&out_path).await?;
+
+    let mut packages = vec![];
+
+    // Construct Meta for each package in the index
+    for payload in payloads {
+        let meta = package::Meta::from_stone_payload(&payload)?;
+
+        // Create id from hash of meta
+        let hash = meta.hash.clone().ok_or(Error::MissingMetaField(
+            stone::payload::meta::Tag::PackageHash,
+        ))?;
+        let id = package::Id::from(hash);
+
+        packages.push((id, meta));
+    }
+
+    let deltas = refresh_deltas(state, &urls, &out_dir, reporter).await?;
+
+    state
+        .db
+        .apply_diff(packages, deltas, &index)
+        .await
+        .map_err(db_error)?;
+
+    Ok(())
+}
+
+/// Fetch, verify & read the deltas index published alongside the index at `urls`
+async fn refresh_deltas(
+    state: &repository::Active,
+    urls: &[Url],
+    out_dir: &Path,
+    reporter: &dyn Reporter,
+) -> Result<Vec<package::Delta>, Error> {
+    let out_path = out_dir.join(repository::DELTAS_INDEX);
+    let part_path = out_dir.join(format!("{}.part", repository::DELTAS_INDEX));
+
+    let Some(urls) = repository::fetch_deltas(urls, &part_path, |url, error| {
+        reporter.report(Event::MirrorFailed { url, error })
+    })
+    .await?
+    else {
+        // Repository no longer publishes deltas
+        let _ = fs::remove_file(&out_path).await;
+        return Ok(vec![]);
+    };
+
+    if let Err(error) = verify_index(state, &urls, &part_path, reporter).await {
+        let _ = fs::remove_file(&part_path).await;
+        return Err(error);
+    }
+
+    fs::rename(&part_path, &out_path)
+        .await
+        .map_err(Error::WriteIndex)?;
+
+    Ok(read_meta_payloads(&out_path)
+        .await?
+        .iter()
+        .map(|payload| package::Delta::from_stone_payload(payload))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Read the body of every meta payload in the index at `path`
+async fn read_meta_payloads(path: &Path) -> Result<Vec<Vec<stone::payload::Meta>>, Error> {
+    let (_, payloads) = stone::stream_payloads(path).await?;
 
-    // Update each payload into the meta db
     payloads
         .map_err(Error::ReadStone)
-        // Batch up to `DB_BATCH_SIZE` payloads
-        .chunks(environment::DB_BATCH_SIZE)
-        // Transpose error for early bail
-        .map(|results| results.into_iter().collect::<Result<Vec<_>, _>>())
-        .try_for_each(|payloads| async {
-            // Construct Meta for each payload
-            let packages = payloads
-                .into_iter()
-                .filter_map(|payload| {
-                    if let stone::read::PayloadKind::Meta(meta) = payload {
-                        Some(meta)
-                    } else {
-                        None
-                    }
-                })
-                .map(|payload| {
-                    let meta = package::Meta::from_stone_payload(&payload.body)?;
-
-                    // Create id from hash of meta
-                    let hash = meta.hash.clone().ok_or(Error::MissingMetaField(
-                        stone::payload::meta::Tag::PackageHash,
-                    ))?;
-                    let id = package::Id::from(hash);
-
-                    Ok((id, meta))
-                })
-                .collect::<Result<Vec<_>, Error>>()?;
-
-            // Batch add to db
-            //
-            // Sqlite supports up to 32k parametized query binds. Adding a
-            // package has 13 binds x 1k batch size = 17k. This leaves us
-            // overhead to add more binds in the future, otherwise we can
-            // lower the `DB_BATCH_SIZE`.
-            state.db.batch_add(packages).await.map_err(Error::Database)
+        .try_filter_map(|payload| async {
+            Ok(match payload {
+                stone::read::PayloadKind::Meta(payload) => Some(payload.body),
+                _ => None,
+            })
         })
-        .await?;
+        .try_collect()
+        .await
+}
 
-    Ok(())
+/// Verify the index fetched from the first of `urls` to `path` was signed
+/// by the trusted key of the repository, unless it's marked as insecure
+async fn verify_index(
+    state: &repository::Active,
+    urls: &[Url],
+    path: &Path,
+    reporter: &dyn Reporter,
+) -> Result<(), Error> {
+    if state.repository.insecure {
+        return Ok(());
+    }
+
+    let key = state
+        .repository
+        .public_key
+        .as_ref()
+        .ok_or_else(|| Error::MissingKey(state.id.clone()))?;
+
+    let signature = repository::fetch_signature(urls, |url, error| {
+        reporter.report(Event::MirrorFailed { url, error })
+    })
+    .await
+    .map_err(|error| Error::Unsigned(state.id.clone(), error))?;
+
+    let bytes = fs::read(path).await.map_err(Error::ReadIndex)?;
+
+    key.verify(&bytes, &signature)
+        .map_err(|error| Error::InvalidSignature(state.id.clone(), error))
 }
 
 #[derive(Debug, Error)]
@@ -266,16 +642,41 @@ pub enum Error {
     CreateDir(#[source] io::Error),
     #[error("remove directory")]
     RemoveDir(#[source] io::Error),
+    #[error("write index file")]
+    WriteIndex(#[source] io::Error),
     #[error("fetch index file")]
     FetchIndex(#[from] repository::FetchError),
     #[error("read index file")]
     ReadStone(#[from] stone::read::Error),
-    #[error("meta db")]
-    Database(#[from] meta::Error),
+    #[error("meta db of repository {0}")]
+    Database(repository::Id, #[source] meta::Error),
     #[error("save config")]
     SaveConfig(#[source] config::SaveError),
+    #[error("delete config")]
+    DeleteConfig(#[source] config::SaveError),
     #[error("unknown repo")]
     UnknownRepo(repository::Id),
+    #[error("repository {0} is provided by a vendor config, disable it instead")]
+    VendorRepo(repository::Id),
+    #[error("repository {0} has no trusted key, add one or mark it as insecure")]
+    MissingKey(repository::Id),
+    #[error("index signature of repository {0} couldn't be fetched")]
+    Unsigned(repository::Id, #[source] repository::FetchError),
+    #[error("index of repository {0} failed signature verification")]
+    InvalidSignature(repository::Id, #[source] signature::Error),
+    #[error("read index file")]
+    ReadIndex(#[source] io::Error),
+}
+
+impl Error {
+    /// Returns the repository whose meta db is corrupt, if that's
+    /// the cause of this error
+    pub fn corrupt_repository(&self) -> Option<&repository::Id> {
+        match self {
+            Error::Database(id, error) if error.is_corrupt() => Some(id),
+            _ => None,
+        }
+    }
 }
 
 impl From<package::MissingMetaFieldError> for Error {
@@ -283,3 +684,238 @@ impl From<package::MissingMetaFieldError> for Error {
         Self::MissingMetaField(error.0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, io::Cursor, sync::Mutex};
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{registry::plugin, signature::SecretKey};
+
+    const SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+
+    /// Requests received by [`serve`], as the path & whether it was conditional
+    type Requests = Arc<Mutex<Vec<(String, bool)>>>;
+
+    /// Serve a signed index on a local port, answering conditional
+    /// requests for it's etag with 304 Not Modified
+    ///
+    /// Unless `sidecars`, everything but the index is unavailable
+    async fn serve(index: Vec<u8>, requests: Requests, sidecars: bool) -> Url {
+        let signature = SECRET_KEY.parse::<SecretKey>().unwrap().sign(&index);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/stone.index", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0; 4096];
+                let read = socket.read(&mut request).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                let conditional = request.contains("if-none-match: \"v1\"");
+                requests.lock().unwrap().push((path.clone(), conditional));
+
+                let (status, body) = match path.as_str() {
+                    path if !sidecars && path != "/stone.index" => {
+                        ("503 Service Unavailable", vec![])
+                    }
+                    "/stone.index" if conditional => ("304 Not Modified", vec![]),
+                    "/stone.index" => ("200 OK", index.clone()),
+                    "/stone.index.sig" => ("200 OK", signature.to_string().into_bytes()),
+                    _ => ("404 Not Found", vec![]),
+                };
+
+                let head = format!(
+                    "HTTP/1.1 {status}\r\netag: \"v1\"\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+
+        url.parse().unwrap()
+    }
+
+    fn request(path: &str, conditional: bool) -> (String, bool) {
+        (path.to_string(), conditional)
+    }
+
+    /// An index listing the test stone
+    fn index() -> Vec<u8> {
+        let meta = plugin::test::stone_package().meta.to_stone_payload();
+
+        let mut bytes = Cursor::new(vec![]);
+        let mut writer =
+            ::stone::Writer::new(&mut bytes, ::stone::header::v1::FileType::Repository).unwrap();
+        writer.add_payload(meta.as_slice()).unwrap();
+        writer.finalize().unwrap();
+
+        bytes.into_inner()
+    }
+
+    #[tokio::test]
+    async fn refresh_not_modified() {
+        let root = tempfile::tempdir().unwrap();
+        let requests = Requests::default();
+        let url = serve(index(), requests.clone(), true).await;
+
+        let mut manager = Manager::system(
+            config::Manager::system(root.path(), "moss"),
+            Installation::open(root.path()),
+        )
+        .await
+        .unwrap()
+        .with_reporter(progress::Silent);
+
+        let id = repository::Id::new("test".to_string());
+        let repository = |public_key: Option<&str>| Repository {
+            description: String::default(),
+            uri: url.clone(),
+            mirrors: vec![],
+            priority: repository::Priority::new(0),
+            enabled: true,
+            public_key: public_key.map(|key| key.parse().unwrap()),
+            insecure: public_key.is_none(),
+        };
+        let packages = |manager: &Manager| {
+            let db = manager.repositories[&id].db.clone();
+            async move { db.query(None).await.unwrap().len() }
+        };
+        let take = || std::mem::take(&mut *requests.lock().unwrap());
+
+        manager
+            .add_repository(id.clone(), repository(None))
+            .await
+            .unwrap();
+        manager.refresh(&id).await.unwrap();
+        assert_eq!(packages(&manager).await, 1);
+        assert_eq!(
+            take(),
+            [
+                request("/stone.index", false),
+                request("/deltas.index", false)
+            ]
+        );
+
+        // Unchanged upstream, the insecure index is kept & only deltas are refreshed
+        manager.refresh(&id).await.unwrap();
+        assert_eq!(packages(&manager).await, 1);
+        assert_eq!(
+            take(),
+            [
+                request("/stone.index", true),
+                request("/deltas.index", false)
+            ]
+        );
+
+        // A key the index wasn't signed with wipes the unverified index &
+        // fails the full refetch instead of accepting a 304
+        let other_key = "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c";
+        manager
+            .add_repository(id.clone(), repository(Some(other_key)))
+            .await
+            .unwrap();
+        assert_eq!(packages(&manager).await, 0);
+
+        let result = manager.refresh(&id).await;
+        assert!(matches!(result, Err(Error::InvalidSignature(..))));
+        assert_eq!(packages(&manager).await, 0);
+        assert_eq!(
+            take(),
+            [
+                request("/stone.index", false),
+                request("/stone.index.sig", false)
+            ]
+        );
+
+        // The right key verifies the full refetch, after which 304s are trusted again
+        let public_key = SECRET_KEY
+            .parse::<SecretKey>()
+            .unwrap()
+            .public_key()
+            .to_string();
+        manager
+            .add_repository(id.clone(), repository(Some(&public_key)))
+            .await
+            .unwrap();
+        manager.refresh(&id).await.unwrap();
+        assert_eq!(packages(&manager).await, 1);
+        assert_eq!(
+            take(),
+            [
+                request("/stone.index", false),
+                request("/stone.index.sig", false),
+                request("/deltas.index", false),
+            ]
+        );
+
+        manager.refresh(&id).await.unwrap();
+        assert_eq!(packages(&manager).await, 1);
+        assert_eq!(
+            take(),
+            [
+                request("/stone.index", true),
+                request("/deltas.index", false)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_mirror_sidecars() {
+        let root = tempfile::tempdir().unwrap();
+        let primary = Requests::default();
+        let mirror = Requests::default();
+
+        let public_key = SECRET_KEY.parse::<SecretKey>().unwrap().public_key();
+        let repository = Repository {
+            description: String::default(),
+            uri: serve(index(), primary.clone(), false).await,
+            mirrors: vec![serve(index(), mirror.clone(), true).await],
+            priority: repository::Priority::new(0),
+            enabled: true,
+            public_key: Some(public_key),
+            insecure: false,
+        };
+        let id = repository::Id::new("test".to_string());
+
+        let mut manager = Manager::system(
+            config::Manager::system(root.path(), "moss"),
+            Installation::open(root.path()),
+        )
+        .await
+        .unwrap()
+        .with_reporter(progress::Silent);
+
+        manager
+            .add_repository(id.clone(), repository)
+            .await
+            .unwrap();
+
+        // The signature & deltas index fail over to the mirror when the primary is unavailable
+        manager.refresh(&id).await.unwrap();
+
+        let paths = |requests: &Requests| {
+            requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(path, _)| path.clone())
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(
+            paths(&primary),
+            HashSet::from(["/stone.index", "/stone.index.sig", "/deltas.index"].map(String::from))
+        );
+        assert_eq!(
+            paths(&mirror),
+            HashSet::from(["/stone.index.sig", "/deltas.index"].map(String::from))
+        );
+    }
+}
@@ -7,6 +7,7 @@ use std::{collections::HashMap, fmt, path::Path};
 use config::Config;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use tokio::{
     fs::File,
@@ -14,12 +15,19 @@ use tokio::{
 };
 use url::Url;
 
-use crate::{db::meta, request};
+use crate::{
+    db::meta,
+    request,
+    signature::{self, Signature},
+};
 
 pub use self::manager::Manager;
 
 pub mod manager;
 
+/// File name of the deltas index, published alongside `stone.index`
+pub const DELTAS_INDEX: &str = "deltas.index";
+
 /// A unique [`Repository`] identifier
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Id(String);
@@ -41,12 +49,42 @@ impl fmt::Display for Id {
     }
 }
 
+impl AsRef<str> for Id {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
 /// Repository configuration data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Repository {
     pub description: String,
     pub uri: Url,
+    /// Alternative index uris, tried in order when `uri` is unavailable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Url>,
     pub priority: Priority,
+    /// Disabled repositories keep their meta db, but aren't
+    /// refreshed or queried for packages
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// Trusted key which must have signed the index
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<signature::PublicKey>,
+    /// Skip verification of the index signature
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure: bool,
+}
+
+impl Repository {
+    /// The index uri followed by all mirrors, in order of preference
+    pub fn uris(&self) -> impl Iterator<Item = &Url> {
+        std::iter::once(&self.uri).chain(&self.mirrors)
+    }
+}
+
+fn enabled_by_default() -> bool {
+    true
 }
 
 /// An active repository that has been
@@ -133,8 +171,97 @@ impl Config for Map {
     }
 }
 
-async fn fetch_index(url: Url, out_path: impl AsRef<Path>) -> Result<(), FetchError> {
-    let mut stream = request::get(url).await?;
+/// A freshly fetched index
+struct FetchedIndex {
+    validators: request::Validators,
+    /// Sha256 hash of the index contents
+    hash: String,
+}
+
+/// Fetch the index from the first available of `urls` to `out_path`,
+/// unless it's unchanged since it was fetched with `validators`
+///
+/// Returns the mirror which responded along with the index, if changed
+async fn fetch_index(
+    urls: &[Url],
+    out_path: impl AsRef<Path>,
+    validators: &request::Validators,
+    on_failover: impl Fn(&Url, &request::Error),
+) -> Result<(Url, Option<FetchedIndex>), FetchError> {
+    let (url, response) = request::failover(
+        urls,
+        |url| request::get_if_modified(url, validators),
+        on_failover,
+    )
+    .await?;
+
+    let (validators, mut stream) = match response {
+        request::Conditional::NotModified => return Ok((url, None)),
+        request::Conditional::Modified(validators, stream) => (validators, stream),
+    };
+
+    let mut out = File::create(out_path).await?;
+    let mut hasher = Sha256::new();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        out.write_all(&chunk).await?;
+    }
+
+    out.flush().await?;
+
+    Ok((
+        url,
+        Some(FetchedIndex {
+            validators,
+            hash: hex::encode(hasher.finalize()),
+        }),
+    ))
+}
+
+/// `urls` reordered so `url`, i.e. the mirror which served the index, is tried first
+fn preferring(url: &Url, urls: &[Url]) -> Vec<Url> {
+    std::iter::once(url)
+        .chain(urls.iter().filter(|other| *other != url))
+        .cloned()
+        .collect()
+}
+
+/// Fetch the deltas index published alongside the index at each of `urls`
+/// to `out_path`, failing over to the next mirror when one is unavailable
+///
+/// Deltas live in their own index so clients unaware of them can still read the
+/// package index. Returns the urls of the deltas index starting with the mirror
+/// it was fetched from, or `None` if the repository doesn't publish one.
+async fn fetch_deltas(
+    urls: &[Url],
+    out_path: impl AsRef<Path>,
+    on_failover: impl Fn(&Url, &request::Error),
+) -> Result<Option<Vec<Url>>, FetchError> {
+    let urls = urls
+        .iter()
+        .map(|url| url.join(DELTAS_INDEX))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Mirrors are copies of the repository, so one without a deltas index
+    // means none is published
+    let (url, stream) = request::failover(
+        &urls,
+        |url| async move {
+            match request::get(url).await {
+                Ok(stream) => Ok(Some(stream)),
+                Err(error) if error.is_not_found() => Ok(None),
+                Err(error) => Err(error),
+            }
+        },
+        on_failover,
+    )
+    .await?;
+
+    let Some(mut stream) = stream else {
+        return Ok(None);
+    };
 
     let mut out = File::create(out_path).await?;
 
@@ -144,7 +271,31 @@ async fn fetch_index(url: Url, out_path: impl AsRef<Path>) -> Result<(), FetchEr
 
     out.flush().await?;
 
-    Ok(())
+    Ok(Some(preferring(&url, &urls)))
+}
+
+/// Fetch the detached signature of the index at the first available of `urls`
+async fn fetch_signature(
+    urls: &[Url],
+    on_failover: impl Fn(&Url, &request::Error),
+) -> Result<Signature, FetchError> {
+    let urls = urls
+        .iter()
+        .map(|url| {
+            let mut url = url.clone();
+            url.set_path(&format!("{}.{}", url.path(), signature::EXTENSION));
+            url
+        })
+        .collect::<Vec<_>>();
+
+    let (_, mut stream) = request::failover(&urls, request::get, on_failover).await?;
+    let mut bytes = vec![];
+
+    while let Some(chunk) = stream.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+
+    Ok(String::from_utf8_lossy(&bytes).parse()?)
 }
 
 #[derive(Debug, Error)]
@@ -153,4 +304,8 @@ pub enum FetchError {
     Request(#[from] request::Error),
     #[error("io")]
     Io(#[from] io::Error),
+    #[error("signature")]
+    Signature(#[from] signature::Error),
+    #[error("url")]
+    Url(#[from] url::ParseError),
 }
@@ -2,49 +2,296 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, path::PathBuf};
+use std::{fs, future::Future, io, path::PathBuf, sync::RwLock, time::Duration};
 
 use bytes::Bytes;
 use futures::{
     stream::{self, BoxStream},
-    Stream, StreamExt,
+    StreamExt,
 };
 use once_cell::sync::Lazy;
+use reqwest::{header, Certificate, NoProxy, Proxy, StatusCode};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::{fs::File, io::AsyncReadExt};
+use tokio::{fs::File, io::AsyncReadExt, time};
 use tokio_util::io::ReaderStream;
 use url::Url;
 
 use crate::environment;
 
 /// Shared client for tcp socket reuse and connection limit
-static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
-    reqwest::ClientBuilder::new()
-        .user_agent(concat!(
-            env!("CARGO_PKG_NAME"),
-            "/",
-            env!("CARGO_PKG_VERSION")
-        ))
-        .build()
-        .expect("build reqwest client")
-});
+static CLIENT: Lazy<RwLock<Client>> =
+    Lazy::new(|| RwLock::new(Client::new(&Config::default()).expect("build reqwest client")));
+
+/// Network configuration, loaded from the `network` config domain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Proxy for all requests, otherwise the `HTTP_PROXY`, `HTTPS_PROXY`
+    /// & `ALL_PROXY` env vars are used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Url>,
+    /// Comma separated hosts which bypass `proxy`, otherwise the
+    /// `NO_PROXY` env var is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_proxy: Option<String>,
+    /// PEM encoded CA certificates to trust in addition to the built-in roots
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ca_certificates: Vec<PathBuf>,
+    /// Seconds to wait for a connection to be established
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for a response or the next chunk of it's body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<u64>,
+    /// Number of times a request is retried after a transient error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Milliseconds to wait before the first retry, doubled for each subsequent retry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay: Option<u64>,
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "network".into()
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            proxy: other.proxy.or(self.proxy),
+            no_proxy: other.no_proxy.or(self.no_proxy),
+            ca_certificates: self
+                .ca_certificates
+                .into_iter()
+                .chain(other.ca_certificates)
+                .collect(),
+            connect_timeout: other.connect_timeout.or(self.connect_timeout),
+            read_timeout: other.read_timeout.or(self.read_timeout),
+            retries: other.retries.or(self.retries),
+            retry_delay: other.retry_delay.or(self.retry_delay),
+        }
+    }
+}
+
+/// Apply the network [`Config`] to all subsequent requests
+pub fn configure(config: &Config) -> Result<(), Error> {
+    let client = Client::new(config)?;
+
+    *CLIENT.write().unwrap() = client;
+
+    Ok(())
+}
+
+/// Load the network [`Config`] from `manager` and apply it to all subsequent requests
+pub async fn load_config(manager: &config::Manager) -> Result<(), Error> {
+    configure(&manager.load::<Config>().await.unwrap_or_default())
+}
+
+/// A [`reqwest::Client`] with it's configured retry & read timeout behaviour
+#[derive(Clone)]
+struct Client {
+    http: reqwest::Client,
+    read_timeout: Duration,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl Client {
+    fn new(config: &Config) -> Result<Self, Error> {
+        let mut builder = reqwest::ClientBuilder::new()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .connect_timeout(Duration::from_secs(
+                config
+                    .connect_timeout
+                    .unwrap_or(environment::CONNECT_TIMEOUT),
+            ));
+
+        // Setting a proxy disables the env proxies
+        if let Some(proxy) = &config.proxy {
+            let no_proxy = match &config.no_proxy {
+                Some(hosts) => NoProxy::from_string(hosts),
+                None => NoProxy::from_env(),
+            };
+
+            builder = builder.proxy(Proxy::all(proxy.clone())?.no_proxy(no_proxy));
+        }
+
+        for path in &config.ca_certificates {
+            let pem =
+                fs::read(path).map_err(|error| Error::ReadCertificate(path.clone(), error))?;
+
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+
+        Ok(Self {
+            http: builder.build()?,
+            read_timeout: Duration::from_secs(
+                config.read_timeout.unwrap_or(environment::READ_TIMEOUT),
+            ),
+            retries: config.retries.unwrap_or(environment::REQUEST_RETRIES),
+            retry_delay: Duration::from_millis(
+                config
+                    .retry_delay
+                    .unwrap_or(environment::REQUEST_RETRY_DELAY),
+            ),
+        })
+    }
+
+    /// Send the request built by `request`, retrying with exponential
+    /// backoff on transient errors
+    async fn send(
+        &self,
+        request: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, Error> {
+        let mut attempt = 0;
+
+        loop {
+            let result = match time::timeout(self.read_timeout, request(&self.http).send()).await {
+                Ok(result) => result
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(Error::Fetch),
+                Err(_) => Err(Error::Timeout),
+            };
+
+            match result {
+                Err(error) if error.is_transient() && attempt < self.retries => {
+                    time::sleep(self.retry_delay * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Stream the body of `response`, failing if a chunk
+    /// isn't received within the read timeout
+    fn stream(&self, response: reqwest::Response) -> BoxStream<'static, Result<Bytes, Error>> {
+        tokio_stream::StreamExt::timeout(response.bytes_stream(), self.read_timeout)
+            .map(|result| match result {
+                Ok(chunk) => chunk.map_err(Error::Fetch),
+                Err(_) => Err(Error::Timeout),
+            })
+            .boxed()
+    }
+}
+
+/// Returns the configured [`Client`]
+fn client() -> Client {
+    CLIENT.read().unwrap().clone()
+}
+
+/// Cache validators of a previously fetched resource
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Response of a conditional request
+pub enum Conditional {
+    /// The resource is unchanged since it was last fetched
+    NotModified,
+    /// The resource has changed, along with it's new validators
+    Modified(Validators, BoxStream<'static, Result<Bytes, Error>>),
+}
 
 /// Fetch a resource at the provided [`Url`] and stream it's response bytes
 pub async fn get(url: Url) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
     match url_file(&url) {
         Some(path) => read(path).await,
-        _ => Ok(fetch(url).await?.boxed()),
+        _ => fetch(url).await,
+    }
+}
+
+/// Fetch a resource at the provided [`Url`] only if it's changed since
+/// it was last fetched with `validators`
+///
+/// Local files are always considered modified
+pub async fn get_if_modified(url: Url, validators: &Validators) -> Result<Conditional, Error> {
+    if let Some(path) = url_file(&url) {
+        return Ok(Conditional::Modified(
+            Validators::default(),
+            read(path).await?,
+        ));
+    }
+
+    let client = client();
+    let response = client
+        .send(|http| {
+            let mut request = http.get(url.clone());
+            if let Some(etag) = &validators.etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+            request
+        })
+        .await?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Conditional::NotModified);
     }
+
+    let value = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let validators = Validators {
+        etag: value(header::ETAG),
+        last_modified: value(header::LAST_MODIFIED),
+    };
+
+    Ok(Conditional::Modified(validators, client.stream(response)))
 }
 
-async fn fetch(url: Url) -> Result<impl Stream<Item = Result<Bytes, Error>>, Error> {
-    let response = CLIENT.get(url).send().await?;
+/// Make `request` against each of `urls` in order, failing over to the next when
+/// a mirror is unreachable, has a server error or is missing the resource
+///
+/// Each failing mirror is passed to `on_failover`. Returns the url which
+/// succeeded along with it's response.
+pub async fn failover<T, F>(
+    urls: &[Url],
+    mut request: impl FnMut(Url) -> F,
+    on_failover: impl Fn(&Url, &Error),
+) -> Result<(Url, T), Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    let mut last_error = None;
+
+    for url in urls {
+        match request(url.clone()).await {
+            Ok(response) => return Ok((url.clone(), response)),
+            Err(error) if error.should_failover() => {
+                on_failover(url, &error);
+                last_error = Some((url.clone(), error));
+            }
+            Err(error) => return Err(error),
+        }
+    }
 
-    response
-        .error_for_status()
-        .map(reqwest::Response::bytes_stream)
-        .map(|stream| stream.map(|result| result.map_err(Error::Fetch)))
-        .map_err(Error::Fetch)
+    match last_error {
+        Some((url, error)) => Err(Error::MirrorsExhausted {
+            url: Box::new(url),
+            source: Box::new(error),
+        }),
+        None => Err(Error::NoMirrors),
+    }
+}
+
+async fn fetch(url: Url) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
+    let client = client();
+    let response = client.send(|http| http.get(url.clone())).await?;
+
+    Ok(client.stream(response))
 }
 
 async fn read(path: PathBuf) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
@@ -77,4 +324,156 @@ pub enum Error {
     Fetch(#[from] reqwest::Error),
     #[error("io")]
     Read(#[from] io::Error),
+    #[error("timed out waiting for a response")]
+    Timeout,
+    #[error("read CA certificate {0:?}")]
+    ReadCertificate(PathBuf, #[source] io::Error),
+    #[error("no mirrors to fetch from")]
+    NoMirrors,
+    #[error("all mirrors failed, last tried {url}")]
+    MirrorsExhausted {
+        url: Box<Url>,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Whether the request may succeed if retried
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Fetch(error) => {
+                error.is_connect()
+                    || error.is_timeout()
+                    || error
+                        .status()
+                        .is_some_and(|status| status.is_server_error())
+            }
+            Error::Timeout => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the resource doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Fetch(error) => error.status() == Some(StatusCode::NOT_FOUND),
+            Error::Read(error) => error.kind() == io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
+
+    /// Whether the resource should be requested from the next mirror
+    fn should_failover(&self) -> bool {
+        self.is_not_found() || self.is_transient()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+    use super::*;
+
+    /// Serve every connection with a canned `status` response on a local port
+    async fn stand_in(status: &'static str) -> Url {
+        stand_in_with(move |_| status).await
+    }
+
+    /// Serve each connection with the status returned for it's connection number
+    async fn stand_in_with(status: impl Fn(usize) -> &'static str + Send + 'static) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/stone.index", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let mut connection = 0;
+
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let status = status(connection);
+                connection += 1;
+
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{status}",
+                    status.len()
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        url.parse().unwrap()
+    }
+
+    /// A url which refuses connections
+    async fn unreachable() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        format!("http://{addr}/stone.index").parse().unwrap()
+    }
+
+    async fn body(stream: BoxStream<'static, Result<Bytes, Error>>) -> String {
+        let chunks = stream.map(|chunk| chunk.unwrap()).collect::<Vec<_>>().await;
+        String::from_utf8(chunks.concat()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn failover_mirrors() {
+        let urls = vec![
+            unreachable().await,
+            stand_in("503 Service Unavailable").await,
+            stand_in("404 Not Found").await,
+            stand_in("200 OK").await,
+        ];
+        let failed = Mutex::new(vec![]);
+
+        let (url, stream) = failover(&urls, get, |url, _| {
+            failed.lock().unwrap().push(url.clone())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(url, urls[3]);
+        assert_eq!(*failed.lock().unwrap(), urls[..3]);
+        assert_eq!(body(stream).await, "200 OK");
+
+        // Other client errors are authoritative
+        let urls = vec![stand_in("403 Forbidden").await, stand_in("200 OK").await];
+        let result = failover(&urls, get, |_, _| panic!("unexpected failover")).await;
+        assert!(matches!(result, Err(Error::Fetch(_))));
+
+        // Everything failed
+        let urls = vec![stand_in("500 Internal Server Error").await];
+        let result = failover(&urls, get, |_, _| {}).await;
+        assert!(matches!(result, Err(Error::MirrorsExhausted { url, .. }) if *url == urls[0]));
+    }
+
+    #[tokio::test]
+    async fn retry_transient() {
+        let url = stand_in_with(|connection| {
+            if connection == 0 {
+                "502 Bad Gateway"
+            } else {
+                "200 OK"
+            }
+        })
+        .await;
+
+        let stream = get(url).await.unwrap();
+        assert_eq!(body(stream).await, "200 OK");
+
+        // Client errors aren't retried
+        let url = stand_in_with(|connection| {
+            if connection == 0 {
+                "401 Unauthorized"
+            } else {
+                "200 OK"
+            }
+        })
+        .await;
+        assert!(get(url).await.is_err());
+    }
 }
@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detached ed25519 signatures used to authenticate repository indices
+//!
+//! Keys & signatures are stored as hex strings. A secret key is the 32 byte
+//! ed25519 seed, so any source of 32 random bytes (i.e. `openssl rand -hex 32`)
+//! can be used to create one.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{fs, io};
+
+/// Extension appended to a signed file for it's detached signature
+pub const EXTENSION: &str = "sig";
+
+/// A trusted key used to verify [`Signature`]s
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PublicKey([u8; 32]);
+
+impl PublicKey {
+    /// Verify `signature` was created for `bytes` by the owner of this key
+    pub fn verify(&self, bytes: &[u8], signature: &Signature) -> Result<(), Error> {
+        let key = VerifyingKey::from_bytes(&self.0)?;
+        Ok(key.verify_strict(bytes, &signature.0)?)
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = decode(s)?;
+        // Ensure it's a valid point
+        VerifyingKey::from_bytes(&bytes)?;
+        Ok(Self(bytes))
+    }
+}
+
+impl TryFrom<String> for PublicKey {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PublicKey> for String {
+    fn from(key: PublicKey) -> Self {
+        key.to_string()
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        hex::encode(self.0).fmt(f)
+    }
+}
+
+/// A private key used to create [`Signature`]s
+pub struct SecretKey(SigningKey);
+
+impl SecretKey {
+    /// Read a hex encoded secret key from the file at `path`
+    pub async fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        fs::read_to_string(path).await?.parse()
+    }
+
+    /// The [`PublicKey`] which verifies signatures of this key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(self.0.verifying_key().to_bytes())
+    }
+
+    /// Create a detached [`Signature`] of `bytes`
+    pub fn sign(&self, bytes: &[u8]) -> Signature {
+        Signature(self.0.sign(bytes))
+    }
+}
+
+impl FromStr for SecretKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(SigningKey::from_bytes(&decode(s)?)))
+    }
+}
+
+/// A detached signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature(ed25519_dalek::Signature);
+
+impl FromStr for Signature {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(ed25519_dalek::Signature::from_bytes(&decode(s)?)))
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        hex::encode(self.0.to_bytes()).fmt(f)
+    }
+}
+
+/// Decode a hex string into a fixed size array
+fn decode<const N: usize>(s: &str) -> Result<[u8; N], Error> {
+    let bytes = hex::decode(s.trim())?;
+    let len = bytes.len();
+
+    bytes
+        .try_into()
+        .map_err(|_| Error::InvalidLength { expected: N, len })
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid hex")]
+    Hex(#[from] hex::FromHexError),
+    #[error("expected {expected} bytes, got {len}")]
+    InvalidLength { expected: usize, len: usize },
+    #[error("signature")]
+    Signature(#[from] ed25519_dalek::SignatureError),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sign_verify() {
+        let secret = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60"
+            .parse::<SecretKey>()
+            .unwrap();
+        let public = secret
+            .public_key()
+            .to_string()
+            .parse::<PublicKey>()
+            .unwrap();
+
+        let index = b"stone index contents";
+        let signature = secret.sign(index).to_string().parse::<Signature>().unwrap();
+
+        assert!(public.verify(index, &signature).is_ok());
+        assert!(public.verify(b"tampered contents", &signature).is_err());
+        assert!("abcd".parse::<PublicKey>().is_err());
+    }
+}
@@ -2,12 +2,14 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{fmt, io::Write};
+use std::{fmt, io::Write, time::Duration};
 
 use chrono::{DateTime, Utc};
+use itertools::Itertools;
+use nix::unistd::{Uid, User};
 use tui::{pretty, Stylize};
 
-use crate::package;
+use crate::{environment, package, repository};
 
 /// Unique identifier for [`State`]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -59,6 +61,69 @@ pub struct State {
     pub created: DateTime<Utc>,
     /// Relevant type for this State
     pub kind: Kind,
+    /// Where this state came from, unknown for states
+    /// recorded by older versions of moss
+    pub provenance: Option<Provenance>,
+}
+
+/// Audit information on how a [`State`] was created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// Command line of the process which created the state
+    pub command_line: String,
+    /// Name of the real uid of the process
+    pub user: Option<String>,
+    /// Real uid of the process
+    pub uid: u32,
+    /// User who ran `sudo`, as reported by `SUDO_USER`. Unverified, as
+    /// any process can set it
+    pub sudo_user: Option<String>,
+    /// Version of moss which created the state
+    pub version: String,
+    /// Enabled repositories & the hash of their index at the time
+    pub repositories: Vec<RepositoryIndex>,
+    /// Wall-clock time taken to create the state
+    pub duration: Duration,
+}
+
+impl Provenance {
+    /// Provenance of a state created by the current process
+    pub fn current(repositories: Vec<RepositoryIndex>, duration: Duration) -> Self {
+        let uid = Uid::current();
+
+        Self {
+            command_line: std::env::args().map(quote).join(" "),
+            user: User::from_uid(uid).ok().flatten().map(|user| user.name),
+            uid: uid.as_raw(),
+            sudo_user: std::env::var("SUDO_USER")
+                .ok()
+                .filter(|user| !user.is_empty()),
+            version: environment::VERSION.to_string(),
+            repositories,
+            duration,
+        }
+    }
+}
+
+/// Quote a command line argument for display, if needed
+fn quote(arg: String) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./=:@#+,%".contains(c))
+    {
+        arg
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// The index a repository had when a [`State`] was created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepositoryIndex {
+    pub repository: repository::Id,
+    /// Hash of the index, unless it was never fetched
+    pub hash: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
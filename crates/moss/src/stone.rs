@@ -7,6 +7,8 @@ pub use stone::payload;
 
 pub use self::read::stream_payloads;
 
+pub mod delta;
+
 pub mod read {
     use std::{fs::File, path::PathBuf};
 
@@ -0,0 +1,323 @@
+// SPDX-FileCopyrightText: Copyright © 2020-2023 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Delta stones transform a cached base package into a target package
+//!
+//! A delta holds the full meta & layout payloads of it's target, plus a
+//! [`Tag::DeltaBase`] meta record with the hash of the base package. It's index
+//! & content payloads only hold files whose digest isn't in the base package
+//! index, so it can only be applied when the base package assets are cached.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use stone::{
+    header::v1::FileType,
+    payload::{
+        self,
+        meta::{Kind, Tag},
+    },
+    read::PayloadKind,
+};
+use thiserror::Error;
+
+/// File extension of delta stones
+pub const EXTENSION: &str = "delta.stone";
+
+/// Create a delta stone at `out_path` which produces the `target` stone
+/// from the `base` stone, identified by it's `base_hash`
+///
+/// Returns the number of files held by the delta
+pub fn create(
+    base: &Path,
+    base_hash: &str,
+    target: &Path,
+    out_path: &Path,
+) -> Result<usize, Error> {
+    let base_digests = stone::read(File::open(base)?)?
+        .payloads()?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .filter_map(PayloadKind::index)
+        .flat_map(|payload| &payload.body)
+        .map(|index| index.digest)
+        .collect::<HashSet<_>>();
+
+    let mut target = stone::read(File::open(target)?)?;
+    let payloads = target.payloads()?.collect::<Result<Vec<_>, _>>()?;
+
+    let meta = payloads
+        .iter()
+        .find_map(PayloadKind::meta)
+        .ok_or(Error::MissingPayload(payload::Kind::Meta))?;
+    let layout = payloads
+        .iter()
+        .find_map(PayloadKind::layout)
+        .ok_or(Error::MissingPayload(payload::Kind::Layout))?;
+    let attributes = payloads.iter().find_map(PayloadKind::attributes);
+
+    // Files which aren't already provided by the base, once per digest
+    let mut seen = HashSet::new();
+    let changed = payloads
+        .iter()
+        .filter_map(PayloadKind::index)
+        .flat_map(|payload| &payload.body)
+        .filter(|index| !base_digests.contains(&index.digest) && seen.insert(index.digest))
+        .collect::<Vec<_>>();
+
+    let meta = meta
+        .body
+        .iter()
+        .cloned()
+        .chain(Some(payload::Meta {
+            tag: Tag::DeltaBase,
+            kind: Kind::String(base_hash.to_string()),
+        }))
+        .collect::<Vec<_>>();
+
+    let mut out = File::create(out_path)?;
+    let mut writer = stone::Writer::new(&mut out, FileType::Delta)?;
+
+    writer.add_payload(meta.as_slice())?;
+
+    if changed.is_empty() {
+        writer.add_payload(layout.body.as_slice())?;
+        if let Some(attributes) = attributes {
+            writer.add_payload(attributes.body.as_slice())?;
+        }
+        writer.finalize()?;
+
+        return Ok(0);
+    }
+
+    let content = payloads
+        .iter()
+        .find_map(PayloadKind::content)
+        .ok_or(Error::MissingPayload(payload::Kind::Content))?;
+
+    // Unpack the target content so changed files can be read by range
+    let content_path = out_path.with_extension("content");
+    let buffer_path = out_path.with_extension("buffer");
+
+    let result = (|| {
+        let mut content_file = scratch_file(&content_path)?;
+        target.unpack_content(content, &mut content_file)?;
+
+        let pledged_size = changed.iter().map(|index| index.end - index.start).sum();
+        let mut writer = writer.with_content(scratch_file(&buffer_path)?, Some(pledged_size))?;
+
+        for index in &changed {
+            content_file.seek(SeekFrom::Start(index.start))?;
+            writer.add_content(&mut (&content_file).take(index.end - index.start))?;
+        }
+
+        writer.add_payload(layout.body.as_slice())?;
+        if let Some(attributes) = attributes {
+            writer.add_payload(attributes.body.as_slice())?;
+        }
+        writer.finalize()?;
+
+        Ok(changed.len())
+    })();
+
+    let _ = fs::remove_file(&content_path);
+    let _ = fs::remove_file(&buffer_path);
+
+    result
+}
+
+/// Returns the hash of the base package a delta applies to, from it's meta payload
+pub fn base(payloads: &[PayloadKind]) -> Option<&str> {
+    payloads
+        .iter()
+        .find_map(PayloadKind::meta)?
+        .body
+        .iter()
+        .find_map(|meta| match (&meta.tag, &meta.kind) {
+            (Tag::DeltaBase, Kind::String(hash)) => Some(hash.as_str()),
+            _ => None,
+        })
+}
+
+fn scratch_file(path: &Path) -> Result<File, io::Error> {
+    File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("missing {0:?} payload")]
+    MissingPayload(payload::Kind),
+    #[error("stone read")]
+    Read(#[from] stone::read::Error),
+    #[error("stone write")]
+    Write(#[from] stone::write::Error),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use stone::{header::v1::FileType, payload::layout};
+    use xxhash_rust::xxh3::xxh3_128;
+
+    use super::*;
+
+    /// Write a binary stone at `path` holding `files` as regular files
+    fn write_stone(path: &Path, files: &[(&str, &[u8])]) {
+        let meta = [payload::Meta {
+            tag: Tag::Name,
+            kind: Kind::String("test".to_string()),
+        }];
+        let layout = files
+            .iter()
+            .map(|(name, bytes)| payload::Layout {
+                uid: 0,
+                gid: 0,
+                mode: 0o100644,
+                tag: 0,
+                entry: layout::Entry::Regular(xxh3_128(bytes), format!("usr/share/{name}")),
+            })
+            .collect::<Vec<_>>();
+
+        let mut out = File::create(path).unwrap();
+        let mut writer = stone::Writer::new(&mut out, FileType::Binary)
+            .unwrap()
+            .with_content(scratch_file(&path.with_extension("buffer")).unwrap(), None)
+            .unwrap();
+
+        writer.add_payload(meta.as_slice()).unwrap();
+        for (_, mut bytes) in files {
+            writer.add_content(&mut bytes).unwrap();
+        }
+        writer.add_payload(layout.as_slice()).unwrap();
+        writer.finalize().unwrap();
+
+        fs::remove_file(path.with_extension("buffer")).unwrap();
+    }
+
+    /// Unpack the assets of the stone at `path` by digest, along with it's layout
+    fn read_stone(path: &Path) -> (HashMap<u128, Vec<u8>>, Vec<payload::Layout>) {
+        let mut reader = stone::read(File::open(path).unwrap()).unwrap();
+        let payloads = reader
+            .payloads()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut assets = HashMap::new();
+
+        if let Some(content) = payloads.iter().find_map(PayloadKind::content) {
+            let mut bytes = vec![];
+            reader.unpack_content(content, &mut bytes).unwrap();
+
+            for index in payloads
+                .iter()
+                .filter_map(PayloadKind::index)
+                .flat_map(|p| &p.body)
+            {
+                assets.insert(
+                    index.digest,
+                    bytes[index.start as usize..index.end as usize].to_vec(),
+                );
+            }
+        }
+
+        let layout = payloads
+            .iter()
+            .find_map(PayloadKind::layout)
+            .unwrap()
+            .body
+            .clone();
+
+        (assets, layout)
+    }
+
+    #[test]
+    fn round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+
+        let base_path = dir.join("base.stone");
+        let target_path = dir.join("target.stone");
+        let out_path = dir.join(format!("delta.{EXTENSION}"));
+
+        write_stone(
+            &base_path,
+            &[
+                ("unchanged", b"unchanged"),
+                ("changed", b"before"),
+                ("removed", b"removed"),
+            ],
+        );
+        write_stone(
+            &target_path,
+            &[
+                ("unchanged", b"unchanged"),
+                ("changed", b"after"),
+                ("added", b"added"),
+            ],
+        );
+
+        // Only the changed & added files are held by the delta
+        let files = create(&base_path, "base", &target_path, &out_path).unwrap();
+        assert_eq!(files, 2);
+
+        let (base_assets, _) = read_stone(&base_path);
+        let (target_assets, target_layout) = read_stone(&target_path);
+        let (delta_assets, delta_layout) = read_stone(&out_path);
+
+        assert!(!delta_assets.contains_key(&xxh3_128(b"unchanged")));
+        assert!(!delta_assets.contains_key(&xxh3_128(b"removed")));
+        assert_eq!(delta_layout, target_layout);
+
+        // Applying the delta over the cached base assets produces every target file
+        let applied = base_assets
+            .into_iter()
+            .chain(delta_assets)
+            .collect::<HashMap<_, _>>();
+
+        for layout in &delta_layout {
+            let layout::Entry::Regular(digest, _) = &layout.entry else {
+                continue;
+            };
+            assert_eq!(applied.get(digest), target_assets.get(digest));
+        }
+    }
+
+    #[test]
+    fn create_unchanged() {
+        let stone = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../test/bash-completion-2.11-1-1-x86_64.stone");
+        let temp = tempfile::tempdir().unwrap();
+        let out_path = temp.path().join(format!("delta.{EXTENSION}"));
+
+        // Nothing changes between a package & itself
+        let files = create(&stone, "base", &stone, &out_path).unwrap();
+        assert_eq!(files, 0);
+
+        let mut delta = stone::read(File::open(&out_path).unwrap()).unwrap();
+        let payloads = delta
+            .payloads()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let stone::Header::V1(header) = delta.header;
+        assert_eq!(header.file_type, FileType::Delta);
+        assert_eq!(base(&payloads), Some("base"));
+        assert!(payloads.iter().find_map(PayloadKind::layout).is_some());
+        assert!(payloads.iter().find_map(PayloadKind::content).is_none());
+    }
+}
@@ -41,9 +41,9 @@ pub struct AgnosticHeader {
 
 impl AgnosticHeader {
     fn decode<R: Read>(mut reader: R) -> Result<Self, io::Error> {
-        let magic = reader.read_array()?;
-        let data = reader.read_array()?;
-        let version = reader.read_array()?;
+        let magic = ReadExt::read_array(&mut reader)?;
+        let data = ReadExt::read_array(&mut reader)?;
+        let version = ReadExt::read_array(&mut reader)?;
 
         Ok(Self {
             magic,
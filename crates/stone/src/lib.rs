@@ -16,30 +16,31 @@ pub use self::write::Writer;
 
 pub trait ReadExt: Read {
     fn read_u8(&mut self) -> Result<u8> {
-        let bytes = self.read_array::<1>()?;
+        let bytes = ReadExt::read_array::<1>(self)?;
         Ok(bytes[0])
     }
 
     fn read_u16(&mut self) -> Result<u16> {
-        let bytes = self.read_array()?;
+        let bytes = ReadExt::read_array(self)?;
         Ok(u16::from_be_bytes(bytes))
     }
 
     fn read_u32(&mut self) -> Result<u32> {
-        let bytes = self.read_array()?;
+        let bytes = ReadExt::read_array(self)?;
         Ok(u32::from_be_bytes(bytes))
     }
 
     fn read_u64(&mut self) -> Result<u64> {
-        let bytes = self.read_array()?;
+        let bytes = ReadExt::read_array(self)?;
         Ok(u64::from_be_bytes(bytes))
     }
 
     fn read_u128(&mut self) -> Result<u128> {
-        let bytes = self.read_array()?;
+        let bytes = ReadExt::read_array(self)?;
         Ok(u128::from_be_bytes(bytes))
     }
 
+    /// Called as `ReadExt::read_array` as it collides with the unstable `Read::read_array`
     fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
         let mut bytes = [0u8; N];
         self.read_exact(&mut bytes)?;
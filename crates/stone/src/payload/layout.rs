@@ -117,7 +117,7 @@ impl Record for Layout {
             t => return Err(DecodeError::UnknownFileType(t)),
         };
 
-        let _padding = reader.read_array::<11>()?;
+        let _padding = ReadExt::read_array::<11>(&mut reader)?;
 
         // Make the layout entry *usable*
         let entry = match file_type {
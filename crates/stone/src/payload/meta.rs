@@ -147,6 +147,10 @@ pub enum Tag {
     SourcePath = 19,
     // Ref/commit of the upstream source
     SourceRef = 20,
+    // Delta specific (hash of the package the delta applies to)
+    DeltaBase = 21,
+    // Delta index specific (hash of the package the delta produces)
+    DeltaTarget = 22,
 }
 
 /// Helper to decode a dependency's encoded kind
@@ -191,11 +195,13 @@ impl Record for Meta {
             18 => Tag::SourceURI,
             19 => Tag::SourcePath,
             20 => Tag::SourceRef,
+            21 => Tag::DeltaBase,
+            22 => Tag::DeltaTarget,
             t => return Err(DecodeError::UnknownMetaTag(t)),
         };
 
         let kind = reader.read_u8()?;
-        let _padding = reader.read_array::<1>()?;
+        let _padding = ReadExt::read_array::<1>(&mut reader)?;
 
         // Remove null terminated byte from string
         let sanitize = |s: String| s.trim_end_matches('\0').to_string();
@@ -61,7 +61,7 @@ impl Header {
     pub fn decode<R: Read>(mut reader: R) -> Result<Self, DecodeError> {
         let stored_size = reader.read_u64()?;
         let plain_size = reader.read_u64()?;
-        let checksum = reader.read_array()?;
+        let checksum = ReadExt::read_array(&mut reader)?;
         let num_records = reader.read_u32()? as usize;
         let version = reader.read_u16()?;
 
@@ -47,7 +47,42 @@ impl<R: Read + Seek> Reader<R> {
             .flat_map(|_| PayloadKind::decode(&mut self.reader, &mut self.hasher).transpose()))
     }
 
-    pub fn unpack_content<W: Write>(
+    /// Compute the checksum of every payload's stored bytes without decoding them,
+    /// so all payloads can be checked even when some are corrupt
+    pub fn checksums(&mut self) -> Result<Vec<Checksum>, Error> {
+        self.reader.seek(SeekFrom::Start(Header::SIZE as u64))?;
+
+        let mut checksums = vec![];
+
+        for _ in 0..self.header.num_payloads() {
+            let header = match payload::Header::decode(&mut self.reader) {
+                Ok(header) => header,
+                Err(payload::DecodeError::Io(error))
+                    if error.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    break;
+                }
+                Err(error) => return Err(Error::PayloadDecode(error)),
+            };
+
+            self.hasher.reset();
+            let framed = (&mut self.reader).take(header.stored_size);
+            let mut hashed = digest::Reader::new(framed, &mut self.hasher);
+
+            if io::copy(&mut hashed, &mut io::sink())? != header.stored_size {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            checksums.push(Checksum {
+                header,
+                got: self.hasher.digest(),
+            });
+        }
+
+        Ok(checksums)
+    }
+
+    pub fn unpack_content<W>(
         &mut self,
         content: &Payload<Content>,
         writer: &mut W,
@@ -71,6 +106,23 @@ impl<R: Read + Seek> Reader<R> {
     }
 }
 
+/// The checksum computed over the stored bytes of a payload
+#[derive(Debug, Clone, Copy)]
+pub struct Checksum {
+    pub header: payload::Header,
+    pub got: u64,
+}
+
+impl Checksum {
+    pub fn expected(&self) -> u64 {
+        u64::from_be_bytes(self.header.checksum)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.got == self.expected()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Content {
     offset: u64,
@@ -264,6 +316,21 @@ mod test {
         assert_eq!(stone.header.version(), header::Version::V1);
     }
 
+    #[test]
+    fn checksums() {
+        let mut stone = read_bytes(include_bytes!(
+            "../../../../test/bash-completion-2.11-1-1-x86_64.stone"
+        ))
+        .expect("valid stone");
+
+        let checksums = stone.checksums().expect("read checksums");
+        assert_eq!(checksums.len(), stone.header.num_payloads() as usize);
+        assert!(checksums.iter().all(Checksum::is_valid));
+
+        // Payloads can still be decoded afterwards
+        assert_eq!(stone.payloads().unwrap().count(), checksums.len());
+    }
+
     #[test]
     fn read_bash_completion() {
         let mut stone = read_bytes(include_bytes!(
@@ -8,17 +8,22 @@ use std::{
     path::PathBuf,
 };
 
-use crate::tree::{Kind, Tree};
+use crate::tree::{Kind, Policy, Tree};
 
 use super::{BlitFile, Error};
 
 /// Builder used to generate a full tree, free of conflicts
+///
+/// Conflicting paths are resolved by the [`Policy`], which errors by default
 pub struct TreeBuilder<T: BlitFile> {
     // Explicitly requested incoming paths
     explicit: Vec<T>,
 
     // Implicitly created paths
     implicit_dirs: BTreeMap<PathBuf, T>,
+
+    // How conflicting paths are resolved
+    policy: Policy,
 }
 
 /// Special sort algorithm for files by directory
@@ -43,9 +48,15 @@ impl<T: BlitFile> TreeBuilder<T> {
         TreeBuilder {
             explicit: vec![],
             implicit_dirs: BTreeMap::new(),
+            policy: Policy::default(),
         }
     }
 
+    /// Resolve conflicting paths with `policy` instead of erroring
+    pub fn with_policy(self, policy: Policy) -> Self {
+        Self { policy, ..self }
+    }
+
     /// Push an item to the builder - we don't care if we have duplicates yet
     pub fn push(&mut self, item: T) {
         let path = item.path();
@@ -114,7 +125,7 @@ impl<T: BlitFile> TreeBuilder<T> {
                 };
                 let string_path = path.to_string_lossy().to_string();
                 let string_target = target.to_string_lossy().to_string();
-                if all_dirs.get(&string_target).is_some() {
+                if all_dirs.contains_key(&string_target) {
                     redirects.insert(string_path, string_target);
                 }
             }
@@ -131,7 +142,7 @@ impl<T: BlitFile> TreeBuilder<T> {
             .collect::<Vec<_>>();
         full_set.sort_by(|a, b| sorted_paths(*a, *b));
 
-        let mut tree: Tree<T> = Tree::new();
+        let mut tree: Tree<T> = Tree::new(self.policy.clone());
 
         // Build the initial full tree now.
         for entry in full_set {
@@ -155,8 +166,11 @@ impl<T: BlitFile> TreeBuilder<T> {
 #[cfg(test)]
 mod tests {
     use super::{BlitFile, TreeBuilder};
-    use crate::tree::Kind;
-    use std::path::PathBuf;
+    use crate::tree::{Error, Kind, Policy};
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    };
 
     #[derive(Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
     struct CustomFile {
@@ -234,4 +248,79 @@ mod tests {
         b.bake();
         b.tree().unwrap();
     }
+
+    #[test]
+    fn test_conflicts() {
+        let builder = |policy| {
+            let mut b: TreeBuilder<CustomFile> = TreeBuilder::new().with_policy(policy);
+            for id in ["vim", "neovim"] {
+                b.push(CustomFile {
+                    path: "/usr/bin/vi".into(),
+                    kind: Kind::Regular,
+                    id: id.into(),
+                });
+                // Identical symlinks don't conflict
+                b.push(CustomFile {
+                    path: "/usr/bin/ex".into(),
+                    kind: Kind::Symlink("vi".into()),
+                    id: id.into(),
+                });
+            }
+            b.bake();
+            b
+        };
+
+        assert!(matches!(
+            builder(Policy::Error).tree(),
+            Err(Error::Duplicate(..))
+        ));
+
+        let tree = builder(Policy::Warn).tree().unwrap();
+        assert_eq!(tree.conflicts().len(), 1);
+        assert_eq!(tree.conflicts()[0].kept, "vim");
+        assert_eq!(tree.iter().filter(|f| f.path.ends_with("vi")).count(), 1);
+
+        let priorities = HashMap::from([("neovim".to_string(), 1)]);
+        let tree = builder(Policy::Prefer(priorities)).tree().unwrap();
+        assert_eq!(tree.conflicts().len(), 1);
+        assert_eq!(tree.conflicts()[0].kept, "neovim");
+        let vi = tree.iter().find(|f| f.path.ends_with("vi")).unwrap();
+        assert_eq!(vi.id, "neovim");
+    }
+
+    #[test]
+    fn test_replace_directory() {
+        let mut b: TreeBuilder<CustomFile> = TreeBuilder::new()
+            .with_policy(Policy::Prefer(HashMap::from([("compat".to_string(), 1)])));
+        for (path, kind, id) in [
+            ("/usr/lib/x", Kind::Directory, "base"),
+            ("/usr/lib/x/y", Kind::Regular, "base"),
+            ("/lib", Kind::Symlink("usr/lib".into()), "compat"),
+            ("/lib/x", Kind::Regular, "compat"),
+        ] {
+            b.push(CustomFile {
+                path: path.into(),
+                kind,
+                id: id.into(),
+            });
+        }
+        b.bake();
+
+        // `/lib/x` is reparented onto the `/usr/lib/x` directory & replaces it
+        let tree = b.tree().unwrap();
+        assert_eq!(tree.conflicts().len(), 1);
+        assert_eq!(tree.conflicts()[0].kept, "compat");
+
+        let files = tree.iter().collect::<Vec<_>>();
+        assert_eq!(tree.len(), files.len() as u64);
+        assert!(files.iter().all(|f| f.path != Path::new("/usr/lib/x/y")));
+        assert!(tree.resolve_node("/usr/lib/x/y").is_none());
+        assert_eq!(
+            tree.iter()
+                .find(|f| f.path == Path::new("/usr/lib/x"))
+                .unwrap()
+                .id,
+            "compat"
+        );
+    }
 }
@@ -5,7 +5,7 @@
 //! Virtual filesystem tree (optimise layout inserts)
 
 use core::fmt::Debug;
-use std::{collections::HashMap, ffi::OsStr, path::PathBuf, vec};
+use std::{collections::HashMap, ffi::OsStr, fmt, path::PathBuf, vec};
 
 use indextree::{Arena, Descendants, NodeId};
 use thiserror::Error;
@@ -35,24 +35,69 @@ pub trait BlitFile: Clone + Sized + Debug + From<PathBuf> {
     fn cloned_to(&self, path: PathBuf) -> Self;
 }
 
+/// How entries of different [`BlitFile::id`]s at the same path are handled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Fail with [`Error::Duplicate`]
+    #[default]
+    Error,
+    /// Keep the first entry & record a [`Conflict`]
+    Warn,
+    /// Keep the entry whose id has the highest priority & record a [`Conflict`]
+    ///
+    /// Ids without a priority have a priority of 0, the first entry is kept on ties
+    Prefer(HashMap<String, u64>),
+}
+
+/// Two entries at the same path, only one of which is kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub path: PathBuf,
+    /// Id of the entry in the tree
+    pub kept: String,
+    /// Id of the entry which was dropped
+    pub discarded: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} from {} replaced by {}",
+            self.path.display(),
+            self.discarded,
+            self.kept
+        )
+    }
+}
+
 /// Actual tree implementation, encapsulating indextree
 #[derive(Debug)]
 pub struct Tree<T: BlitFile> {
     arena: Arena<T>,
     map: HashMap<PathBuf, NodeId>,
     length: u64,
+    policy: Policy,
+    conflicts: Vec<Conflict>,
 }
 
 impl<T: BlitFile> Tree<T> {
     /// Construct a new Tree
-    fn new() -> Self {
+    fn new(policy: Policy) -> Self {
         Tree {
             arena: Arena::new(),
             map: HashMap::new(),
             length: 0_u64,
+            policy,
+            conflicts: vec![],
         }
     }
 
+    /// Conflicts resolved by the [`Policy`] while building the tree
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
     /// Return the number of items in the tree
     pub fn len(&self) -> u64 {
         self.length
@@ -77,52 +122,89 @@ impl<T: BlitFile> Tree<T> {
         self.map.get(&data.into())
     }
 
-    /// Add a child to the given parent node
+    /// Add a child to the given parent node, resolving any
+    /// existing child of the same name with the [`Policy`]
     fn add_child_to_node(
         &mut self,
         node_id: NodeId,
         parent: impl Into<PathBuf>,
     ) -> Result<(), Error> {
         let parent = parent.into();
-        let node = self.arena.get(node_id).unwrap();
-        if let Some(parent_node) = self.map.get(&parent) {
-            let others = parent_node
-                .children(&self.arena)
-                .filter_map(|n| self.arena.get(n))
-                .filter_map(|n| {
-                    if n.get().path().file_name() == node.get().path().file_name() {
-                        Some(n.get())
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
-            if !others.is_empty() {
-                // TODO: Reenable
-                // Err(Error::Duplicate(
-                //     node.get().path(),
-                //     node.get().id(),
-                //     others.first().unwrap().id(),
-                // ))
-
-                // Report duplicate and skip for now
-                eprintln!(
-                    "error: {}",
-                    Error::Duplicate(
-                        node.get().path(),
-                        node.get().id(),
-                        others.first().unwrap().id(),
-                    )
-                );
-
-                Ok(())
-            } else {
-                parent_node.append(node_id, &mut self.arena);
-                Ok(())
+        let parent_node = *self
+            .map
+            .get(&parent)
+            .ok_or_else(|| Error::MissingParent(parent.clone()))?;
+        let node = self.arena[node_id].get();
+        let existing = parent_node.children(&self.arena).find(|n| {
+            *n != node_id && self.arena[*n].get().path().file_name() == node.path().file_name()
+        });
+
+        let Some(existing_id) = existing else {
+            parent_node.append(node_id, &mut self.arena);
+            return Ok(());
+        };
+
+        let existing = self.arena[existing_id].get();
+
+        // Identical symlinks are harmless
+        if matches!(node.kind(), Kind::Symlink(_)) && node.kind() == existing.kind() {
+            self.discard(node_id, existing_id);
+            return Ok(());
+        }
+
+        let (path, id, existing_id_string) = (node.path(), node.id(), existing.id());
+
+        let replace = match &self.policy {
+            Policy::Error => return Err(Error::Duplicate(path, id, existing_id_string)),
+            Policy::Warn => false,
+            Policy::Prefer(priorities) => {
+                let priority = |id: &str| priorities.get(id).copied().unwrap_or_default();
+                priority(&id) > priority(&existing_id_string)
             }
+        };
+
+        if replace {
+            self.remove_subtree(existing_id);
+            parent_node.append(node_id, &mut self.arena);
+            self.conflicts.push(Conflict {
+                path,
+                kept: id,
+                discarded: existing_id_string,
+            });
         } else {
-            Err(Error::MissingParent(parent.clone()))
+            self.discard(node_id, existing_id);
+            self.conflicts.push(Conflict {
+                path,
+                kept: existing_id_string,
+                discarded: id,
+            });
         }
+
+        Ok(())
+    }
+
+    /// Drop the unparented `node_id` in favour of `kept` at the same path
+    fn discard(&mut self, node_id: NodeId, kept: NodeId) {
+        let path = self.arena[kept].get().path();
+        node_id.remove(&mut self.arena);
+        self.map.insert(path, kept);
+        self.length -= 1;
+    }
+
+    /// Remove `node_id` and all of it's descendants, along with their path mappings
+    fn remove_subtree(&mut self, node_id: NodeId) {
+        let removed = node_id.descendants(&self.arena).collect::<Vec<_>>();
+
+        for id in &removed {
+            let path = self.arena[*id].get().path();
+            // The path may already map to the node replacing it
+            if self.map.get(&path) == Some(id) {
+                self.map.remove(&path);
+            }
+        }
+
+        self.length -= removed.len() as u64;
+        node_id.remove_subtree(&mut self.arena);
     }
 
     pub fn print(&self) {
@@ -157,18 +239,22 @@ impl<T: BlitFile> Tree<T> {
 
             // Remove descendents
             let children = source.children(&self.arena).collect::<Vec<_>>();
-            for child in children.iter() {
-                child.remove_subtree(&mut self.arena)
+            for child in children {
+                self.remove_subtree(child);
             }
         }
 
         for orphan in orphans {
             let path = orphan.path().clone();
-            // Do we have this node already?
-            let node = match self.resolve_node(&path) {
-                Some(n) => *n,
-                None => self.new_node(orphan),
-            };
+            // Directories merge into an existing one
+            if let Some(existing) = self.resolve_node(&path) {
+                if orphan.kind() == Kind::Directory
+                    && self.arena[*existing].get().kind() == Kind::Directory
+                {
+                    continue;
+                }
+            }
+            let node = self.new_node(orphan);
             if let Some(parent) = path.parent() {
                 self.add_child_to_node(node, parent)?;
             }
@@ -245,6 +331,6 @@ pub enum Error {
     #[error("missing parent: {0}")]
     MissingParent(PathBuf),
 
-    #[error("duplicate entry: {0} {1} attempts to overwrite {2}")]
+    #[error("file conflict: {0} from {1} attempts to overwrite {2}")]
     Duplicate(PathBuf, String, String),
 }